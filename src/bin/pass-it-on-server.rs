@@ -1,14 +1,19 @@
-use clap::Parser;
-use pass_it_on::{Error, ServerConfiguration, start_server, verify_matrix_devices};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use pass_it_on::{
+    ConfigFormat, Error, ServerConfigFile, ServerConfiguration, healthcheck, start_server_with_reload,
+    verify_matrix_devices,
+};
+use serde_json::json;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use tracing::level_filters::LevelFilter;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Parser, Debug)]
 #[clap(name = "pass-it-on-server", author, version, about = "Pass-it-on server binary", long_about = None)]
 struct CliArgs {
-    /// Path to pass-it-on server configuration file
+    /// Path to pass-it-on server configuration file, read as YAML or JSON when it has that extension
     #[clap(short, long, value_parser)]
     configuration: Option<PathBuf>,
     /// Set the logging level [default: Info]
@@ -17,8 +22,47 @@ struct CliArgs {
     /// Interactively verify Matrix endpoint devices when set
     #[clap(short, long, value_parser, default_value_t = false)]
     matrix_verify_devices: bool,
+    /// Send the notifications in the dead-letter file to their endpoints again when set
+    #[clap(short, long, value_parser, default_value_t = false)]
+    reinject_dead_letters: bool,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check that a running server is ready and exit with a failure when it is not
+    Healthcheck {
+        /// URL of the server ready route
+        #[clap(short, long, value_parser, default_value = DEFAULT_READY_URL)]
+        url: String,
+    },
+    /// Check the configuration file and report every problem found without starting the server
+    Check,
+    /// Print which notification names reach each endpoint and target
+    Routes,
+    /// Print the configuration with the files it includes and its secrets resolved, with the secrets redacted
+    Dump,
+    /// Interactively write a new configuration file and a matching client configuration file with a generated key
+    Init {
+        /// Path of the client configuration file to write [default: client.toml next to the configuration file]
+        #[cfg(feature = "client")]
+        #[clap(long, value_parser)]
+        client: Option<PathBuf>,
+    },
+    /// Print the JSON Schema of the server configuration file for editors to validate and complete it
+    Schema {
+        /// Print the JSON Schema of the client configuration file instead
+        #[cfg(feature = "client")]
+        #[clap(long, value_parser, default_value_t = false)]
+        client: bool,
+    },
 }
 
+const DEFAULT_READY_URL: &str = "http://127.0.0.1:8080/pass-it-on/ready";
+const KEY_LENGTH: usize = 32;
+const KEY_CHARACTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
 #[tokio::main]
 async fn main() -> ExitCode {
     let cli = CliArgs::parse();
@@ -34,24 +78,224 @@ async fn main() -> ExitCode {
 }
 
 async fn run(cliargs: CliArgs) -> Result<(), Error> {
+    // The schema is the only output so it can be redirected to a file
+    if let Some(Command::Schema { .. }) = cliargs.command {
+        return print_schema(&cliargs.command);
+    }
     info!("Log level is set to {}", cliargs.log_level.unwrap_or(LevelFilter::INFO));
+    if let Some(Command::Healthcheck { url }) = cliargs.command {
+        return healthcheck(url).await;
+    }
+
     // Setup default directories
     let default_config_path = directories::ProjectDirs::from("com", "pass-it-on", "pass-it-on-server").unwrap();
 
     // Parse Config file
-    let server_config = {
-        let config_path = match cliargs.configuration {
-            Some(path) => path,
-            None => PathBuf::from(default_config_path.config_dir()).join("server.toml"),
-        };
-
-        info!("Reading configuration from: {}", config_path.to_str().unwrap());
-        ServerConfiguration::try_from(std::fs::read_to_string(config_path)?.as_str())?
+    let config_path = match cliargs.configuration {
+        Some(path) => path,
+        None => PathBuf::from(default_config_path.config_dir()).join("server.toml"),
+    };
+
+    match cliargs.command {
+        Some(Command::Check) => return check_configuration(&config_path),
+        Some(Command::Routes) => return print_routes(&config_path),
+        Some(Command::Dump) => return print_configuration(&config_path),
+        Some(Command::Init { .. }) => return init_configuration(&config_path, &cliargs.command),
+        _ => (),
+    }
+
+    let server_config = read_configuration(&config_path)?;
+
+    let server_config = match (cliargs.reinject_dead_letters, server_config.dead_letter().cloned()) {
+        (true, None) => {
+            warn!("No dead-letter file is configured, there are no dead letters to reinject");
+            server_config
+        }
+        (true, Some(dead_letter)) => server_config.with_dead_letter(Some(dead_letter.with_reinject(true))),
+        (false, _) => server_config,
     };
 
     // Run interactive matrix device verification when flag is passed
     match cliargs.matrix_verify_devices {
         true => verify_matrix_devices(server_config).await,
-        false => start_server(server_config, None, None).await,
+        false => start_server_with_reload(server_config, move || read_configuration(&config_path), None, None).await,
+    }
+}
+
+fn read_configuration(config_path: &Path) -> Result<ServerConfiguration, Error> {
+    info!("Reading configuration from: {}", config_path.to_str().unwrap());
+    ServerConfiguration::try_from(config_path)
+}
+
+fn check_configuration(config_path: &Path) -> Result<(), Error> {
+    info!("Checking configuration in: {}", config_path.to_str().unwrap());
+    let problems = ServerConfigFile::check_file(config_path)?;
+    for problem in problems.iter() {
+        let file = problem.file().unwrap_or(config_path);
+        println!("{}:{}:{}: {}", file.display(), problem.line(), problem.column(), problem.message());
+    }
+    match problems.is_empty() {
+        true => {
+            println!("{}: configuration is valid", config_path.display());
+            Ok(())
+        }
+        false => Err(Error::invalid_server_configuration(format!("{} problem(s) found", problems.len()))),
+    }
+}
+
+fn print_schema(command: &Option<Command>) -> Result<(), Error> {
+    let schema = match command {
+        #[cfg(feature = "client")]
+        Some(Command::Schema { client: true }) => pass_it_on::ClientConfigFile::schema(),
+        _ => ServerConfigFile::schema(),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+fn print_routes(config_path: &Path) -> Result<(), Error> {
+    info!("Reading configuration from: {}", config_path.to_str().unwrap());
+    let routes = ServerConfigFile::routes_file(config_path)?;
+    let rows: Vec<_> = routes
+        .iter()
+        .map(|route| {
+            [route.notification(), route.endpoint(), if route.target().is_empty() { "-" } else { route.target() }]
+        })
+        .collect();
+    let header = ["NOTIFICATION", "ENDPOINT", "TARGET"];
+    let mut widths = header.map(str::len);
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        println!("{:<w0$}  {:<w1$}  {}", row[0], row[1], row[2], w0 = widths[0], w1 = widths[1]);
+    }
+    Ok(())
+}
+
+fn print_configuration(config_path: &Path) -> Result<(), Error> {
+    info!("Reading configuration from: {}", config_path.to_str().unwrap());
+    let configuration = ServerConfigFile::from_path(config_path)?;
+    print!("{}", configuration.to_redacted_format(ConfigFormat::from_path(config_path))?);
+    Ok(())
+}
+
+// The client configuration file is only written with the client feature
+#[cfg_attr(not(feature = "client"), allow(unused_variables))]
+fn init_configuration(config_path: &Path, command: &Option<Command>) -> Result<(), Error> {
+    #[cfg(feature = "client")]
+    let client_path = match command {
+        Some(Command::Init { client: Some(client) }) => client.clone(),
+        _ => config_path.with_file_name("client.toml"),
+    };
+    #[cfg(feature = "client")]
+    let paths = [config_path, client_path.as_path()];
+    #[cfg(not(feature = "client"))]
+    let paths = [config_path];
+    if let Some(path) = paths.iter().find(|path| path.exists()) {
+        return Err(Error::invalid_server_configuration(format!("{} already exists", path.display())));
+    }
+
+    let key = generate_key()?;
+    let (server_interface, client_interface) = match choose("Interface type", &["http", "pipe"])? {
+        "pipe" => {
+            let interface = json!({ "type": "pipe", "path": prompt("Named pipe path", "/tmp/pass-it-on.fifo")? });
+            (interface.clone(), interface)
+        }
+        _ => {
+            let host = prompt("Host the server listens on", "0.0.0.0")?;
+            let port = prompt("Port", "8080")?;
+            let port: u16 = port
+                .parse()
+                .map_err(|_| Error::invalid_interface_configuration(format!("{} is not a valid port", port)))?;
+            let client_host = prompt("Host clients connect to", "localhost")?;
+            let server = json!({ "type": "http", "host": host, "port": port });
+            (server, json!({ "type": "http", "host": client_host, "port": port }))
+        }
+    };
+    let notifications: Vec<_> = prompt("Notification names separated by commas", "notification1")?
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect();
+    let mut endpoint = match choose("Endpoint type", &["file", "discord"])? {
+        "discord" => json!({ "type": "discord", "url": prompt("Discord webhook URL", "")? }),
+        _ => json!({ "type": "file", "path": prompt("File path", "pass-it-on.log")? }),
+    };
+    endpoint["notifications"] = json!(notifications);
+
+    let server = json!({ "key": key, "interface": [server_interface], "endpoint": [endpoint] });
+    let server: ServerConfigFile = serde_json::from_value(server)?;
+    let format = ConfigFormat::from_path(config_path);
+    let server = server.to_format(format)?;
+    ServerConfiguration::from_format(server.as_str(), format)?;
+    write_new_file(config_path, server.as_str())?;
+    println!("Wrote {}", config_path.display());
+
+    #[cfg(feature = "client")]
+    {
+        let client: pass_it_on::ClientConfigFile =
+            serde_json::from_value(json!({ "key": key, "interface": [client_interface] }))?;
+        let client = client.to_format(ConfigFormat::from_path(&client_path))?;
+        write_new_file(&client_path, client.as_str())?;
+        println!("Wrote {}", client_path.display());
+    }
+    Ok(())
+}
+
+/// Ask `question` and return the answer, or `default` when the answer is empty.
+fn prompt(question: &str, default: &str) -> Result<String, Error> {
+    match default.is_empty() {
+        true => print!("{}: ", question),
+        false => print!("{} [{}]: ", question, default),
+    }
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(match answer.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    })
+}
+
+/// Ask `question` until the answer is one of the `choices`, the first choice is the default.
+fn choose<'a>(question: &str, choices: &[&'a str]) -> Result<&'a str, Error> {
+    let question = format!("{} ({})", question, choices.join(", "));
+    loop {
+        let answer = prompt(question.as_str(), choices[0])?;
+        if let Some(choice) = choices.iter().find(|choice| **choice == answer) {
+            return Ok(choice);
+        }
+    }
+}
+
+/// Return a random key of letters and digits.
+fn generate_key() -> Result<String, Error> {
+    // Bytes past the last whole multiple of the characters are skipped so every character is as likely
+    let limit = 256 / KEY_CHARACTERS.len() * KEY_CHARACTERS.len();
+    let mut key = String::with_capacity(KEY_LENGTH);
+    while key.len() < KEY_LENGTH {
+        let mut bytes = [0u8; KEY_LENGTH];
+        getrandom::fill(&mut bytes).map_err(|e| std::io::Error::other(e.to_string()))?;
+        let characters = bytes.iter().map(|byte| *byte as usize).filter(|byte| *byte < limit);
+        key.extend(characters.map(|byte| KEY_CHARACTERS[byte % KEY_CHARACTERS.len()] as char));
+    }
+    key.truncate(KEY_LENGTH);
+    Ok(key)
+}
+
+/// Write `text` to the new file at `path`, which only its owner can read since it holds the key.
+fn write_new_file(path: &Path, text: &str) -> Result<(), Error> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
     }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(text.as_bytes())?;
+    Ok(())
 }
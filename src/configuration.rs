@@ -2,9 +2,29 @@
 pub mod client_configuration_file;
 #[cfg(all(feature = "parse-cfg", feature = "server"))]
 pub mod server_configuration_file;
+#[cfg(all(feature = "parse-cfg", any(feature = "client", feature = "server")))]
+pub mod format;
+#[cfg(all(feature = "parse-cfg", feature = "server"))]
+pub(crate) mod include;
+#[cfg(all(feature = "schema", any(feature = "client", feature = "server")))]
+pub(crate) mod schema;
+#[cfg(all(feature = "parse-cfg", any(feature = "client", feature = "server")))]
+pub(crate) mod secrets;
 
 #[cfg(feature = "server")]
 use crate::endpoints::{Endpoint, EndpointChannel, EndpointConfig};
+#[cfg(feature = "server")]
+use crate::endpoints::dedup::Dedup;
+#[cfg(feature = "server")]
+use crate::server::admin::Admin;
+#[cfg(feature = "server")]
+use crate::server::dead_letter::DeadLetter;
+#[cfg(feature = "server")]
+use crate::server::escalation::{escalation_targets, Escalation};
+#[cfg(feature = "server")]
+use crate::server::replay::ReplayProtection;
+#[cfg(feature = "server")]
+use crate::server::spool::Spool;
 use crate::interfaces::{Interface, InterfaceConfig};
 use crate::notifications::Key;
 use crate::Error;
@@ -16,6 +36,12 @@ pub struct ServerConfiguration {
     key: Key,
     interfaces: Vec<Box<dyn Interface + Send>>,
     endpoints: Vec<Box<dyn Endpoint + Send>>,
+    replay_protection: ReplayProtection,
+    dedup: Option<Dedup>,
+    spool: Option<Spool>,
+    dead_letter: Option<DeadLetter>,
+    escalations: Vec<Escalation>,
+    admin: Option<Admin>,
 }
 
 #[cfg(feature = "server")]
@@ -27,24 +53,39 @@ impl ServerConfiguration {
         endpoints: Vec<Box<dyn Endpoint + Send>>,
     ) -> Result<Self, Error> {
         let key = Key::derive_shared_key(key);
-        let config = Self { key, interfaces, endpoints };
+        let config = Self {
+            key,
+            interfaces,
+            endpoints,
+            replay_protection: ReplayProtection::default(),
+            dedup: None,
+            spool: None,
+            dead_letter: None,
+            escalations: Vec::new(),
+            admin: None,
+        };
         Self::validate(config)
     }
 
-    pub(crate) fn endpoint_channels(&self) -> Vec<EndpointChannel> {
+    /// Replace the default [`ReplayProtection`] settings.
+    pub fn with_replay_protection(mut self, replay_protection: ReplayProtection) -> Self {
+        self.replay_protection = replay_protection;
+        self
+    }
+
+    /// Create the channel for the endpoint at `index`.
+    pub(crate) fn endpoint_channel(&self, index: usize) -> EndpointChannel {
+        use crate::endpoints::queue::Queue;
         use crate::notifications::ValidatedNotification;
-        use crate::CHANNEL_BUFFER;
         use tokio::sync::broadcast;
         use tokio::sync::broadcast::{Receiver, Sender};
 
-        let mut endpoints = Vec::new();
-        for endpoint in &self.endpoints {
-            let (endpoint_tx, _endpoint_rx): (Sender<ValidatedNotification>, Receiver<ValidatedNotification>) =
-                broadcast::channel(CHANNEL_BUFFER);
-            let keys = endpoint.generate_keys(&self.key);
-            endpoints.push(EndpointChannel::from(endpoint.clone(), endpoint_tx, keys));
-        }
-        endpoints
+        let endpoint = &self.endpoints[index];
+        let size = endpoint.options().queue().map(Queue::size).unwrap_or_else(|| Queue::default().size());
+        let (endpoint_tx, _endpoint_rx): (Sender<ValidatedNotification>, Receiver<ValidatedNotification>) =
+            broadcast::channel(size);
+        let keys = endpoint.generate_keys(&self.key);
+        EndpointChannel::from(endpoint.clone(), endpoint_tx, keys)
     }
 
     /// Return server [`Key`] value.
@@ -52,6 +93,40 @@ impl ServerConfiguration {
         &self.key
     }
 
+    /// Set the default [`Dedup`] settings for endpoints that do not set their own.
+    pub fn with_dedup(mut self, dedup: Option<Dedup>) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Set the durable [`Spool`] for notifications that have not been delivered, `None` keeps them in memory.
+    pub fn with_spool(mut self, spool: Option<Spool>) -> Self {
+        self.spool = spool;
+        self
+    }
+
+    /// Set the [`DeadLetter`] file for notifications that could not be delivered, `None` only logs them.
+    pub fn with_dead_letter(mut self, dead_letter: Option<DeadLetter>) -> Self {
+        self.dead_letter = dead_letter;
+        self
+    }
+
+    /// Set the [`Escalation`] chains between endpoints.
+    ///
+    /// Returns an error when a chain names an endpoint that is not defined, uses an endpoint more than once or
+    /// escalates from an endpoint that does not report delivery.
+    pub fn with_escalations(mut self, escalations: Vec<Escalation>) -> Result<Self, Error> {
+        escalation_targets(&escalations, &self.endpoints)?;
+        self.escalations = escalations;
+        Ok(self)
+    }
+
+    /// Set the [`Admin`] API settings, `None` disables the admin API.
+    pub fn with_admin(mut self, admin: Option<Admin>) -> Self {
+        self.admin = admin;
+        self
+    }
+
     /// Return all server interfaces.
     pub fn interfaces(&self) -> Vec<Box<dyn Interface + Send>> {
         self.interfaces.clone()
@@ -62,6 +137,36 @@ impl ServerConfiguration {
         &self.endpoints
     }
 
+    /// Return the [`ReplayProtection`] settings.
+    pub fn replay_protection(&self) -> &ReplayProtection {
+        &self.replay_protection
+    }
+
+    /// Return the default [`Dedup`] settings for endpoints.
+    pub fn dedup(&self) -> Option<Dedup> {
+        self.dedup
+    }
+
+    /// Return the [`Spool`] settings.
+    pub fn spool(&self) -> Option<&Spool> {
+        self.spool.as_ref()
+    }
+
+    /// Return the [`DeadLetter`] settings.
+    pub fn dead_letter(&self) -> Option<&DeadLetter> {
+        self.dead_letter.as_ref()
+    }
+
+    /// Return the [`Escalation`] chains.
+    pub fn escalations(&self) -> &[Escalation] {
+        &self.escalations
+    }
+
+    /// Return the [`Admin`] API settings.
+    pub fn admin(&self) -> Option<&Admin> {
+        self.admin.as_ref()
+    }
+
     fn validate(config: ServerConfiguration) -> Result<ServerConfiguration, Error> {
         if config.interfaces.is_empty() {
             return Err(Error::missing_interface());
@@ -71,8 +176,40 @@ impl ServerConfiguration {
             return Err(Error::missing_endpoint());
         }
 
+        Self::validate_fallbacks(&config.endpoints)?;
         Ok(config)
     }
+
+    /// Check endpoint names are unique and every schedule fallback names another endpoint without forming a loop.
+    fn validate_fallbacks(endpoints: &[Box<dyn Endpoint + Send>]) -> Result<(), Error> {
+        use std::collections::HashMap;
+
+        let options: Vec<_> = endpoints.iter().map(|endpoint| endpoint.options()).collect();
+        let mut names = HashMap::new();
+        for (index, name) in options.iter().enumerate().filter_map(|(index, o)| o.name().map(|name| (index, name))) {
+            if names.insert(name, index).is_some() {
+                return Err(Error::invalid_endpoint_configuration(format!("endpoint name {} is not unique", name)));
+            }
+        }
+
+        for start in 0..options.len() {
+            let mut visited = vec![start];
+            let mut current = start;
+            while let Some(fallback) = options[current].schedule().and_then(|schedule| schedule.fallback()) {
+                current = *names.get(fallback).ok_or_else(|| {
+                    Error::invalid_endpoint_configuration(format!("fallback endpoint {} is not defined", fallback))
+                })?;
+                if visited.contains(&current) {
+                    return Err(Error::invalid_endpoint_configuration(format!(
+                        "fallback endpoint {} leads back to an endpoint already in the fallback chain",
+                        fallback
+                    )));
+                }
+                visited.push(current);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(all(feature = "parse-cfg", feature = "server"))]
@@ -84,6 +221,23 @@ impl TryFrom<&str> for ServerConfiguration {
     }
 }
 
+#[cfg(all(feature = "parse-cfg", feature = "server"))]
+impl TryFrom<&std::path::Path> for ServerConfiguration {
+    type Error = Error;
+
+    fn try_from(value: &std::path::Path) -> Result<Self, Self::Error> {
+        server_configuration_file::ServerConfigFileParser::from_path(value)
+    }
+}
+
+#[cfg(all(feature = "parse-cfg", feature = "server"))]
+impl ServerConfiguration {
+    /// Parse a `ServerConfiguration` from `text` written in the configuration file `format`.
+    pub fn from_format(text: &str, format: format::ConfigFormat) -> Result<Self, Error> {
+        server_configuration_file::ServerConfigFileParser::from_format(text, format)
+    }
+}
+
 #[cfg(feature = "client")]
 /// Client configuration that can be used to start the client.
 #[derive(Debug)]
@@ -128,6 +282,23 @@ impl TryFrom<&str> for ClientConfiguration {
     }
 }
 
+#[cfg(all(feature = "parse-cfg", feature = "client"))]
+impl TryFrom<&std::path::Path> for ClientConfiguration {
+    type Error = Error;
+
+    fn try_from(value: &std::path::Path) -> Result<Self, Self::Error> {
+        client_configuration_file::ClientConfigFileParser::from_path(value)
+    }
+}
+
+#[cfg(all(feature = "parse-cfg", feature = "client"))]
+impl ClientConfiguration {
+    /// Parse a `ClientConfiguration` from `text` written in the configuration file `format`.
+    pub fn from_format(text: &str, format: format::ConfigFormat) -> Result<Self, Error> {
+        client_configuration_file::ClientConfigFileParser::from_format(text, format)
+    }
+}
+
 #[cfg(all(feature = "parse-cfg", any(feature = "client", feature = "server")))]
 fn collect_interfaces(
     interface_configs: Vec<Box<dyn InterfaceConfig>>,
@@ -1,16 +1,24 @@
+use crate::configuration::format::{parse_file, write_file, ConfigFormat};
+#[cfg(feature = "schema")]
+use crate::configuration::schema::schema_for;
 use crate::configuration::{collect_interfaces, ClientConfiguration};
 use crate::interfaces::{Interface, InterfaceConfig};
 use crate::Error;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 /// Client configuration parsed from TOML that handles any [`InterfaceConfig`][`crate::interfaces::InterfaceConfig`].
 #[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(super) struct ClientConfigFileParser {
     client: ClientConfigFile,
 }
 
 /// Serde compatible representation of [`ClientConfiguration`]
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub struct ClientConfigFile {
     key: String,
     interface: Vec<Box<dyn InterfaceConfig>>,
@@ -19,9 +27,44 @@ pub struct ClientConfigFile {
 impl ClientConfigFileParser {
     /// Parse [`ClientConfiguration`] from provided TOML.
     pub fn from(string: &str) -> Result<ClientConfiguration, Error> {
-        let parsed: ClientConfigFileParser = toml::from_str(string)?;
+        Self::from_format(string, ConfigFormat::Toml)
+    }
+
+    /// Parse [`ClientConfiguration`] from provided text written in `format`.
+    pub fn from_format(string: &str, format: ConfigFormat) -> Result<ClientConfiguration, Error> {
+        let parsed: ClientConfigFileParser = parse_file(string, format)?;
         parsed.client.try_into()
     }
+
+    /// Parse [`ClientConfiguration`] from the file at `path` in the format picked from its extension.
+    pub fn from_path(path: &Path) -> Result<ClientConfiguration, Error> {
+        Self::from_format(std::fs::read_to_string(path)?.as_str(), ConfigFormat::from_path(path))
+    }
+}
+
+impl ClientConfigFile {
+    /// Read the client configuration file at `path` in the format picked from its extension.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)?;
+        let parsed: ClientConfigFileParser = parse_file(text.as_str(), ConfigFormat::from_path(path))?;
+        Ok(parsed.client)
+    }
+
+    /// Return the configuration written in `format`, which reads back as the same configuration.
+    pub fn to_format(&self, format: ConfigFormat) -> Result<String, Error> {
+        write_file("client", self, format, false)
+    }
+
+    /// Return the configuration written in `format` with the key replaced by `<redacted>`, to show it without it.
+    pub fn to_redacted_format(&self, format: ConfigFormat) -> Result<String, Error> {
+        write_file("client", self, format, true)
+    }
+
+    /// Return the JSON Schema of client configuration files with every interface type that is enabled.
+    #[cfg(feature = "schema")]
+    pub fn schema() -> serde_json::Value {
+        schema_for::<ClientConfigFileParser>("pass-it-on client configuration")
+    }
 }
 
 impl TryFrom<ClientConfigFile> for ClientConfiguration {
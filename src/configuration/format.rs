@@ -0,0 +1,179 @@
+//! Configuration file formats
+//!
+//! Server and client configuration files are TOML by default. With the `parse-yaml` and `parse-json` features the
+//! same settings can be written in YAML or JSON, which suits configuration kept in Helm values or generated by other
+//! tools. The format of a file is picked from its extension, `.yaml` or `.yml` for YAML, `.json` for JSON and TOML
+//! for anything else.
+//!
+//! Environment variables and `_file` settings work the same in every format. Including other files with `include`
+//! is only supported in TOML files.
+//!
+//! [`ServerConfigFile::to_format`][`crate::ServerConfigFile::to_format`] and
+//! [`ClientConfigFile::to_format`][`crate::ClientConfigFile::to_format`] write a configuration in any of the formats
+//! so it reads back as the same configuration, and `to_redacted_format` writes it with secrets like the key and
+//! passwords replaced to show it.
+//!
+//! # Configuration Example
+//! ```yaml
+//! server:
+//!   key: "${PASS_IT_ON_KEY}"
+//!   interface:
+//!     - type: http
+//!       port: 8080
+//!   endpoint:
+//!     - type: file
+//!       path: /var/log/pass-it-on/notifications.log
+//!       notifications: ["notification1", "notification2"]
+//! ```
+//!
+//! ```json
+//! {
+//!   "client": {
+//!     "key": "${PASS_IT_ON_KEY}",
+//!     "interface": [{ "type": "http", "host": "localhost", "port": 8080 }]
+//!   }
+//! }
+//! ```
+
+use crate::configuration::secrets::{deserialize, parse_document, prepare_value, resolve_value_secrets};
+use crate::Error;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Format of a configuration file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum ConfigFormat {
+    /// TOML, which is always supported.
+    #[default]
+    Toml,
+    /// YAML, which requires the `parse-yaml` feature.
+    Yaml,
+    /// JSON, which requires the `parse-json` feature.
+    Json,
+}
+
+impl ConfigFormat {
+    /// Return the format of the configuration file at `path` from its extension.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// Parse the configuration in `text` written in `format`, resolve its secrets and deserialize it.
+#[cfg(feature = "client")]
+pub(crate) fn parse_file<T: serde::de::DeserializeOwned>(text: &str, format: ConfigFormat) -> Result<T, Error> {
+    match format {
+        ConfigFormat::Toml => deserialize(parse_document(text)?, text),
+        _ => Ok(serde_json::from_value(parse_value(text, format)?)?),
+    }
+}
+
+/// Parse the configuration in `text` written in `format` to a [`Value`] and resolve its secrets.
+pub(crate) fn parse_value(text: &str, format: ConfigFormat) -> Result<Value, Error> {
+    let mut value = match format {
+        ConfigFormat::Toml => return deserialize(parse_document(text)?, text),
+        ConfigFormat::Yaml => parse_yaml(text)?,
+        ConfigFormat::Json => parse_json(text)?,
+    };
+
+    let problems = resolve_value_secrets(&mut value);
+    match problems.is_empty() {
+        true => Ok(value),
+        false => Err(Error::unresolved_secret(problems.join(", "))),
+    }
+}
+
+/// Write the `settings` of the configuration `table` as text in `format`, with its secrets replaced when `redact`
+/// is set.
+pub(crate) fn write_file<T: Serialize>(
+    table: &str,
+    settings: &T,
+    format: ConfigFormat,
+    redact: bool,
+) -> Result<String, Error> {
+    let mut settings = serde_json::to_value(settings)?;
+    prepare_value(&mut settings, redact);
+    let value = Value::Object(Map::from_iter([(table.to_string(), settings)]));
+    match format {
+        ConfigFormat::Toml => Ok(toml::to_string(&value)?),
+        ConfigFormat::Yaml => write_yaml(&value),
+        ConfigFormat::Json => write_json(&value),
+    }
+}
+
+/// Return the line and column of the syntax error in the YAML or JSON `text` written in `format`, if there is one.
+#[cfg(feature = "server")]
+pub(crate) fn syntax_location(text: &str, format: ConfigFormat) -> Option<(usize, usize)> {
+    match format {
+        ConfigFormat::Toml => None,
+        ConfigFormat::Yaml => yaml_location(text),
+        ConfigFormat::Json => json_location(text),
+    }
+}
+
+#[cfg(feature = "parse-yaml")]
+fn parse_yaml(text: &str) -> Result<Value, Error> {
+    Ok(serde_yaml_ng::from_str(text)?)
+}
+
+#[cfg(not(feature = "parse-yaml"))]
+fn parse_yaml(_text: &str) -> Result<Value, Error> {
+    Err(Error::disabled_config_format("parse-yaml".to_string()))
+}
+
+#[cfg(feature = "parse-yaml")]
+fn write_yaml(value: &Value) -> Result<String, Error> {
+    Ok(serde_yaml_ng::to_string(value)?)
+}
+
+#[cfg(not(feature = "parse-yaml"))]
+fn write_yaml(_value: &Value) -> Result<String, Error> {
+    Err(Error::disabled_config_format("parse-yaml".to_string()))
+}
+
+#[cfg(all(feature = "server", feature = "parse-yaml"))]
+fn yaml_location(text: &str) -> Option<(usize, usize)> {
+    let error = serde_yaml_ng::from_str::<Value>(text).err()?;
+    error.location().map(|location| (location.line(), location.column()))
+}
+
+#[cfg(all(feature = "server", not(feature = "parse-yaml")))]
+fn yaml_location(_text: &str) -> Option<(usize, usize)> {
+    None
+}
+
+#[cfg(feature = "parse-json")]
+fn parse_json(text: &str) -> Result<Value, Error> {
+    Ok(serde_json::from_str(text)?)
+}
+
+#[cfg(not(feature = "parse-json"))]
+fn parse_json(_text: &str) -> Result<Value, Error> {
+    Err(Error::disabled_config_format("parse-json".to_string()))
+}
+
+#[cfg(feature = "parse-json")]
+fn write_json(value: &Value) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(value)? + "\n")
+}
+
+#[cfg(not(feature = "parse-json"))]
+fn write_json(_value: &Value) -> Result<String, Error> {
+    Err(Error::disabled_config_format("parse-json".to_string()))
+}
+
+#[cfg(all(feature = "server", feature = "parse-json"))]
+fn json_location(text: &str) -> Option<(usize, usize)> {
+    let error = serde_json::from_str::<Value>(text).err()?;
+    Some((error.line(), error.column().max(1)))
+}
+
+#[cfg(all(feature = "server", not(feature = "parse-json")))]
+fn json_location(_text: &str) -> Option<(usize, usize)> {
+    None
+}
@@ -0,0 +1,291 @@
+//! Including other configuration files
+//!
+//! A server configuration file can include other files with `include` in the `[server]` table, for example to keep
+//! one file of endpoints per team in a `conf.d` directory. Each entry is a file, a directory whose `*.toml` files are
+//! all included, or a file pattern with `*` in the file name like `conf.d/*.toml`. Relative paths start from the
+//! directory of the file that includes them, or the current directory when the configuration is not read from a
+//! file. The files of a directory or pattern are included in name order.
+//!
+//! Included files can only contain `[[server.interface]]`, `[[server.endpoint]]` and `[[server.escalation]]`
+//! entries and an `include` of their own. They are appended after the entries of the including file in the order
+//! the files are included. Other server settings, a file that is included more than once, an endpoint name that is
+//! already used and an interface or endpoint defined twice are reported with the file they are in.
+//!
+//! # Configuration Example
+//! ```toml
+//! [server]
+//! key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+//! include = ["conf.d/*.toml"]
+//!
+//! [[server.interface]]
+//! type = "http"
+//! port = 8080
+//! ```
+//!
+//! `conf.d/ops.toml`
+//! ```toml
+//! [[server.endpoint]]
+//! type = "file"
+//! name = "ops-log"
+//! path = "/var/log/pass-it-on/ops.log"
+//! notifications = ["backup", "disk"]
+//! ```
+
+use crate::configuration::secrets::{location, resolve_secrets};
+use serde::Deserialize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::de::{DeTable, DeValue, ValueDeserializer};
+use toml::Spanned;
+
+pub(crate) const INCLUDE: &str = "include";
+
+/// Settings an included file can contain in its `[server]` table.
+const INCLUDABLE: [&str; 4] = [INCLUDE, "interface", "endpoint", "escalation"];
+
+/// Configuration file included by another one.
+#[derive(Debug, Clone)]
+pub(crate) struct Included {
+    path: PathBuf,
+    text: String,
+}
+
+/// Problem with where it is, in the main configuration file when `file` is `None`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct FileProblem {
+    file: Option<PathBuf>,
+    span: Range<usize>,
+    message: String,
+}
+
+/// Interfaces and endpoints defined so far and the file they are defined in.
+#[derive(Debug, Default)]
+pub(crate) struct Definitions {
+    defined: Vec<(String, Option<PathBuf>)>,
+}
+
+impl Included {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl FileProblem {
+    pub fn new<S: AsRef<str>>(file: Option<&Path>, span: Range<usize>, message: S) -> Self {
+        Self { file: file.map(PathBuf::from), span, message: message.as_ref().into() }
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Describe the problem with its file, line and column in the main configuration `toml` or included `files`.
+    pub fn describe(&self, toml: &str, files: &[Included]) -> String {
+        let text = match self.file() {
+            Some(file) => files.iter().find(|included| included.path == file).map(Included::text).unwrap_or_default(),
+            None => toml,
+        };
+        let (line, column) = location(text, self.span.start);
+        format!("{}: line {}, column {}: {}", file_name(self.file()), line, column, self.message)
+    }
+}
+
+impl Definitions {
+    /// Record the interfaces and endpoints of the server `table` in `file` and return those already defined in
+    /// another file. Duplicates within one file are left to the validation of the whole configuration.
+    pub fn define(&mut self, file: Option<&Path>, table: &DeTable) -> Vec<FileProblem> {
+        let mut problems = Vec::new();
+        for (kind, value) in entries(table, "interface").chain(entries(table, "endpoint")) {
+            let name = value.get_ref().get("name").and_then(|name| name.get_ref().as_str());
+            if let Some(name) = name.filter(|_| kind == "endpoint") {
+                let identity = format!("endpoint name {}", name);
+                if let Some(defined) = self.find(&identity, file) {
+                    let message = format!("endpoint name {} is already used in {}", name, file_name(defined));
+                    problems.push(FileProblem::new(file, value.span(), message));
+                    continue;
+                }
+                self.defined.push((identity, file.map(PathBuf::from)));
+            }
+
+            let Ok(settings) = toml::Value::deserialize(ValueDeserializer::from(value.clone())) else { continue };
+            let identity = format!("{} {:?}", kind, settings);
+            match self.find(&identity, file) {
+                Some(defined) => {
+                    let message = format!("the same {} is already defined in {}", kind, file_name(defined));
+                    problems.push(FileProblem::new(file, value.span(), message));
+                }
+                None => self.defined.push((identity, file.map(PathBuf::from))),
+            }
+        }
+        problems
+    }
+
+    fn find(&self, identity: &str, file: Option<&Path>) -> Option<Option<&Path>> {
+        self.defined
+            .iter()
+            .find(|(defined, defined_file)| defined == identity && defined_file.as_deref() != file)
+            .map(|(_, file)| file.as_deref())
+    }
+}
+
+/// Read the files included by the configuration `document` read from `path` and the files they include.
+///
+/// The problems of entries that could not be included are returned next to the files that could.
+pub(crate) fn read_includes(document: &DeTable, path: Option<&Path>) -> (Vec<Included>, Vec<FileProblem>) {
+    let mut seen: Vec<PathBuf> = path.and_then(|path| path.canonicalize().ok()).into_iter().collect();
+    let mut included = Vec::new();
+    let mut problems = Vec::new();
+    visit(document, path, &mut seen, &mut included, &mut problems);
+    (included, problems)
+}
+
+/// Return a problem for each setting of the server `table` in the included `file` that cannot be included.
+pub(crate) fn check_includable(file: &Path, table: &DeTable) -> Vec<FileProblem> {
+    table
+        .iter()
+        .filter(|(key, _)| !INCLUDABLE.contains(&key.get_ref().as_ref()))
+        .map(|(key, _)| {
+            let message = format!(
+                "`{}` can only be set in the main configuration file, included files can only set {}",
+                key.get_ref(),
+                INCLUDABLE.join(", ")
+            );
+            FileProblem::new(Some(file), key.span(), message)
+        })
+        .collect()
+}
+
+/// Remove `include` from the server table of a `document` once the files it includes are read.
+pub(crate) fn remove_include(document: &mut DeTable) {
+    if let Some(DeValue::Table(server)) = document.get_mut("server").map(Spanned::get_mut) {
+        server.remove(INCLUDE);
+    }
+}
+
+/// Return the server table of a configuration `document`.
+pub(crate) fn server_table<'a, 'i>(document: &'a DeTable<'i>) -> Option<&'a DeTable<'i>> {
+    document.get("server").and_then(|server| server.get_ref().as_table())
+}
+
+/// Return how a configuration file is named in messages.
+pub(crate) fn file_name(file: Option<&Path>) -> String {
+    match file {
+        Some(file) => file.display().to_string(),
+        None => "the main configuration file".to_string(),
+    }
+}
+
+fn visit(
+    document: &DeTable,
+    path: Option<&Path>,
+    seen: &mut Vec<PathBuf>,
+    included: &mut Vec<Included>,
+    problems: &mut Vec<FileProblem>,
+) {
+    let Some(include) = server_table(document).and_then(|server| server.get(INCLUDE)) else { return };
+    let Some(entries) = include.get_ref().as_array() else {
+        problems.push(FileProblem::new(path, include.span(), "`include` must be an array of paths"));
+        return;
+    };
+
+    let base = path.and_then(Path::parent).map(PathBuf::from).unwrap_or_default();
+    for entry in entries.iter() {
+        let files = match entry.get_ref().as_str() {
+            Some(pattern) => expand(base.join(pattern).as_path()),
+            None => Err("`include` must be an array of paths".to_string()),
+        };
+        let files = match files {
+            Ok(files) => files,
+            Err(message) => {
+                problems.push(FileProblem::new(path, entry.span(), message));
+                continue;
+            }
+        };
+
+        for file in files {
+            let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+            if seen.contains(&canonical) {
+                let message = format!("{} is included more than once", file.display());
+                problems.push(FileProblem::new(path, entry.span(), message));
+                continue;
+            }
+            seen.push(canonical);
+
+            let text = match std::fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) => {
+                    let message = format!("unable to read {}: {}", file.display(), e);
+                    problems.push(FileProblem::new(path, entry.span(), message));
+                    continue;
+                }
+            };
+            // Problems in the included file are reported when the file itself is parsed
+            let (mut document, _) = DeTable::parse_recoverable(text.as_str());
+            resolve_secrets(document.get_mut());
+            included.push(Included { path: file.clone(), text: text.clone() });
+            visit(document.get_ref(), Some(file.as_path()), seen, included, problems);
+        }
+    }
+}
+
+/// Return the files named by an include `pattern`, which is a file, a directory or has `*` in its file name.
+fn expand(pattern: &Path) -> Result<Vec<PathBuf>, String> {
+    let file_name = pattern.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let (directory, wildcard) = match (file_name.contains('*'), pattern.is_dir()) {
+        (true, _) => (pattern.parent().unwrap_or(Path::new("")), file_name),
+        (false, true) => (pattern, "*.toml"),
+        (false, false) => return Ok(vec![pattern.to_path_buf()]),
+    };
+
+    let directory = match directory.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => directory,
+    };
+    let entries =
+        std::fs::read_dir(directory).map_err(|e| format!("unable to read directory {}: {}", directory.display(), e))?;
+    let mut files: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| matches(wildcard, name)))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Return if `name` matches the `wildcard` where `*` matches any number of characters.
+fn matches(wildcard: &str, name: &str) -> bool {
+    let mut parts = wildcard.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else { return false };
+    let mut parts: Vec<_> = parts.collect();
+    let Some(last) = parts.pop() else { return rest.is_empty() };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Return the `kind` and each entry of the array `kind` in the server `table`.
+fn entries<'a, 'i>(
+    table: &'a DeTable<'i>,
+    kind: &'static str,
+) -> impl Iterator<Item = (&'static str, &'a Spanned<DeValue<'i>>)> {
+    let values = table.get(kind).and_then(|values| values.get_ref().as_array());
+    values.into_iter().flat_map(|values| values.iter()).map(move |value| (kind, value))
+}
@@ -0,0 +1,174 @@
+//! JSON Schema of the configuration files
+//!
+//! With the `schema` feature [`ServerConfigFile::schema`][`crate::ServerConfigFile::schema`] and
+//! [`ClientConfigFile::schema`][`crate::ClientConfigFile::schema`] return a JSON Schema of the configuration files
+//! that editors like Taplo or the YAML language server use to validate and complete them. The schema includes every
+//! interface and endpoint type enabled when the crate is built, and a `_file` variant of each text setting.
+//! Settings that are not in the schema are rejected when the configuration is read.
+//!
+//! # Configuration Example
+//! ```text
+//! pass-it-on-server schema > pass-it-on-server.schema.json
+//! ```
+//!
+//! ```toml
+//! #:schema ./pass-it-on-server.schema.json
+//! [server]
+//! key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+//! ```
+
+#[cfg(feature = "server")]
+use crate::configuration::include::INCLUDE;
+use crate::configuration::secrets::FILE_SUFFIX;
+#[cfg(feature = "server")]
+use crate::endpoints::EndpointConfig;
+use crate::interfaces::InterfaceConfig;
+use schemars::generate::SchemaSettings;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+
+const TYPE: &str = "type";
+
+impl JsonSchema for Box<dyn InterfaceConfig> {
+    fn schema_name() -> Cow<'static, str> {
+        "InterfaceConfig".into()
+    }
+
+    // Builds without any interface or endpoint feature have no types to add
+    #[allow(unused_mut, unused_variables)]
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let mut types: Vec<Schema> = Vec::new();
+        #[cfg(any(feature = "http-client", feature = "http-server"))]
+        types.push(tagged::<crate::interfaces::http::HttpSocketConfigFile>(generator, "http"));
+        #[cfg(all(unix, any(feature = "pipe-client", feature = "pipe-server", feature = "pipe")))]
+        types.push(tagged::<crate::interfaces::pipe::PipeConfigFile>(generator, "pipe"));
+        json_schema!({ "oneOf": types })
+    }
+}
+
+#[cfg(feature = "server")]
+impl JsonSchema for Box<dyn EndpointConfig> {
+    fn schema_name() -> Cow<'static, str> {
+        "EndpointConfig".into()
+    }
+
+    // Builds without any interface or endpoint feature have no types to add
+    #[allow(unused_mut, unused_variables)]
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let mut types: Vec<Schema> = Vec::new();
+        #[cfg(feature = "discord")]
+        types.push(tagged::<crate::endpoints::discord::DiscordConfigFile>(generator, "discord"));
+        #[cfg(feature = "email")]
+        types.push(tagged::<crate::endpoints::email::EmailConfigFile>(generator, "email"));
+        #[cfg(feature = "file")]
+        types.push(tagged::<crate::endpoints::file::FileConfigFile>(generator, "file"));
+        #[cfg(feature = "matrix")]
+        types.push(tagged::<crate::endpoints::matrix::MatrixConfigFile>(generator, "matrix"));
+        json_schema!({ "oneOf": types })
+    }
+}
+
+/// Return the JSON Schema with `title` of a configuration file deserialized as `T`.
+pub(crate) fn schema_for<T: JsonSchema>(title: &str) -> Value {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let mut schema = generator.root_schema_for::<T>();
+    schema.insert("title".into(), title.into());
+    let mut schema = schema.to_value();
+    add_file_settings(&mut schema);
+    schema
+}
+
+/// Add the `include` setting, which is read before the rest of the configuration, to the server table `schema`.
+#[cfg(feature = "server")]
+pub(crate) fn with_include(schema: &mut Schema) {
+    let include = json!({
+        "description":
+            "Files, directories or patterns like `conf.d/*.toml` with more interfaces, endpoints and escalations",
+        "type": "array",
+        "items": { "type": "string" },
+    });
+    if let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) {
+        properties.insert(INCLUDE.into(), include);
+    }
+}
+
+/// Return the schema of the configuration `T` of the interface or endpoint type `name` with its `type` tag.
+#[cfg_attr(
+    not(any(
+        feature = "http-client",
+        feature = "http-server",
+        feature = "pipe-client",
+        feature = "pipe-server",
+        feature = "discord",
+        feature = "email",
+        feature = "file",
+        feature = "matrix"
+    )),
+    allow(dead_code)
+)]
+fn tagged<T: JsonSchema>(generator: &mut SchemaGenerator, name: &str) -> Schema {
+    let mut schema = T::json_schema(generator);
+    schema.insert("title".into(), name.into());
+    if let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) {
+        properties.insert(TYPE.into(), json!({ "const": name }));
+    }
+    match schema.get_mut("required").and_then(Value::as_array_mut) {
+        Some(required) => required.insert(0, TYPE.into()),
+        None => {
+            schema.insert("required".into(), json!([TYPE]));
+        }
+    }
+    schema
+}
+
+/// Allow each text setting in `schema` to be read from a file with the `_file` suffix instead.
+fn add_file_settings(schema: &mut Value) {
+    match schema {
+        Value::Object(object) => {
+            object.values_mut().for_each(add_file_settings);
+
+            let Some(Value::Object(properties)) = object.get_mut("properties") else { return };
+            let text: Vec<_> = properties
+                .iter()
+                .filter(|(name, property)| !name.ends_with(FILE_SUFFIX) && accepts_text(property))
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in text.iter() {
+                let description = format!("File to read `{}` from", name);
+                let file_setting = json!({ "description": description, "type": "string" });
+                properties.insert(format!("{}{}", name, FILE_SUFFIX), file_setting);
+            }
+
+            // A required setting can be given either directly or from a file
+            let Some(Value::Array(required)) = object.get_mut("required") else { return };
+            let either: Vec<_> = required
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|name| text.iter().any(|text| text == name))
+                .map(|name| {
+                    let file_setting = format!("{}{}", name, FILE_SUFFIX);
+                    json!({ "anyOf": [{ "required": [name] }, { "required": [file_setting] }] })
+                })
+                .collect();
+            required.retain(|name| !name.as_str().is_some_and(|name| text.iter().any(|text| text == name)));
+            if required.is_empty() {
+                object.remove("required");
+            }
+            if !either.is_empty() {
+                object.insert("allOf".into(), Value::Array(either));
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(add_file_settings),
+        _ => (),
+    }
+}
+
+/// Return if a property `schema` accepts a string.
+fn accepts_text(schema: &Value) -> bool {
+    match schema.get(TYPE) {
+        Some(Value::String(kind)) => kind == "string",
+        Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind == "string"),
+        _ => false,
+    }
+}
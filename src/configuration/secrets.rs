@@ -0,0 +1,323 @@
+//! Secrets in configuration files
+//!
+//! Every string value in a server or client configuration file, whatever its format, can refer to environment
+//! variables with `${NAME}`, which is replaced with the value of the variable `NAME`. Use `$${` for a literal `${`.
+//!
+//! Any setting can also be read from a file by adding `_file` to its name, like `password_file` for `password`.
+//! The file content is used as the value without its trailing newline, which suits Docker and Kubernetes secrets.
+//! The path of the file can use environment variables, the content of the file is used as is.
+//!
+//! A variable that is not set, a file that cannot be read or a setting given both directly and from a file is
+//! reported with its location in the configuration file, or the path of its setting like `server.key` in YAML and
+//! JSON files.
+//!
+//! A configuration written with `to_redacted_format` shows `<redacted>` instead of the key, passwords, tokens,
+//! the Matrix recovery passphrase and the Discord webhook URL.
+//!
+//! # Configuration Example
+//! ```toml
+//! [server]
+//! key_file = "/run/secrets/pass-it-on-key"
+//!
+//! [[server.endpoint]]
+//! type = "email"
+//! hostname = "smtp.example.com"
+//! port = 587
+//! username = "${SMTP_USERNAME}"
+//! password_file = "${CREDENTIALS_DIRECTORY}/smtp-password"
+//! from = "pass-it-on@example.com"
+//! to = ["ops@example.com"]
+//! subject = "Pass-It-On"
+//! notifications = ["notification1"]
+//! ```
+
+use crate::Error;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::env::VarError;
+use std::ops::Range;
+use toml::de::{DeTable, DeValue, Deserializer};
+use toml::Spanned;
+
+pub(crate) const FILE_SUFFIX: &str = "_file";
+
+/// Settings that hold secrets, replaced when a configuration is written redacted.
+const SECRET_SETTINGS: [&str; 5] = ["key", "password", "recovery_passphrase", "token", "url"];
+const REDACTED: &str = "<redacted>";
+
+/// Secret that could not be resolved with where it is in the configuration file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct SecretProblem {
+    span: Range<usize>,
+    message: String,
+}
+
+// Only checking a server configuration reports where each problem is
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+impl SecretProblem {
+    fn new<S: AsRef<str>>(span: Range<usize>, message: S) -> Self {
+        Self { span, message: message.as_ref().into() }
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Parse the TOML document in `toml` and resolve its secrets.
+pub(crate) fn parse_document(toml: &str) -> Result<Spanned<DeTable<'_>>, Error> {
+    let mut document = DeTable::parse(toml)?;
+    let problems = resolve_secrets(document.get_mut());
+    match problems.is_empty() {
+        true => Ok(document),
+        false => {
+            let problems: Vec<_> = problems
+                .iter()
+                .map(|problem| {
+                    let (line, column) = location(toml, problem.span.start);
+                    format!("line {}, column {}: {}", line, column, problem.message)
+                })
+                .collect();
+            Err(Error::unresolved_secret(problems.join(", ")))
+        }
+    }
+}
+
+/// Deserialize a `document` parsed from `toml`.
+pub(crate) fn deserialize<T: DeserializeOwned>(document: Spanned<DeTable<'_>>, toml: &str) -> Result<T, Error> {
+    T::deserialize(Deserializer::from(document)).map_err(|mut e| {
+        e.set_input(Some(toml));
+        e.into()
+    })
+}
+
+/// Replace environment variables and `_file` settings in `table` and return the secrets that could not be resolved.
+pub(crate) fn resolve_secrets(table: &mut DeTable<'_>) -> Vec<SecretProblem> {
+    let mut problems = Vec::new();
+    resolve_table(table, &mut problems);
+    problems
+}
+
+/// Replace environment variables and `_file` settings in a YAML or JSON `value` and return the secrets that could
+/// not be resolved with the path of their setting.
+pub(crate) fn resolve_value_secrets(value: &mut Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    resolve_json(value, "", &mut problems);
+    problems
+}
+
+/// Return the line and column, both starting at 1, of the byte `offset` in `toml`.
+pub(crate) fn location(toml: &str, offset: usize) -> (usize, usize) {
+    let before = &toml[..offset.min(toml.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+    (line, column)
+}
+
+fn resolve_table(table: &mut DeTable<'_>, problems: &mut Vec<SecretProblem>) {
+    table.iter_mut().for_each(|(_, value)| resolve_value(value, problems));
+
+    let files: Vec<_> = table.keys().filter(|key| key.get_ref().ends_with(FILE_SUFFIX)).cloned().collect();
+    for file_key in files {
+        let Some(file) = table.remove(file_key.get_ref().as_ref()) else { continue };
+        let name = file_key.get_ref().trim_end_matches(FILE_SUFFIX).to_string();
+        if table.contains_key(name.as_str()) {
+            problems.push(SecretProblem::new(
+                file_key.span(),
+                format!("set either `{}` or `{}`, not both", name, file_key.get_ref()),
+            ));
+            continue;
+        }
+
+        let content = match file.get_ref().as_str() {
+            Some(path) => read_secret(path).unwrap_or_else(|message| {
+                problems.push(SecretProblem::new(file.span(), message));
+                String::new()
+            }),
+            None => {
+                problems.push(SecretProblem::new(file.span(), format!("`{}` must be a path", file_key.get_ref())));
+                String::new()
+            }
+        };
+        table.insert(
+            Spanned::new(file_key.span(), Cow::Owned(name)),
+            Spanned::new(file.span(), DeValue::String(Cow::Owned(content))),
+        );
+    }
+}
+
+fn resolve_value(value: &mut Spanned<DeValue<'_>>, problems: &mut Vec<SecretProblem>) {
+    let span = value.span();
+    match value.get_mut() {
+        DeValue::String(string) if string.contains('$') => match interpolate(string) {
+            Ok(resolved) => *string = Cow::Owned(resolved),
+            Err(message) => problems.push(SecretProblem::new(span, message)),
+        },
+        DeValue::Array(array) => array.iter_mut().for_each(|value| resolve_value(value, problems)),
+        DeValue::Table(table) => resolve_table(table, problems),
+        _ => (),
+    }
+}
+
+fn resolve_json(value: &mut Value, path: &str, problems: &mut Vec<String>) {
+    match value {
+        Value::String(string) if string.contains('$') => match interpolate(string) {
+            Ok(resolved) => *string = resolved,
+            Err(message) => problems.push(format!("{}: {}", path, message)),
+        },
+        Value::Array(array) => {
+            for (index, value) in array.iter_mut().enumerate() {
+                resolve_json(value, format!("{}[{}]", path, index).as_str(), problems);
+            }
+        }
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                resolve_json(value, setting_path(path, key).as_str(), problems);
+            }
+
+            let files: Vec<_> = object.keys().filter(|key| key.ends_with(FILE_SUFFIX)).cloned().collect();
+            for file_key in files {
+                let Some(file) = object.remove(file_key.as_str()) else { continue };
+                let file_path = setting_path(path, file_key.as_str());
+                let name = file_key.trim_end_matches(FILE_SUFFIX).to_string();
+                if object.contains_key(name.as_str()) {
+                    problems.push(format!("{}: set either `{}` or `{}`, not both", file_path, name, file_key));
+                    continue;
+                }
+
+                let content = match file.as_str() {
+                    Some(secret) => read_secret(secret).unwrap_or_else(|message| {
+                        problems.push(format!("{}: {}", file_path, message));
+                        String::new()
+                    }),
+                    None => {
+                        problems.push(format!("{}: `{}` must be a path", file_path, file_key));
+                        String::new()
+                    }
+                };
+                object.insert(name, Value::String(content));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Prepare the serialized configuration `value` to be written, replacing its secrets when `redact` is set.
+///
+/// Settings that are not set are left out and `${` is escaped so every value reads back as it is.
+pub(crate) fn prepare_value(value: &mut Value, redact: bool) {
+    match value {
+        Value::String(string) => *string = string.replace("${", "$${"),
+        Value::Array(array) => array.iter_mut().for_each(|value| prepare_value(value, redact)),
+        Value::Object(object) => {
+            object.retain(|_, value| !value.is_null());
+            for (key, value) in object.iter_mut() {
+                match redact && value.is_string() && SECRET_SETTINGS.contains(&key.as_str()) {
+                    true => *value = Value::String(REDACTED.to_string()),
+                    false => prepare_value(value, redact),
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Return the path of the setting `key` in the table at `path`, like `server.endpoint[0].password`.
+fn setting_path(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", path, key),
+    }
+}
+
+/// Return the content of the secret file at `path` without its trailing newline.
+fn read_secret(path: &str) -> Result<String, String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content.trim_end_matches(['\n', '\r']).to_string()),
+        Err(e) => Err(format!("unable to read {}: {}", path, e)),
+    }
+}
+
+/// Replace each `${NAME}` in `value` with the environment variable `NAME`.
+fn interpolate(value: &str) -> Result<String, String> {
+    interpolate_with(value, |name| std::env::var(name))
+}
+
+/// Replace each `${NAME}` in `value` with the variable `NAME` returned by `lookup`.
+fn interpolate_with<F>(value: &str, lookup: F) -> Result<String, String>
+where
+    F: Fn(&str) -> Result<String, VarError>,
+{
+    let mut resolved = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        resolved.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            resolved.push_str("${");
+            rest = escaped;
+        } else if let Some(variable) = rest.strip_prefix("${") {
+            let end = variable.find('}').ok_or_else(|| format!("`${{` is not closed with `}}` in {}", value))?;
+            let name = &variable[..end];
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("`{}` is not a valid environment variable name", name));
+            }
+            match lookup(name) {
+                Ok(variable) => resolved.push_str(variable.as_str()),
+                Err(VarError::NotPresent) => return Err(format!("environment variable {} is not set", name)),
+                Err(VarError::NotUnicode(_)) => {
+                    return Err(format!("environment variable {} is not valid unicode", name))
+                }
+            }
+            rest = &variable[end + 1..];
+        } else {
+            resolved.push('$');
+            rest = &rest[1..];
+        }
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Result<String, VarError> {
+        match name {
+            "USER_NAME" => Ok("pass-it-on".to_string()),
+            "DIRECTORY" => Ok("/run/secrets".to_string()),
+            _ => Err(VarError::NotPresent),
+        }
+    }
+
+    #[test]
+    fn variables_are_replaced() {
+        assert_eq!(interpolate_with("${USER_NAME}", lookup).unwrap(), "pass-it-on");
+        assert_eq!(interpolate_with("${DIRECTORY}/${USER_NAME}.key", lookup).unwrap(), "/run/secrets/pass-it-on.key");
+        assert_eq!(interpolate_with("no variables", lookup).unwrap(), "no variables");
+    }
+
+    #[test]
+    fn escaped_and_lone_dollar_signs_are_kept() {
+        assert_eq!(interpolate_with("$${USER_NAME}", lookup).unwrap(), "${USER_NAME}");
+        assert_eq!(interpolate_with("cost $5 for $USER_NAME", lookup).unwrap(), "cost $5 for $USER_NAME");
+        assert_eq!(interpolate_with("$$${USER_NAME}", lookup).unwrap(), "$${USER_NAME}");
+    }
+
+    #[test]
+    fn invalid_variables_are_reported() {
+        assert_eq!(interpolate_with("${MISSING}", lookup).unwrap_err(), "environment variable MISSING is not set");
+        assert_eq!(interpolate_with("${}", lookup).unwrap_err(), "`` is not a valid environment variable name");
+        assert_eq!(
+            interpolate_with("${USER-NAME}", lookup).unwrap_err(),
+            "`USER-NAME` is not a valid environment variable name"
+        );
+        assert_eq!(interpolate_with("${USER_NAME", lookup).unwrap_err(), "`${` is not closed with `}` in ${USER_NAME");
+    }
+}
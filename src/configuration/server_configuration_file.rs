@@ -1,29 +1,551 @@
+use crate::configuration::format::{parse_value, syntax_location, write_file, ConfigFormat};
+use crate::configuration::include::{
+    check_includable, file_name, read_includes, remove_include, server_table, Definitions, FileProblem, Included,
+    INCLUDE,
+};
+#[cfg(feature = "schema")]
+use crate::configuration::schema::schema_for;
+use crate::configuration::secrets::{deserialize, location, parse_document, resolve_secrets};
 use crate::configuration::{collect_endpoints, collect_interfaces, ServerConfiguration};
+use crate::endpoints::dedup::{Dedup, DedupConfigFile};
 use crate::endpoints::{Endpoint, EndpointConfig};
 use crate::interfaces::{Interface, InterfaceConfig};
+use crate::notifications::Key;
+use crate::server::admin::{Admin, AdminConfigFile};
+use crate::server::dead_letter::{DeadLetter, DeadLetterConfigFile};
+use crate::server::dispatch::endpoint_label;
+use crate::server::escalation::{Escalation, EscalationConfigFile};
+use crate::server::replay::{ReplayProtection, ReplayProtectionConfigFile};
+use crate::server::spool::{Spool, SpoolConfigFile};
 use crate::Error;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::de::{DeTable, DeValue, ValueDeserializer};
+use toml::Spanned;
+
+/// Settings of the `[server]` table, `include` is read before the rest of the configuration.
+const SERVER_SETTINGS: [&str; 10] = [
+    "key",
+    "replay_protection",
+    "dedup",
+    "spool",
+    "dead_letter",
+    "escalation",
+    "admin",
+    "interface",
+    "endpoint",
+    INCLUDE,
+];
 
 /// Server configuration parsed from TOML that handles any [`InterfaceConfig`][`crate::interfaces::InterfaceConfig`]
 /// and [`EndpointConfig`][`crate::endpoints::EndpointConfig`].
 #[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(super) struct ServerConfigFileParser {
     server: ServerConfigFile,
 }
 
 /// Serde compatible representation of [`ServerConfiguration`]
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schema", schemars(transform = crate::configuration::schema::with_include))]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfigFile {
     key: String,
+    replay_protection: Option<ReplayProtectionConfigFile>,
+    dedup: Option<DedupConfigFile>,
+    spool: Option<SpoolConfigFile>,
+    dead_letter: Option<DeadLetterConfigFile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    escalation: Vec<EscalationConfigFile>,
+    admin: Option<AdminConfigFile>,
+    #[serde(default)]
+    interface: Vec<Box<dyn InterfaceConfig>>,
+    #[serde(default)]
+    endpoint: Vec<Box<dyn EndpointConfig>>,
+}
+
+/// Configuration file included by a [`ServerConfigFile`].
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct IncludedConfigFileParser {
+    #[serde(default)]
+    server: IncludedConfigFile,
+}
+
+/// Entries an included configuration file adds to the [`ServerConfigFile`] that includes it.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct IncludedConfigFile {
+    #[serde(default)]
+    escalation: Vec<EscalationConfigFile>,
+    #[serde(default)]
     interface: Vec<Box<dyn InterfaceConfig>>,
+    #[serde(default)]
     endpoint: Vec<Box<dyn EndpointConfig>>,
 }
 
+/// Problem found in a server configuration file by [`ServerConfigFile::check`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConfigProblem {
+    file: Option<PathBuf>,
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+/// Notification name that reaches an endpoint, listed by [`ServerConfigFile::routes`].
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct NotificationRoute {
+    notification: String,
+    endpoint: String,
+    target: String,
+}
+
+/// Collects the problems found while checking a configuration file.
+struct Checker<'a> {
+    toml: &'a str,
+    file: Option<&'a Path>,
+    problems: Vec<ConfigProblem>,
+}
+
+impl ServerConfigFile {
+    /// Read the server configuration file at `path` with the entries of the files it includes, in the format picked
+    /// from its extension.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let format = ConfigFormat::from_path(path);
+        Ok(ServerConfigFileParser::load(std::fs::read_to_string(path)?.as_str(), Some(path), format)?.0)
+    }
+
+    /// Return the configuration written in `format`, which reads back as the same configuration.
+    ///
+    /// Environment variables and `_file` settings are written with the values they were resolved to, and the entries
+    /// of included files are written with the others.
+    pub fn to_format(&self, format: ConfigFormat) -> Result<String, Error> {
+        write_file("server", self, format, false)
+    }
+
+    /// Return the configuration written in `format` like [`ServerConfigFile::to_format`] with the key, passwords,
+    /// tokens and webhook URLs replaced by `<redacted>`, to show it without its secrets.
+    pub fn to_redacted_format(&self, format: ConfigFormat) -> Result<String, Error> {
+        write_file("server", self, format, true)
+    }
+
+    /// Check the server configuration in `toml` and return every problem found with its location.
+    ///
+    /// Each section, interface and endpoint is checked on its own so one problem does not hide the others. The
+    /// interfaces and endpoints are created to validate their settings but nothing is bound, opened or logged in to.
+    /// Endpoint names, fallbacks and escalation chains are only checked once every interface and endpoint is valid.
+    /// Included files are looked up from the current directory.
+    pub fn check(toml: &str) -> Vec<ConfigProblem> {
+        check_configuration(toml, None)
+    }
+
+    /// Check the server configuration file at `path` and the files it includes like [`ServerConfigFile::check`].
+    ///
+    /// The format of the file is picked from its extension. YAML and JSON files are checked as a whole and report
+    /// the first problem found, at the start of the file unless it is a syntax error.
+    pub fn check_file(path: &Path) -> Result<Vec<ConfigProblem>, Error> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(match ConfigFormat::from_path(path) {
+            ConfigFormat::Toml => check_configuration(text.as_str(), Some(path)),
+            format => check_document(text.as_str(), format),
+        })
+    }
+
+    /// Return which notification names reach each endpoint and target for the server configuration in `toml`.
+    ///
+    /// Notification names are found by matching the keys each endpoint generates against the names listed in
+    /// `notifications` anywhere in the endpoint settings. Included files are looked up from the current directory.
+    pub fn routes(toml: &str) -> Result<Vec<NotificationRoute>, Error> {
+        configuration_routes(toml, None, ConfigFormat::Toml)
+    }
+
+    /// Return the routes of the server configuration file at `path` and the files it includes like
+    /// [`ServerConfigFile::routes`]. The format of the file is picked from its extension.
+    pub fn routes_file(path: &Path) -> Result<Vec<NotificationRoute>, Error> {
+        configuration_routes(std::fs::read_to_string(path)?.as_str(), Some(path), ConfigFormat::from_path(path))
+    }
+
+    /// Return the JSON Schema of server configuration files with every interface and endpoint type that is enabled.
+    #[cfg(feature = "schema")]
+    pub fn schema() -> serde_json::Value {
+        schema_for::<ServerConfigFileParser>("pass-it-on server configuration")
+    }
+
+    /// Add the entries of an included file after the ones already defined.
+    fn append(&mut self, included: IncludedConfigFile) {
+        self.escalation.extend(included.escalation);
+        self.interface.extend(included.interface);
+        self.endpoint.extend(included.endpoint);
+    }
+}
+
+fn check_configuration(toml: &str, path: Option<&Path>) -> Vec<ConfigProblem> {
+    let mut checker = Checker { toml, file: None, problems: Vec::new() };
+    let Some(document) = checker.parse_document() else { return checker.problems };
+    let Some(table) = server_table(document.get_ref()) else {
+        checker.problem(document.span(), "missing table `server`");
+        return checker.problems;
+    };
+    let server_span = document.get_ref().get("server").map(Spanned::span).unwrap_or_default();
+    checker.unknown(document.get_ref(), &["server"]);
+    checker.unknown(table, &SERVER_SETTINGS);
+
+    let key: Option<String> = checker.required(table, server_span.clone(), "key");
+    let _: Option<ReplayProtectionConfigFile> = checker.optional(table, "replay_protection");
+    let _: Option<DedupConfigFile> = checker.optional(table, "dedup");
+    checker.convert(table, "spool", |value: SpoolConfigFile| Spool::try_from(&value));
+    checker.convert(table, "dead_letter", |value: DeadLetterConfigFile| DeadLetter::try_from(&value));
+    checker.convert(table, "admin", |value: AdminConfigFile| Admin::try_from(&value));
+    let mut escalations: Vec<EscalationConfigFile> = checker.optional(table, "escalation").unwrap_or_default();
+    let mut interfaces = checker.each(table, "interface", |config: Box<dyn InterfaceConfig>| config.to_interface());
+    let mut endpoints = checker.each(table, "endpoint", |config: Box<dyn EndpointConfig>| config.to_endpoint());
+
+    let mut definitions = Definitions::default();
+    let mut file_problems = definitions.define(None, table);
+    let (included, include_problems) = read_includes(document.get_ref(), path);
+    file_problems.extend(include_problems);
+    for file in included.iter() {
+        let mut file_checker = Checker { toml: file.text(), file: Some(file.path()), problems: Vec::new() };
+        if let Some(document) = file_checker.parse_document()
+            && let Some(table) = server_table(document.get_ref())
+        {
+            file_problems.extend(check_includable(file.path(), table));
+            file_problems.extend(definitions.define(Some(file.path()), table));
+            let more_escalations: Option<Vec<EscalationConfigFile>> = file_checker.optional(table, "escalation");
+            escalations.extend(more_escalations.unwrap_or_default());
+            let more_interfaces =
+                file_checker.each(table, "interface", |config: Box<dyn InterfaceConfig>| config.to_interface());
+            let more_endpoints =
+                file_checker.each(table, "endpoint", |config: Box<dyn EndpointConfig>| config.to_endpoint());
+            append(&mut interfaces, more_interfaces);
+            append(&mut endpoints, more_endpoints);
+        }
+        checker.problems.extend(file_checker.problems);
+    }
+    // Conflicts between files are reported above with the file they are in, the whole configuration would repeat them
+    let composed = file_problems.is_empty();
+    for problem in file_problems {
+        let text = match problem.file() {
+            Some(file) => included.iter().find(|included| included.path() == file).map(Included::text),
+            None => Some(toml),
+        };
+        checker.problems.push(ConfigProblem::new(problem.file(), text.unwrap_or_default(), &problem));
+    }
+
+    if let (Some(key), Some(interfaces), Some(endpoints), true) = (key, interfaces, endpoints, composed) {
+        let span = table.get("endpoint").map(Spanned::span).unwrap_or(server_span);
+        let escalations = escalations.iter().map(Escalation::from).collect();
+        match ServerConfiguration::new(key, interfaces, endpoints) {
+            Ok(config) => {
+                let span = table.get("escalation").map(Spanned::span).unwrap_or(span);
+                if let Err(e) = config.with_escalations(escalations) {
+                    checker.problem(span, e);
+                }
+            }
+            Err(e) => checker.problem(span, e),
+        }
+    }
+    checker.problems
+}
+
+/// Check a YAML or JSON configuration `text` as a whole and return the first problem found.
+fn check_document(text: &str, format: ConfigFormat) -> Vec<ConfigProblem> {
+    let checked = ServerConfigFileParser::load(text, None, format).and_then(|(config, _)| {
+        ServerConfiguration::try_from(config)?;
+        Ok(())
+    });
+    match checked {
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            let (line, column) = syntax_location(text, format).unwrap_or((1, 1));
+            vec![ConfigProblem { file: None, line, column, message: e.to_string() }]
+        }
+    }
+}
+
+fn configuration_routes(
+    text: &str,
+    path: Option<&Path>,
+    format: ConfigFormat,
+) -> Result<Vec<NotificationRoute>, Error> {
+    let (config_file, included) = ServerConfigFileParser::load(text, path, format)?;
+    let config = ServerConfiguration::try_from(config_file)?;
+    let mut names = BTreeSet::new();
+    let documents = std::iter::once((text, format))
+        .chain(included.iter().map(|included| (included.text(), ConfigFormat::Toml)));
+    for (text, format) in documents {
+        let document = parse_value(text, format)?;
+        if let Some(endpoints) = document.get("server").and_then(|server| server.get("endpoint")) {
+            notification_names(endpoints, &mut names);
+        }
+    }
+
+    let mut routes = Vec::new();
+    for (index, endpoint) in config.endpoints().iter().enumerate() {
+        let label = endpoint_label(index, &endpoint.options());
+        for (target, keys) in endpoint.generate_keys(config.key()) {
+            let matched = names.iter().filter(|name| keys.contains(&Key::generate(name, config.key())));
+            routes.extend(matched.map(|name| NotificationRoute {
+                notification: name.clone(),
+                endpoint: label.clone(),
+                target: target.clone(),
+            }));
+        }
+    }
+    routes.sort();
+    Ok(routes)
+}
+
+/// Add `more` entries to `all`, which stays `None` once any of the entries were not valid.
+fn append<T>(all: &mut Option<Vec<T>>, more: Option<Vec<T>>) {
+    match (all.as_mut(), more) {
+        (Some(all), Some(more)) => all.extend(more),
+        _ => *all = None,
+    }
+}
+
+impl ConfigProblem {
+    fn new(file: Option<&Path>, toml: &str, problem: &FileProblem) -> Self {
+        let (line, column) = location(toml, problem.span().start);
+        Self { file: file.map(PathBuf::from), line, column, message: problem.message().into() }
+    }
+
+    /// Return the included file the problem is in, or `None` when it is in the checked configuration itself.
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// Return the line of the configuration file the problem is on, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Return the column of the configuration file the problem starts at, starting at 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Return what is wrong.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = self.file() {
+            write!(f, "{}: ", file.display())?;
+        }
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl NotificationRoute {
+    /// Return the notification name.
+    pub fn notification(&self) -> &str {
+        &self.notification
+    }
+
+    /// Return the endpoint `name`, or `#` and its position in the configuration when it does not have one.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Return the target within the endpoint, like a Matrix room, which is empty for endpoints without targets.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+}
+
+impl<'a> Checker<'a> {
+    /// Record a problem at the start of `span`.
+    fn problem<M: Display>(&mut self, span: Range<usize>, message: M) {
+        let (line, column) = location(self.toml, span.start);
+        let message = message.to_string().trim_end().into();
+        self.problems.push(ConfigProblem { file: self.file.map(PathBuf::from), line, column, message });
+    }
+
+    /// Parse the configuration and resolve its secrets, or return `None` when it is not valid TOML.
+    fn parse_document(&mut self) -> Option<Spanned<DeTable<'a>>> {
+        let (mut document, errors) = DeTable::parse_recoverable(self.toml);
+        let valid = errors.is_empty();
+        for error in errors {
+            self.toml_error(error, 0..0);
+        }
+        if !valid {
+            return None;
+        }
+        for problem in resolve_secrets(document.get_mut()) {
+            self.problem(problem.span(), problem.message());
+        }
+        Some(document)
+    }
+
+    /// Record a TOML error at its own location or at `span` when it does not have one.
+    fn toml_error(&mut self, error: toml::de::Error, span: Range<usize>) {
+        self.problem(error.span().unwrap_or(span), error.message());
+    }
+
+    fn parse<T: DeserializeOwned>(&mut self, value: &Spanned<DeValue>) -> Option<T> {
+        match T::deserialize(ValueDeserializer::from(value.clone())) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.toml_error(e, value.span());
+                None
+            }
+        }
+    }
+
+    /// Report each setting of `table` that is not one of the `known` settings.
+    fn unknown(&mut self, table: &DeTable, known: &[&str]) {
+        let expected: Vec<_> = known.iter().map(|name| format!("`{}`", name)).collect();
+        for (key, _) in table.iter().filter(|(key, _)| !known.contains(&key.get_ref().as_ref())) {
+            let message = format!("unknown field `{}`, expected one of {}", key.get_ref(), expected.join(", "));
+            self.problem(key.span(), message);
+        }
+    }
+
+    fn required<T: DeserializeOwned>(&mut self, table: &DeTable, span: Range<usize>, name: &str) -> Option<T> {
+        match table.get(name) {
+            Some(value) => self.parse(value),
+            None => {
+                self.problem(span, format!("missing field `{}`", name));
+                None
+            }
+        }
+    }
+
+    fn optional<T: DeserializeOwned>(&mut self, table: &DeTable, name: &str) -> Option<T> {
+        table.get(name).and_then(|value| self.parse(value))
+    }
+
+    /// Parse the optional setting `name` and check it with `convert`.
+    fn convert<T, U, F>(&mut self, table: &DeTable, name: &str, convert: F)
+    where
+        T: DeserializeOwned,
+        F: Fn(T) -> Result<U, Error>,
+    {
+        if let Some(value) = table.get(name)
+            && let Err(e) = self.parse(value).map(convert).transpose()
+        {
+            self.problem(value.span(), e);
+        }
+    }
+
+    /// Parse and `convert` each entry of the array `name` and return them when all are valid.
+    fn each<T, U, F>(&mut self, table: &DeTable, name: &str, convert: F) -> Option<Vec<U>>
+    where
+        T: DeserializeOwned,
+        F: Fn(T) -> Result<U, Error>,
+    {
+        let values = match table.get(name) {
+            Some(values) if values.get_ref().is_array() => values,
+            Some(values) => {
+                self.problem(values.span(), format!("`{}` must be an array of tables", name));
+                return None;
+            }
+            None => return Some(Vec::new()),
+        };
+
+        let mut converted = Vec::new();
+        let mut valid = true;
+        for value in values.get_ref().as_array().map(|array| array.iter()).into_iter().flatten() {
+            match self.parse(value).map(&convert) {
+                Some(Ok(entry)) => converted.push(entry),
+                Some(Err(e)) => {
+                    self.problem(value.span(), e);
+                    valid = false;
+                }
+                None => valid = false,
+            }
+        }
+        valid.then_some(converted)
+    }
+}
+
+/// Collect the strings in every `notifications` array found in `value`.
+fn notification_names(value: &Value, names: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter() {
+                match (key.as_str(), value) {
+                    ("notifications", Value::Array(array)) => {
+                        names.extend(array.iter().filter_map(Value::as_str).map(String::from))
+                    }
+                    (_, value) => notification_names(value, names),
+                }
+            }
+        }
+        Value::Array(array) => array.iter().for_each(|value| notification_names(value, names)),
+        _ => (),
+    }
+}
+
 impl ServerConfigFileParser {
-    /// Parse [`ServerConfiguration`] from provided TOML
+    /// Parse [`ServerConfiguration`] from provided TOML, looking up included files from the current directory.
     pub fn from(string: &str) -> Result<ServerConfiguration, Error> {
-        let parsed: ServerConfigFileParser = toml::from_str(string)?;
-        parsed.server.try_into()
+        Self::from_format(string, ConfigFormat::Toml)
+    }
+
+    /// Parse [`ServerConfiguration`] from provided text written in `format`.
+    pub fn from_format(string: &str, format: ConfigFormat) -> Result<ServerConfiguration, Error> {
+        Self::load(string, None, format)?.0.try_into()
+    }
+
+    /// Parse [`ServerConfiguration`] from the file at `path` and the files it includes, in the format picked from
+    /// its extension.
+    pub fn from_path(path: &Path) -> Result<ServerConfiguration, Error> {
+        let format = ConfigFormat::from_path(path);
+        Self::load(std::fs::read_to_string(path)?.as_str(), Some(path), format)?.0.try_into()
+    }
+
+    /// Parse the configuration in `text` read from `path` with the files it includes and return the included files.
+    fn load(text: &str, path: Option<&Path>, format: ConfigFormat) -> Result<(ServerConfigFile, Vec<Included>), Error> {
+        if format != ConfigFormat::Toml {
+            let value = parse_value(text, format)?;
+            if value.get("server").and_then(|server| server.get(INCLUDE)).is_some() {
+                return Err(Error::invalid_include("`include` is only supported in TOML configuration files"));
+            }
+            let parsed: ServerConfigFileParser = serde_json::from_value(value)?;
+            return Ok((parsed.server, Vec::new()));
+        }
+
+        let mut document = parse_document(text)?;
+        let (included, mut problems) = read_includes(document.get_ref(), path);
+        let mut definitions = Definitions::default();
+        if let Some(table) = server_table(document.get_ref()) {
+            problems.extend(definitions.define(None, table));
+        }
+        remove_include(document.get_mut());
+        let mut parsed: ServerConfigFileParser = deserialize(document, text)?;
+
+        for file in included.iter() {
+            let in_file = |e: Error| Error::invalid_include(format!("{}: {}", file_name(Some(file.path())), e));
+            let mut document = parse_document(file.text()).map_err(in_file)?;
+            if let Some(table) = server_table(document.get_ref()) {
+                problems.extend(check_includable(file.path(), table));
+                problems.extend(definitions.define(Some(file.path()), table));
+            }
+            remove_include(document.get_mut());
+            let entries: IncludedConfigFileParser = deserialize(document, file.text()).map_err(in_file)?;
+            parsed.server.append(entries.server);
+        }
+
+        match problems.is_empty() {
+            true => Ok((parsed.server, included)),
+            false => {
+                let problems: Vec<_> = problems.iter().map(|problem| problem.describe(text, &included)).collect();
+                Err(Error::invalid_include(problems.join(", ")))
+            }
+        }
     }
 }
 
@@ -34,6 +556,19 @@ impl TryFrom<ServerConfigFile> for ServerConfiguration {
         let interfaces: Vec<Box<dyn Interface + Send>> = collect_interfaces(value.interface)?;
         let endpoints: Vec<Box<dyn Endpoint + Send>> = collect_endpoints(value.endpoint)?;
 
-        ServerConfiguration::new(value.key.as_str(), interfaces, endpoints)
+        let replay_protection = value.replay_protection.as_ref().map(ReplayProtection::from).unwrap_or_default();
+        let dedup = value.dedup.as_ref().map(Dedup::from);
+        let spool = value.spool.as_ref().map(Spool::try_from).transpose()?;
+        let dead_letter = value.dead_letter.as_ref().map(DeadLetter::try_from).transpose()?;
+        let escalations = value.escalation.iter().map(Escalation::from).collect();
+        let admin = value.admin.as_ref().map(Admin::try_from).transpose()?;
+
+        ServerConfiguration::new(value.key.as_str(), interfaces, endpoints)?
+            .with_replay_protection(replay_protection)
+            .with_dedup(dedup)
+            .with_spool(spool)
+            .with_dead_letter(dead_letter)
+            .with_admin(admin)
+            .with_escalations(escalations)
     }
 }
@@ -1,5 +1,12 @@
 //! Endpoints for the server
 
+use crate::endpoints::batch::Batch;
+use crate::endpoints::dedup::Dedup;
+use crate::endpoints::filter::Filter;
+use crate::endpoints::queue::Queue;
+use crate::endpoints::rate_limit::RateLimit;
+use crate::endpoints::retry::Retry;
+use crate::endpoints::schedule::Schedule;
 use crate::notifications::{Key, ValidatedNotification};
 use crate::Error;
 use async_trait::async_trait;
@@ -7,19 +14,30 @@ use dyn_clone::DynClone;
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::time::Instant;
 use tokio::sync::{broadcast, watch};
+#[cfg(any(feature = "discord", feature = "email", feature = "file", feature = "matrix"))]
+use tracing::warn;
 
+pub mod batch;
+pub mod dedup;
+pub mod delivery;
 #[cfg(feature = "discord")]
 pub mod discord;
 #[cfg(feature = "email")]
 pub mod email;
 #[cfg(feature = "file")]
 pub mod file;
+pub mod filter;
 #[cfg(feature = "matrix")]
 pub mod matrix;
+pub mod queue;
+pub mod rate_limit;
+pub mod retry;
+pub mod schedule;
 
 /// A data structure that can be deserialized and converted into an [`Endpoint`].
-#[typetag::deserialize(tag = "type")]
+#[typetag::serde(tag = "type")]
 pub trait EndpointConfig: Debug {
     /// Convert this `EndpointConfig` into an [`Endpoint`].
     fn to_endpoint(&self) -> Result<Box<dyn Endpoint + Send>, Error>;
@@ -29,6 +47,9 @@ pub trait EndpointConfig: Debug {
 #[async_trait]
 pub trait Endpoint: DynClone + Send + Debug {
     /// Implements the server sending notifications to the `Endpoint`.
+    ///
+    /// The server waits for room in `endpoint_rx` according to the [`Queue`] settings, so the `Endpoint` should
+    /// receive from `endpoint_rx` itself rather than keep it unused next to a resubscribed receiver.
     async fn notify(
         &self,
         endpoint_rx: broadcast::Receiver<ValidatedNotification>,
@@ -44,10 +65,162 @@ pub trait Endpoint: DynClone + Send + Debug {
 
     /// Get [`Endpoint`] as [`Any`]
     fn as_any(&self) -> &dyn Any;
+
+    /// Return if this `Endpoint` reports the result of delivering each notification back to the server.
+    ///
+    /// An `Endpoint` that returns `true` must call [`ValidatedNotification::report_delivery`] once for every
+    /// notification it receives, see [`delivery`]. Only notifications for endpoints that report delivery are kept
+    /// in the spool until they are delivered, escalated or recorded as dead letters.
+    fn reports_delivery(&self) -> bool {
+        false
+    }
+
+    /// Return the [`EndpointOptions`] the server applies before passing notifications to this `Endpoint`.
+    fn options(&self) -> EndpointOptions {
+        EndpointOptions::default()
+    }
 }
 
 dyn_clone::clone_trait_object!(Endpoint);
 
+/// Options the server applies to notifications before they are passed to an [`Endpoint`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EndpointOptions {
+    name: Option<String>,
+    dedup: Option<Dedup>,
+    rate_limit: Option<RateLimit>,
+    batch: Option<Batch>,
+    schedule: Option<Schedule>,
+    retry: Option<Retry>,
+    queue: Option<Queue>,
+    filter: Option<Filter>,
+}
+
+impl EndpointOptions {
+    /// Set the name used to refer to the endpoint, like the fallback of a [`Schedule`], and in logs.
+    pub fn with_name<S: AsRef<str>>(mut self, name: Option<S>) -> Self {
+        self.name = name.map(|name| name.as_ref().into());
+        self
+    }
+
+    /// Set the [`Dedup`] settings, `None` uses the server default.
+    pub fn with_dedup(mut self, dedup: Option<Dedup>) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Set the [`RateLimit`] settings, `None` does not limit the endpoint.
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Set the [`Batch`] settings, `None` sends each notification on its own.
+    pub fn with_batch(mut self, batch: Option<Batch>) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Set the [`Schedule`], `None` keeps the endpoint always active.
+    pub fn with_schedule(mut self, schedule: Option<Schedule>) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Set the [`Retry`] settings, `None` uses the default.
+    pub fn with_retry(mut self, retry: Option<Retry>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the [`Queue`] settings, `None` uses the default.
+    pub fn with_queue(mut self, queue: Option<Queue>) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Set the [`Filter`], `None` routes every notification with one of the notification names to the endpoint.
+    pub fn with_filter(mut self, filter: Option<Filter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Return the name of this endpoint.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Return the [`Dedup`] settings for this endpoint.
+    pub fn dedup(&self) -> Option<Dedup> {
+        self.dedup
+    }
+
+    /// Return the [`RateLimit`] settings for this endpoint.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+
+    /// Return the [`Batch`] settings for this endpoint.
+    pub fn batch(&self) -> Option<Batch> {
+        self.batch
+    }
+
+    /// Return the [`Schedule`] for this endpoint.
+    pub fn schedule(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+
+    /// Return the [`Retry`] settings for this endpoint.
+    pub fn retry(&self) -> Option<Retry> {
+        self.retry
+    }
+
+    /// Return the [`Queue`] settings for this endpoint.
+    pub fn queue(&self) -> Option<&Queue> {
+        self.queue.as_ref()
+    }
+
+    /// Return the [`Filter`] for this endpoint.
+    pub fn filter(&self) -> Option<&Filter> {
+        self.filter.as_ref()
+    }
+}
+
+/// A step the server passes notifications through before they reach an [`Endpoint`].
+pub(crate) trait Stage: Send + Debug {
+    /// Short name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Pass a notification into the stage and return the notifications that are ready to continue.
+    fn push(&mut self, notification: ValidatedNotification, now: Instant) -> Vec<ValidatedNotification>;
+
+    /// Return notifications held by the stage that are ready to continue.
+    fn poll(&mut self, now: Instant) -> Vec<ValidatedNotification>;
+
+    /// Return when [`Stage::poll`] should next be called.
+    fn next_deadline(&self) -> Option<Instant>;
+
+    /// Return the total number of notifications dropped by the stage.
+    fn dropped(&self) -> u64 {
+        0
+    }
+
+    /// Return the number of notifications held by the stage.
+    fn pending(&self) -> usize {
+        0
+    }
+
+    /// Take the notifications the stage has sent to a fallback endpoint instead.
+    fn take_rerouted(&mut self) -> Vec<ValidatedNotification> {
+        Vec::new()
+    }
+
+    /// Take every notification held by the stage, used to pass them on when its endpoint is reloaded.
+    fn take_pending(&mut self) -> Vec<ValidatedNotification> {
+        Vec::new()
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct EndpointChannel {
     endpoint: Box<dyn Endpoint + Send>,
@@ -64,6 +237,7 @@ impl EndpointChannel {
         EndpointChannel { endpoint, channel, keys }
     }
 
+    #[allow(clippy::borrowed_box)]
     pub fn endpoint(&self) -> &Box<dyn Endpoint + Send> {
         &self.endpoint
     }
@@ -81,12 +255,39 @@ impl EndpointChannel {
     }
 }
 
-pub(crate) async fn setup_endpoints(
-    endpoints: Vec<EndpointChannel>,
-    shutdown: watch::Receiver<bool>,
-) -> Result<(), Error> {
-    for channel in endpoints {
-        channel.endpoint().notify(channel.channel_receiver(), shutdown.clone()).await?
+/// Return the notification from a receive on the endpoint channel and log the notifications an endpoint lost
+/// because it fell too far behind.
+#[cfg(any(feature = "discord", feature = "email", feature = "file", feature = "matrix"))]
+pub(crate) fn check_received(
+    endpoint: &str,
+    received: Result<ValidatedNotification, broadcast::error::RecvError>,
+) -> Option<ValidatedNotification> {
+    match received {
+        Ok(notification) => Some(notification),
+        Err(broadcast::error::RecvError::Lagged(missed)) => {
+            warn!("{} endpoint fell behind and lost {} notification(s)", endpoint, missed);
+            None
+        }
+        Err(broadcast::error::RecvError::Closed) => None,
     }
-    Ok(())
+}
+
+/// Render the metadata and tags of a message as `key: value` entries joined by `separator`.
+///
+/// Returns `None` when the message has neither metadata nor tags.
+#[cfg(any(feature = "discord", feature = "email", feature = "file", feature = "matrix"))]
+pub(crate) fn format_details(message: &crate::notifications::Message, separator: &str) -> Option<String> {
+    let mut details: Vec<String> = message.metadata().iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+    if !message.tags().is_empty() {
+        details.push(format!("tags: {}", message.tags().join(", ")));
+    }
+
+    match details.is_empty() {
+        true => None,
+        false => Some(details.join(separator)),
+    }
+}
+
+pub(crate) async fn setup_endpoint(channel: &EndpointChannel, shutdown: watch::Receiver<bool>) -> Result<(), Error> {
+    channel.endpoint().notify(channel.channel_receiver(), shutdown).await
 }
@@ -0,0 +1,278 @@
+//! Batching of notifications passed to an endpoint
+//!
+//! When batching is enabled for an endpoint notifications are held and combined into a single message when the
+//! first held notification is `interval_secs` old or `max_count` notifications are held, whichever comes first.
+//! Notifications are batched separately for each sub-group of an endpoint, like a matrix room.
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.endpoint]]
+//! type = "email"
+//! hostname = "smtp.example.com"
+//! port = 587
+//! username = "test_user"
+//! password = "test_password"
+//! implicit_tls = false
+//! allow_invalid_certs = false
+//! from = "asdf@example.com"
+//! to = ["qwerty@example.com"]
+//! subject = "test_subject"
+//! notifications = ["notification_id1"]
+//!
+//! [server.endpoint.batch]
+//! interval_secs = 3600
+//! max_count = 50
+//! ```
+
+use crate::endpoints::Stage;
+use crate::notifications::{Message, ValidatedNotification};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Settings for combining notifications into a single message.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Batch {
+    interval: Option<Duration>,
+    max_count: Option<usize>,
+}
+
+/// Data structure to represent [`Batch`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BatchConfigFile {
+    interval_secs: Option<u64>,
+    max_count: Option<usize>,
+}
+
+/// Holds notifications for each sub-group until a [`Batch`] is complete.
+#[derive(Debug)]
+pub(crate) struct Batcher {
+    batch: Batch,
+    groups: BTreeMap<String, BatchGroup>,
+}
+
+#[derive(Debug)]
+struct BatchGroup {
+    started: Instant,
+    notifications: Vec<ValidatedNotification>,
+}
+
+impl Batch {
+    /// Create a new `Batch` that is sent after `interval` or once `max_count` notifications are held.
+    ///
+    /// At least one of them must be greater than 0, otherwise notifications would be held forever.
+    pub fn new(interval: Option<Duration>, max_count: Option<usize>) -> Result<Self, Error> {
+        let interval = interval.filter(|interval| !interval.is_zero());
+        let max_count = max_count.filter(|count| *count > 0);
+        if interval.is_none() && max_count.is_none() {
+            return Err(Error::invalid_endpoint_configuration(
+                "batch requires interval_secs or max_count greater than 0".to_string(),
+            ));
+        }
+
+        Ok(Self { interval, max_count })
+    }
+
+    /// Return the longest time a notification is held.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Return the number of notifications that completes a batch.
+    pub fn max_count(&self) -> Option<usize> {
+        self.max_count
+    }
+}
+
+impl TryFrom<&BatchConfigFile> for Batch {
+    type Error = Error;
+
+    fn try_from(value: &BatchConfigFile) -> Result<Self, Self::Error> {
+        Batch::new(value.interval_secs.map(Duration::from_secs), value.max_count)
+    }
+}
+
+impl Batcher {
+    pub fn new(batch: Batch) -> Self {
+        Self { batch, groups: BTreeMap::new() }
+    }
+}
+
+impl Stage for Batcher {
+    fn name(&self) -> &'static str {
+        "batch"
+    }
+
+    fn push(&mut self, notification: ValidatedNotification, now: Instant) -> Vec<ValidatedNotification> {
+        let sub_name = notification.sub_name().to_string();
+        let group = self
+            .groups
+            .entry(sub_name.clone())
+            .or_insert_with(|| BatchGroup { started: now, notifications: Vec::new() });
+        group.notifications.push(notification);
+
+        match self.batch.max_count {
+            Some(max_count) if group.notifications.len() >= max_count => self
+                .groups
+                .remove(&sub_name)
+                .map(|group| combine(sub_name.as_str(), group.notifications, "notifications"))
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<ValidatedNotification> {
+        let interval = match self.batch.interval {
+            None => return Vec::new(),
+            Some(interval) => interval,
+        };
+
+        let due: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|(_, group)| group.started + interval <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|sub_name| {
+                self.groups
+                    .remove(&sub_name)
+                    .map(|group| combine(sub_name.as_str(), group.notifications, "notifications"))
+            })
+            .collect()
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let interval = self.batch.interval?;
+        self.groups.values().map(|group| group.started + interval).min()
+    }
+
+    fn pending(&self) -> usize {
+        self.groups.values().map(|group| group.notifications.len()).sum()
+    }
+
+    fn take_pending(&mut self) -> Vec<ValidatedNotification> {
+        std::mem::take(&mut self.groups).into_values().flat_map(|group| group.notifications).collect()
+    }
+}
+
+/// Combine notifications for the same sub-group into a single notification.
+///
+/// The combined message has the highest priority of the notifications and lists each message on its own line
+/// below a heading of the count followed by `description`. It keeps the tags and metadata of every notification,
+/// a metadata key with different values gets the distinct values separated by commas.
+pub(crate) fn combine(
+    sub_name: &str,
+    notifications: Vec<ValidatedNotification>,
+    description: &str,
+) -> ValidatedNotification {
+    let priority = notifications.iter().map(|n| n.message().priority()).max().unwrap_or_default();
+    let lines: Vec<_> = notifications
+        .iter()
+        .map(|n| match n.message().title() {
+            None => format!("- {}", n.message().text()),
+            Some(title) => format!("- {}: {}", title, n.message().text()),
+        })
+        .collect();
+    let text = format!("{} {}:\n{}", notifications.len(), description, lines.join("\n"));
+
+    let mut message = Message::new(text).with_priority(priority);
+    let mut metadata: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for notification in notifications.iter() {
+        for (key, value) in notification.message().metadata() {
+            let values = metadata.entry(key.as_str()).or_default();
+            if !values.contains(&value.as_str()) {
+                values.push(value.as_str());
+            }
+        }
+        for tag in notification.message().tags() {
+            if !message.tags().contains(tag) {
+                message = message.with_tag(tag);
+            }
+        }
+    }
+    for (key, values) in metadata {
+        message = message.with_metadata(key, values.join(", "));
+    }
+    ValidatedNotification::new(sub_name, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::Priority;
+
+    fn notification(sub_name: &str, text: &str) -> ValidatedNotification {
+        ValidatedNotification::new(sub_name, Message::new(text))
+    }
+
+    fn texts(notifications: &[ValidatedNotification]) -> Vec<&str> {
+        notifications.iter().map(|n| n.message().text()).collect()
+    }
+
+    #[test]
+    fn batch_requires_an_interval_or_max_count() {
+        assert!(Batch::new(None, None).is_err());
+        assert!(Batch::new(Some(Duration::ZERO), Some(0)).is_err());
+        assert_eq!(Batch::new(Some(Duration::ZERO), Some(5)).unwrap().interval(), None);
+    }
+
+    #[test]
+    fn max_count_completes_the_batch_of_a_sub_group() {
+        let now = Instant::now();
+        let mut batcher = Batcher::new(Batch::new(None, Some(2)).unwrap());
+
+        assert!(batcher.push(notification("room1", "one"), now).is_empty());
+        assert!(batcher.push(notification("room2", "two"), now).is_empty());
+        let ready = batcher.push(notification("room1", "three"), now);
+
+        assert_eq!(texts(&ready), vec!["2 notifications:\n- one\n- three"]);
+        assert_eq!(ready[0].sub_name(), "room1");
+        assert_eq!(batcher.pending(), 1);
+        assert_eq!(batcher.next_deadline(), None);
+    }
+
+    #[test]
+    fn interval_sends_the_batch_when_the_first_notification_is_due() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        let mut batcher = Batcher::new(Batch::new(Some(interval), None).unwrap());
+
+        batcher.push(notification("room1", "one"), now);
+        batcher.push(notification("room1", "two"), now + Duration::from_secs(30));
+        assert_eq!(batcher.next_deadline(), Some(now + interval));
+        assert!(batcher.poll(now + Duration::from_secs(59)).is_empty());
+
+        let ready = batcher.poll(now + interval);
+        assert_eq!(texts(&ready), vec!["2 notifications:\n- one\n- two"]);
+        assert_eq!(batcher.pending(), 0);
+        assert_eq!(batcher.next_deadline(), None);
+    }
+
+    #[test]
+    fn combined_message_keeps_priority_tags_and_metadata() {
+        let first = Message::new("disk full").with_title("web1").with_tag("disk").with_metadata("host", "web1");
+        let second = Message::new("disk full")
+            .with_priority(Priority::High)
+            .with_tag("disk")
+            .with_tag("urgent")
+            .with_metadata("host", "web2")
+            .with_metadata("service", "nginx");
+        let third = Message::new("load").with_metadata("host", "web1");
+        let notifications = [first, second, third].map(|message| ValidatedNotification::new("ops", message));
+
+        let combined = combine("ops", notifications.into(), "notifications");
+        let message = combined.message();
+        assert_eq!(message.text(), "3 notifications:\n- web1: disk full\n- disk full\n- load");
+        assert_eq!(message.priority(), Priority::High);
+        assert_eq!(message.tags(), ["disk", "urgent"]);
+        assert_eq!(
+            message.metadata(),
+            &BTreeMap::from([("host".to_string(), "web1, web2".to_string()), ("service".into(), "nginx".into())])
+        );
+    }
+}
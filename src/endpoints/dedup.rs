@@ -0,0 +1,214 @@
+//! Suppression of repeated notifications
+//!
+//! Identical notifications, the same notification name and text, that are received within the window are only
+//! passed to the endpoint once. When `summary` is enabled a message with the number of suppressed repeats is sent
+//! at the end of the window.
+//!
+//! # Configuration Example
+//! Dedup can be set for all endpoints on the server and overridden for an endpoint.
+//! A `window_secs` of 0 disables dedup for an endpoint.
+//! ```toml
+//! [server.dedup]
+//! window_secs = 60
+//! summary = true
+//!
+//! [[server.endpoint]]
+//! type = "file"
+//! path = 'path/to/file_endpoint.txt'
+//! notifications = ["notification_id1"]
+//!
+//! [server.endpoint.dedup]
+//! window_secs = 0
+//! ```
+
+use crate::notifications::ValidatedNotification;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Settings for suppressing identical notifications within a time window.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Dedup {
+    window: Duration,
+    summary: bool,
+}
+
+/// Data structure to represent [`Dedup`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DedupConfigFile {
+    window_secs: u64,
+    #[serde(default)]
+    summary: bool,
+}
+
+/// Tracks notifications seen within the [`Dedup`] window for a single endpoint.
+#[derive(Debug)]
+pub(crate) struct Deduplicator {
+    dedup: Dedup,
+    entries: HashMap<(String, String, String), DedupEntry>,
+}
+
+#[derive(Debug)]
+struct DedupEntry {
+    expires: Instant,
+    repeats: u64,
+    notification: ValidatedNotification,
+}
+
+impl Dedup {
+    /// Create a new `Dedup`.
+    ///
+    /// A `window` of zero disables dedup.
+    pub fn new(window: Duration, summary: bool) -> Self {
+        Self { window, summary }
+    }
+
+    /// Return the time window in which repeats are suppressed.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Return if a summary is sent at the end of the window when repeats were suppressed.
+    pub fn summary(&self) -> bool {
+        self.summary
+    }
+
+    /// Return if dedup is enabled.
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+}
+
+impl From<&DedupConfigFile> for Dedup {
+    fn from(value: &DedupConfigFile) -> Self {
+        Dedup::new(Duration::from_secs(value.window_secs), value.summary)
+    }
+}
+
+impl Deduplicator {
+    pub fn new(dedup: Dedup) -> Self {
+        Self { dedup, entries: HashMap::new() }
+    }
+
+    /// Pass a notification for the notification name identified by `key_id` through the dedup window.
+    ///
+    /// Returns the notifications that should be sent on to the endpoint.
+    pub fn push(&mut self, key_id: &str, notification: ValidatedNotification, now: Instant) -> Vec<ValidatedNotification> {
+        let id = (key_id.to_string(), notification.sub_name().to_string(), notification.message().text().to_string());
+        let mut ready = Vec::new();
+
+        match self.entries.get_mut(&id) {
+            Some(entry) if entry.expires > now => {
+                entry.repeats += 1;
+                entry.notification = notification;
+                return ready;
+            }
+            Some(_) => {
+                if let Some(summary) = self.entries.remove(&id).and_then(|entry| self.summarize(entry)) {
+                    ready.push(summary);
+                }
+            }
+            None => (),
+        }
+
+        let entry = DedupEntry { expires: now + self.dedup.window(), repeats: 0, notification: notification.clone() };
+        self.entries.insert(id, entry);
+        ready.push(notification);
+        ready
+    }
+
+    /// Remove expired entries and return summaries for those that had repeats.
+    pub fn poll(&mut self, now: Instant) -> Vec<ValidatedNotification> {
+        let expired: Vec<_> = self.entries.iter().filter(|(_, entry)| entry.expires <= now).map(|(id, _)| id.clone()).collect();
+        let removed: Vec<_> = expired.into_iter().filter_map(|id| self.entries.remove(&id)).collect();
+        removed.into_iter().filter_map(|entry| self.summarize(entry)).collect()
+    }
+
+    /// Return when the next entry expires.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.entries.values().map(|entry| entry.expires).min()
+    }
+
+    fn summarize(&self, entry: DedupEntry) -> Option<ValidatedNotification> {
+        if !self.dedup.summary() || entry.repeats == 0 {
+            return None;
+        }
+
+        let message = entry.notification.message();
+        let text = format!("{} [repeated {} times in {}s]", message.text(), entry.repeats, self.dedup.window().as_secs());
+        Some(ValidatedNotification::new(entry.notification.sub_name(), message.with_text(text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::Message;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn notification(sub_name: &str, text: &str) -> ValidatedNotification {
+        ValidatedNotification::new(sub_name, Message::new(text).with_title("cron"))
+    }
+
+    fn texts(notifications: &[ValidatedNotification]) -> Vec<&str> {
+        notifications.iter().map(|n| n.message().text()).collect()
+    }
+
+    #[test]
+    fn repeats_within_the_window_are_suppressed() {
+        let now = Instant::now();
+        let mut dedup = Deduplicator::new(Dedup::new(WINDOW, false));
+
+        assert_eq!(texts(&dedup.push("key1", notification("room1", "failed"), now)), vec!["failed"]);
+        assert!(dedup.push("key1", notification("room1", "failed"), now + Duration::from_secs(59)).is_empty());
+
+        // The notification name, sub-group and text identify a repeat
+        assert_eq!(dedup.push("key2", notification("room1", "failed"), now).len(), 1);
+        assert_eq!(dedup.push("key1", notification("room2", "failed"), now).len(), 1);
+        assert_eq!(dedup.push("key1", notification("room1", "succeeded"), now).len(), 1);
+        assert_eq!(dedup.next_deadline(), Some(now + WINDOW));
+    }
+
+    #[test]
+    fn summary_is_sent_at_the_end_of_the_window() {
+        let now = Instant::now();
+        let mut dedup = Deduplicator::new(Dedup::new(WINDOW, true));
+        dedup.push("key1", notification("room1", "failed"), now);
+        dedup.push("key1", notification("room1", "failed"), now + Duration::from_secs(10));
+        dedup.push("key1", notification("room1", "failed"), now + Duration::from_secs(20));
+        dedup.push("key1", notification("room1", "once"), now);
+
+        assert!(dedup.poll(now + Duration::from_secs(59)).is_empty());
+        let summaries = dedup.poll(now + WINDOW);
+        assert_eq!(texts(&summaries), vec!["failed [repeated 2 times in 60s]"]);
+        assert_eq!(summaries[0].sub_name(), "room1");
+        assert_eq!(summaries[0].message().title(), Some("cron"));
+        assert_eq!(dedup.next_deadline(), None);
+    }
+
+    #[test]
+    fn repeat_after_the_window_is_sent_after_the_summary() {
+        let now = Instant::now();
+        let mut dedup = Deduplicator::new(Dedup::new(WINDOW, true));
+        dedup.push("key1", notification("room1", "failed"), now);
+        dedup.push("key1", notification("room1", "failed"), now + Duration::from_secs(30));
+
+        let ready = dedup.push("key1", notification("room1", "failed"), now + WINDOW);
+        assert_eq!(texts(&ready), vec!["failed [repeated 1 times in 60s]", "failed"]);
+        assert_eq!(dedup.next_deadline(), Some(now + WINDOW + WINDOW));
+    }
+
+    #[test]
+    fn no_summary_without_repeats_or_when_disabled() {
+        let now = Instant::now();
+        let mut dedup = Deduplicator::new(Dedup::new(WINDOW, false));
+        dedup.push("key1", notification("room1", "failed"), now);
+        dedup.push("key1", notification("room1", "failed"), now);
+
+        assert!(dedup.poll(now + WINDOW).is_empty());
+        assert!(!Dedup::new(Duration::ZERO, true).is_enabled());
+    }
+}
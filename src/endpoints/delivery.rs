@@ -0,0 +1,144 @@
+//! Reporting the outcome of delivering a notification back to the server
+//!
+//! An [`Endpoint`][crate::endpoints::Endpoint] that returns `true` from
+//! [`reports_delivery`][crate::endpoints::Endpoint::reports_delivery] calls
+//! [`ValidatedNotification::report_delivery`] once for every notification it receives, after its last attempt,
+//! with `Ok(())` when the notification was delivered or a [`DeliveryError`] when it was not.
+//! The server uses the results to keep notifications in the spool until they are delivered, to escalate
+//! notifications to the next endpoint in a chain and to record dead letters.
+//!
+//! A [`DeliveryError`] is either transient, when a later attempt may succeed, or permanent.
+
+use crate::notifications::ValidatedNotification;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Reason a notification was not delivered.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeliveryError {
+    reason: String,
+    retry_after: Option<Duration>,
+    permanent: bool,
+}
+
+/// Handle attached to a [`ValidatedNotification`][crate::notifications::ValidatedNotification] that an endpoint
+/// uses to report whether it was delivered.
+#[derive(Debug, Clone)]
+pub(crate) struct Receipt {
+    endpoint: String,
+    id: u64,
+    sent: Instant,
+    outcomes: mpsc::UnboundedSender<DeliveryOutcome>,
+}
+
+/// Result of delivering the notification with a [`Receipt`] to an endpoint.
+#[derive(Debug, Clone)]
+pub(crate) struct DeliveryOutcome {
+    endpoint: String,
+    id: u64,
+    notification: ValidatedNotification,
+    result: Result<(), DeliveryError>,
+    latency: Duration,
+}
+
+impl Receipt {
+    pub fn new<S: AsRef<str>>(endpoint: S, id: u64, outcomes: mpsc::UnboundedSender<DeliveryOutcome>) -> Self {
+        Self { endpoint: endpoint.as_ref().into(), id, sent: Instant::now(), outcomes }
+    }
+
+    /// Send the result of delivering the `notification` to the server.
+    pub fn report(&self, notification: ValidatedNotification, result: Result<(), DeliveryError>) {
+        let outcome = DeliveryOutcome {
+            endpoint: self.endpoint.clone(),
+            id: self.id,
+            notification,
+            result,
+            latency: self.sent.elapsed(),
+        };
+        let _ = self.outcomes.send(outcome);
+    }
+}
+
+impl PartialEq for Receipt {
+    fn eq(&self, other: &Self) -> bool {
+        self.endpoint == other.endpoint && self.id == other.id
+    }
+}
+
+impl Eq for Receipt {}
+
+impl Hash for Receipt {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.endpoint.hash(state);
+        self.id.hash(state);
+    }
+}
+
+impl DeliveryOutcome {
+    /// Label of the endpoint that made the delivery.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Identifier of the delivered notification.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The notification as it was delivered.
+    pub fn notification(&self) -> &ValidatedNotification {
+        &self.notification
+    }
+
+    pub fn result(&self) -> &Result<(), DeliveryError> {
+        &self.result
+    }
+
+    /// Time from attaching the [`Receipt`] to the notification until the result was reported.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+}
+
+impl DeliveryError {
+    /// A failure that may succeed on a later attempt.
+    pub fn transient<S: AsRef<str>>(reason: S) -> Self {
+        Self { reason: reason.as_ref().into(), retry_after: None, permanent: false }
+    }
+
+    /// A failure that will not succeed on a later attempt.
+    pub fn permanent<S: AsRef<str>>(reason: S) -> Self {
+        Self { reason: reason.as_ref().into(), retry_after: None, permanent: true }
+    }
+
+    /// Wait for `retry_after` before the next attempt instead of the backoff.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Return the reason for the failure.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Return the wait requested before the next attempt.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Return if the failure will not succeed on a later attempt.
+    pub fn is_permanent(&self) -> bool {
+        self.permanent
+    }
+}
+
+impl Display for DeliveryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for DeliveryError {}
@@ -9,6 +9,7 @@
 //! username = "Bot Name"
 //! avatar_url = "https://example.com/avatar/url"
 //! tts = true
+//! critical_mention = "@everyone"
 //! notifications = ["notification_id1", "notification_id3"]
 //!
 //! [server.endpoint.allowed_mentions]
@@ -17,32 +18,59 @@
 //! users = ["user1"]
 //! replied_user = false
 //! ```
+//!
+//! Message titles are shown in bold above the message text and metadata and tags are shown as small text below it.
+//! Messages with critical priority are always sent with text-to-speech enabled and are prefixed with
+//! `critical_mention` when it is set. The mention must also be permitted by `allowed_mentions` to notify anyone.
 
 pub(crate) mod webhook;
 
-use crate::endpoints::discord::webhook::{AllowedMentions, AllowedMentionsConfigFile, WebhookPayload};
-use crate::endpoints::{Endpoint, EndpointConfig};
+use crate::endpoints::discord::webhook::{
+    AllowedMentions, AllowedMentionsConfigFile, RateLimitResponse, WebhookPayload,
+};
+use crate::endpoints::batch::{Batch, BatchConfigFile};
+use crate::endpoints::dedup::{Dedup, DedupConfigFile};
+use crate::endpoints::filter::{Filter, FilterConfigFile};
+use crate::endpoints::queue::{Queue, QueueConfigFile};
+use crate::endpoints::rate_limit::{RateLimit, RateLimitConfigFile};
+use crate::endpoints::delivery::DeliveryError;
+use crate::endpoints::retry::{deliver, Retry, RetryConfigFile};
+use crate::endpoints::schedule::{Schedule, ScheduleConfigFile};
+use crate::endpoints::{check_received, Endpoint, EndpointConfig, EndpointOptions};
 use crate::notifications::{Key, ValidatedNotification};
 use crate::Error;
 use async_trait::async_trait;
-use tracing::{debug, info, warn};
-use reqwest::Client;
-use serde::Deserialize;
+use tracing::{debug, info};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use tokio::sync::broadcast::Receiver;
 use tokio::sync::watch;
 
 /// Data structure to represent the Discord webhook [`EndpointConfig`].
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(crate) struct DiscordConfigFile {
     url: String,
     username: Option<String>,
     avatar_url: Option<String>,
     #[serde(default)]
     tts: bool,
+    critical_mention: Option<String>,
     notifications: Vec<String>,
     allowed_mentions: Option<AllowedMentionsConfigFile>,
+    name: Option<String>,
+    dedup: Option<DedupConfigFile>,
+    rate_limit: Option<RateLimitConfigFile>,
+    batch: Option<BatchConfigFile>,
+    schedule: Option<ScheduleConfigFile>,
+    queue: Option<QueueConfigFile>,
+    filter: Option<FilterConfigFile>,
+    retry: Option<RetryConfigFile>,
 }
 
 /// Data structure to represent the Discord webhook [`Endpoint`].
@@ -52,11 +80,13 @@ pub struct DiscordEndpoint {
     username: Option<String>,
     avatar_url: Option<String>,
     tts: bool,
+    critical_mention: Option<String>,
     notifications: Vec<String>,
     allowed_mentions: AllowedMentions,
+    options: EndpointOptions,
 }
 
-#[typetag::deserialize(name = "discord")]
+#[typetag::serde(name = "discord")]
 impl EndpointConfig for DiscordConfigFile {
     fn to_endpoint(&self) -> Result<Box<dyn Endpoint + Send>, Error> {
         Ok(Box::new(DiscordEndpoint::try_from(self)?))
@@ -91,6 +121,14 @@ impl Endpoint for DiscordEndpoint {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reports_delivery(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> EndpointOptions {
+        self.options.clone()
+    }
 }
 
 impl TryFrom<&DiscordConfigFile> for DiscordEndpoint {
@@ -111,8 +149,18 @@ impl TryFrom<&DiscordConfigFile> for DiscordEndpoint {
             username: value.username.clone(),
             avatar_url: value.avatar_url.clone(),
             tts: value.tts,
+            critical_mention: value.critical_mention.clone(),
             allowed_mentions,
             notifications: value.notifications.clone(),
+            options: EndpointOptions::default()
+                .with_name(value.name.as_ref())
+                .with_dedup(value.dedup.as_ref().map(Dedup::from))
+                .with_rate_limit(value.rate_limit.as_ref().map(RateLimit::try_from).transpose()?)
+                .with_batch(value.batch.as_ref().map(Batch::try_from).transpose()?)
+                .with_schedule(value.schedule.as_ref().map(Schedule::try_from).transpose()?)
+                .with_retry(value.retry.as_ref().map(Retry::try_from).transpose()?)
+                .with_queue(value.queue.as_ref().map(Queue::try_from).transpose()?)
+                .with_filter(value.filter.as_ref().map(Filter::try_from).transpose()?),
         })
     }
 }
@@ -122,25 +170,23 @@ async fn send_messages(
     shutdown: watch::Receiver<bool>,
     discord: DiscordEndpoint,
 ) {
-    let mut rx = endpoint_rx.resubscribe();
+    let mut rx = endpoint_rx;
     let mut shutdown_rx = shutdown.clone();
     let client = Client::new();
 
     loop {
         tokio::select! {
             received = rx.recv() => {
-                if let Ok(message) = received {
-                    let content = message.message().text();
-                    let payload = WebhookPayload::new(content, &discord);
-                    debug!("Discord Webhook Payload: {}", payload.to_json());
-                    let response = client.post(&discord.url)
-                    .header("content-type", "application/json")
-                    .body(payload.to_json())
-                    .send().await;
-                    match response {
-                            Ok(ok) => debug!("Discord Webhook Response - status: {} url: {}", ok.status(), ok.url()),
-                            Err(error) => warn!("Discord Webhook Response Error: {}", error ),
+                if let Some(message) = check_received("Discord", received) {
+                    let retry = discord.options.retry().unwrap_or_default();
+                    let mut result = Ok(());
+                    for payload in WebhookPayload::new(message.message(), &discord).split() {
+                        debug!("Discord Webhook Payload: {}", payload.to_json());
+                        if let Err(e) = deliver(&retry, "discord webhook message", &shutdown, || post_payload(&client, &discord.url, &payload)).await {
+                            result = Err(e);
                         }
+                    }
+                    message.report_delivery(result);
                 }
             }
 
@@ -150,3 +196,35 @@ async fn send_messages(
         }
     }
 }
+
+async fn post_payload(client: &Client, url: &str, payload: &WebhookPayload) -> Result<(), DeliveryError> {
+    let response = client
+        .post(url)
+        .header("content-type", "application/json")
+        .body(payload.to_json())
+        .send()
+        .await
+        .map_err(|error| DeliveryError::transient(format!("Discord Webhook Response Error: {}", error)))?;
+
+    let status = response.status();
+    debug!("Discord Webhook Response - status: {} url: {}", status, response.url());
+    if status.is_success() {
+        return Ok(());
+    }
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let header = response.headers().get(RETRY_AFTER).and_then(|value| value.to_str().ok()?.parse::<f64>().ok());
+        let body = response.json::<RateLimitResponse>().await.ok().map(|body| body.retry_after());
+        let retry_after = body
+            .or(header)
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(|secs| Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX));
+        return Err(DeliveryError::transient("Discord Webhook rate limited").with_retry_after(retry_after));
+    }
+
+    let reason = format!("Discord Webhook Response - status: {} body: {}", status, response.text().await.unwrap_or_default());
+    match status.is_server_error() {
+        true => Err(DeliveryError::transient(reason)),
+        false => Err(DeliveryError::permanent(reason)),
+    }
+}
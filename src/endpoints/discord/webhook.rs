@@ -1,6 +1,11 @@
 use crate::endpoints::discord::DiscordEndpoint;
+use crate::endpoints::format_details;
+use crate::notifications::{Message, Priority};
 use serde::{Deserialize, Serialize};
 
+/// Maximum number of characters Discord accepts in the content of a webhook message.
+const CONTENT_LIMIT: usize = 2000;
+
 #[derive(Debug, Serialize, Clone)]
 pub(crate) struct AllowedMentions {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -13,7 +18,9 @@ pub(crate) struct AllowedMentions {
     replied_user: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(crate) struct AllowedMentionsConfigFile {
     parse: Option<Vec<MentionTypes>>,
     roles: Option<Vec<String>>,
@@ -22,6 +29,7 @@ pub(crate) struct AllowedMentionsConfigFile {
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub(crate) enum MentionTypes {
     Roles,
@@ -29,6 +37,12 @@ pub(crate) enum MentionTypes {
     Everyone,
 }
 
+/// Body of a Discord response when a webhook is rate limited.
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct RateLimitResponse {
+    retry_after: f64,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub(crate) struct WebhookPayload {
     content: String,
@@ -41,21 +55,91 @@ pub(crate) struct WebhookPayload {
 }
 
 impl WebhookPayload {
-    pub fn new(content: &str, config: &DiscordEndpoint) -> Self {
+    pub fn new(message: &Message, config: &DiscordEndpoint) -> Self {
+        let critical = message.priority() == Priority::Critical;
+        let mut text = match message.title() {
+            None => message.text().to_string(),
+            Some(title) => format!("**{}**\n{}", title, message.text()),
+        };
+        if let Some(details) = format_details(message, "\n-# ") {
+            text.push_str(format!("\n-# {}", details).as_str());
+        }
+        let content = match (critical, config.critical_mention.as_ref()) {
+            (true, Some(mention)) => format!("{} {}", mention, text),
+            _ => text,
+        };
+
         Self {
-            content: content.to_string(),
+            content,
             username: config.username.clone(),
             avatar_url: config.avatar_url.clone(),
-            tts: config.tts,
+            tts: config.tts || critical,
             allowed_mentions: config.allowed_mentions.clone(),
         }
     }
 
+    /// Split this payload into payloads whose content fits in the Discord limit.
+    ///
+    /// Content is split on line breaks where possible and text-to-speech is only used for the first payload.
+    pub fn split(self) -> Vec<Self> {
+        split_content(self.content.as_str(), CONTENT_LIMIT)
+            .into_iter()
+            .enumerate()
+            .map(|(index, content)| Self { content, tts: self.tts && index == 0, ..self.clone() })
+            .collect()
+    }
+
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap_or_default()
     }
 }
 
+fn split_content(content: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for line in content.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if current_len + line_len > limit && !current.is_empty() {
+            push_chunk(&mut chunks, current.as_str());
+            current.clear();
+            current_len = 0;
+        }
+
+        if line_len > limit {
+            let chars: Vec<char> = line.chars().collect();
+            for part in chars.chunks(limit) {
+                push_chunk(&mut chunks, part.iter().collect::<String>().as_str());
+            }
+        } else {
+            current.push_str(line);
+            current_len += line_len;
+        }
+    }
+
+    push_chunk(&mut chunks, current.as_str());
+    if chunks.is_empty() {
+        chunks.push(content.trim_end().to_string());
+    }
+    chunks
+}
+
+/// Add `chunk` without trailing whitespace to `chunks` unless nothing is left, Discord rejects empty content.
+fn push_chunk(chunks: &mut Vec<String>, chunk: &str) {
+    let chunk = chunk.trim_end();
+    if !chunk.is_empty() {
+        chunks.push(chunk.to_string());
+    }
+}
+
+impl RateLimitResponse {
+    /// Return the number of seconds to wait before sending again.
+    pub fn retry_after(&self) -> f64 {
+        self.retry_after
+    }
+}
+
 impl From<AllowedMentionsConfigFile> for AllowedMentions {
     fn from(value: AllowedMentionsConfigFile) -> Self {
         Self { parse: value.parse, roles: value.roles, users: value.users, replied_user: value.replied_user }
@@ -67,3 +151,66 @@ impl Default for AllowedMentions {
         Self { parse: Some(Vec::new()), roles: None, users: None, replied_user: None }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(content: &str, tts: bool) -> WebhookPayload {
+        WebhookPayload {
+            content: content.to_string(),
+            username: None,
+            avatar_url: None,
+            tts,
+            allowed_mentions: AllowedMentions::default(),
+        }
+    }
+
+    #[test]
+    fn short_content_is_not_split() {
+        assert_eq!(split_content("first\nsecond\n", 20), vec!["first\nsecond"]);
+    }
+
+    #[test]
+    fn content_is_split_on_line_breaks() {
+        assert_eq!(split_content("first\nsecond\nthird", 13), vec!["first\nsecond", "third"]);
+    }
+
+    #[test]
+    fn line_at_the_limit_is_one_chunk() {
+        let line = "a".repeat(10);
+        assert_eq!(split_content(line.as_str(), 10), vec![line.clone()]);
+        assert_eq!(split_content(format!("{}\n", line).as_str(), 10), vec![line.clone()]);
+        assert_eq!(split_content(format!("{}\nb", line).as_str(), 10), vec![line, "b".to_string()]);
+    }
+
+    #[test]
+    fn long_line_ending_in_a_line_break_has_no_empty_chunk() {
+        let content = format!("{}\n", "a".repeat(25));
+        assert_eq!(split_content(content.as_str(), 10), vec!["a".repeat(10), "a".repeat(10), "a".repeat(5)]);
+    }
+
+    #[test]
+    fn whitespace_lines_have_no_empty_chunk() {
+        let content = format!("{}\n   \n{}\n", "a".repeat(10), "b".repeat(10));
+        assert_eq!(split_content(content.as_str(), 10), vec!["a".repeat(10), "b".repeat(10)]);
+    }
+
+    #[test]
+    fn multibyte_text_is_split_on_characters() {
+        let content = "é".repeat(15);
+        let chunks = split_content(content.as_str(), 10);
+
+        assert_eq!(chunks, vec!["é".repeat(10), "é".repeat(5)]);
+    }
+
+    #[test]
+    fn tts_is_only_used_for_the_first_payload() {
+        let content = format!("{}\n{}", "a".repeat(CONTENT_LIMIT), "b".repeat(CONTENT_LIMIT));
+        let payloads = payload(content.as_str(), true).split();
+
+        assert_eq!(payloads.iter().map(|payload| payload.tts).collect::<Vec<_>>(), vec![true, false]);
+        assert!(payloads.iter().all(|payload| payload.content.chars().count() <= CONTENT_LIMIT));
+        assert!(payload("short", false).split().iter().all(|payload| !payload.tts));
+    }
+}
@@ -15,21 +15,36 @@
 //! subject = "test_email"
 //! notifications = ["notification1", "notification2"]
 //! ```
+//!
+//! The `subject` is used for messages that do not have a title, otherwise the message title is used as the subject.
+//! Messages with a priority other than normal have the `X-Priority` and `Importance` headers set.
+//! Metadata and tags are listed at the end of the email body.
 
 use crate::Error;
-use crate::endpoints::{Endpoint, EndpointConfig};
-use crate::notifications::{Key, ValidatedNotification};
+use crate::endpoints::batch::{Batch, BatchConfigFile};
+use crate::endpoints::dedup::{Dedup, DedupConfigFile};
+use crate::endpoints::filter::{Filter, FilterConfigFile};
+use crate::endpoints::queue::{Queue, QueueConfigFile};
+use crate::endpoints::rate_limit::{RateLimit, RateLimitConfigFile};
+use crate::endpoints::delivery::DeliveryError;
+use crate::endpoints::retry::{deliver, Retry, RetryConfigFile};
+use crate::endpoints::schedule::{Schedule, ScheduleConfigFile};
+use crate::endpoints::{check_received, format_details, Endpoint, EndpointConfig, EndpointOptions};
+use crate::notifications::{Key, Priority, ValidatedNotification};
 use async_trait::async_trait;
 use mail_send::SmtpClientBuilder;
 use mail_send::mail_builder::MessageBuilder;
-use serde::Deserialize;
+use mail_send::mail_builder::headers::raw::Raw;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
 use tokio::sync::{broadcast, watch};
-use tracing::{debug, error, info};
+use tracing::{debug, info};
 
 /// Data structure to represent the email [`EndpointConfig`].
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(crate) struct EmailConfigFile {
     hostname: String,
     port: i64,
@@ -43,6 +58,14 @@ pub(crate) struct EmailConfigFile {
     to: Vec<String>,
     subject: String,
     notifications: Vec<String>,
+    name: Option<String>,
+    dedup: Option<DedupConfigFile>,
+    rate_limit: Option<RateLimitConfigFile>,
+    batch: Option<BatchConfigFile>,
+    schedule: Option<ScheduleConfigFile>,
+    queue: Option<QueueConfigFile>,
+    filter: Option<FilterConfigFile>,
+    retry: Option<RetryConfigFile>,
 }
 
 /// Data structure to represent the email [`Endpoint`].
@@ -58,6 +81,7 @@ pub struct EmailEndpoint {
     to: Vec<String>,
     subject: String,
     notifications: Vec<String>,
+    options: EndpointOptions,
 }
 #[derive(Debug, Clone)]
 struct EmailInfo {
@@ -70,9 +94,10 @@ struct EmailInfo {
     from: String,
     to: Vec<String>,
     subject: String,
+    retry: Retry,
 }
 
-#[typetag::deserialize(name = "email")]
+#[typetag::serde(name = "email")]
 impl EndpointConfig for EmailConfigFile {
     fn to_endpoint(&self) -> Result<Box<dyn Endpoint + Send>, Error> {
         Ok(Box::new(EmailEndpoint::try_from(self)?))
@@ -106,6 +131,15 @@ impl TryFrom<&EmailConfigFile> for EmailEndpoint {
             to: value.to.clone(),
             subject: value.subject.clone(),
             notifications: value.notifications.clone(),
+            options: EndpointOptions::default()
+                .with_name(value.name.as_ref())
+                .with_dedup(value.dedup.as_ref().map(Dedup::from))
+                .with_rate_limit(value.rate_limit.as_ref().map(RateLimit::try_from).transpose()?)
+                .with_batch(value.batch.as_ref().map(Batch::try_from).transpose()?)
+                .with_schedule(value.schedule.as_ref().map(Schedule::try_from).transpose()?)
+                .with_retry(value.retry.as_ref().map(Retry::try_from).transpose()?)
+                .with_queue(value.queue.as_ref().map(Queue::try_from).transpose()?)
+                .with_filter(value.filter.as_ref().map(Filter::try_from).transpose()?),
         })
     }
 }
@@ -135,6 +169,7 @@ impl Endpoint for EmailEndpoint {
             from: self.from.clone(),
             to: self.to.clone(),
             subject: self.subject.clone(),
+            retry: self.options.retry().unwrap_or_default(),
         };
 
         tokio::spawn(async move { send_emails(endpoint_rx, shutdown, email_info).await });
@@ -157,6 +192,14 @@ impl Endpoint for EmailEndpoint {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reports_delivery(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> EndpointOptions {
+        self.options.clone()
+    }
 }
 
 async fn send_emails(
@@ -164,49 +207,18 @@ async fn send_emails(
     shutdown: watch::Receiver<bool>,
     info: EmailInfo,
 ) {
-    let mut rx = endpoint_rx.resubscribe();
+    let mut rx = endpoint_rx;
     let mut shutdown_rx = shutdown.clone();
 
     loop {
-        let info = info.clone();
         tokio::select! {
             received = rx.recv() => {
-                if let Ok(message) = received {
+                if let Some(message) = check_received("Email", received) {
                     debug!("Email endpoint received message");
 
-                    tokio::spawn( async move {
-                        let content = message.message().text();
-                        let email = MessageBuilder::new()
-                        .from(info.from.as_str())
-                        .subject(info.subject.as_str())
-                        .to(info.to.clone())
-                        .text_body(content);
-
-                        debug!("Connecting to SMTP: {}:{} as {}", info.hostname.as_str(), info.port, info.username.as_str());
-                        let client = SmtpClientBuilder::new(info.hostname.as_str(), info.port);
-                        match client {
-                            Ok(mut smpt_client) => {
-                                smpt_client = smpt_client.implicit_tls(info.implicit_tls)
-                                .credentials((info.username.as_str(), info.password.as_str()));
-
-                                if info.allow_invalid_certs {
-                                    smpt_client = smpt_client.allow_invalid_certs();
-                                }
-
-                        match smpt_client.connect().await {
-                            Ok(mut client) => {
-                                match client.send(email).await {
-                                    Ok(_) => debug!("Email sent successfully"),
-                                    Err(e) => error!("Unable to connect to smtp server: {}", e),
-                                }
-                            }
-                            Err(e) => error!("Unable to send email: {}", e)
-                        }
-                            },
-                            Err(e) => error!("SMPT Client Error: {e}"),
-                        }
-                    }).await.unwrap();
-
+                    // One email at a time so a slow SMTP server fills the queue and its overflow policy applies
+                    let result = deliver(&info.retry, "email", &shutdown, || send_email(&info, &message)).await;
+                    message.report_delivery(result);
                 }
             }
 
@@ -216,3 +228,56 @@ async fn send_emails(
         }
     }
 }
+
+async fn send_email(info: &EmailInfo, message: &ValidatedNotification) -> Result<(), DeliveryError> {
+    let content = match format_details(message.message(), "\n") {
+        None => message.message().text().to_string(),
+        Some(details) => format!("{}\n\n--\n{}", message.message().text(), details),
+    };
+    let subject = message.message().title().unwrap_or(info.subject.as_str());
+    let mut email = MessageBuilder::new().from(info.from.as_str()).subject(subject).to(info.to.clone()).text_body(content);
+
+    if let Some((x_priority, importance)) = priority_headers(message.message().priority()) {
+        email = email.header("X-Priority", Raw::new(x_priority)).header("Importance", Raw::new(importance));
+    }
+
+    debug!("Connecting to SMTP: {}:{} as {}", info.hostname.as_str(), info.port, info.username.as_str());
+    let mut smtp_client = SmtpClientBuilder::new(info.hostname.as_str(), info.port)
+        .map_err(|e| DeliveryError::permanent(format!("SMPT Client Error: {e}")))?
+        .implicit_tls(info.implicit_tls)
+        .credentials((info.username.as_str(), info.password.as_str()));
+
+    if info.allow_invalid_certs {
+        smtp_client = smtp_client.allow_invalid_certs();
+    }
+
+    let mut client = smtp_client
+        .connect()
+        .await
+        .map_err(|e| delivery_error(format!("Unable to connect to smtp server: {}", e).as_str(), &e))?;
+    client.send(email).await.map_err(|e| delivery_error(format!("Unable to send email: {}", e).as_str(), &e))?;
+    debug!("Email sent successfully");
+    Ok(())
+}
+
+/// Classify a [`mail_send::Error`] as a transient or permanent [`DeliveryError`].
+fn delivery_error(reason: &str, error: &mail_send::Error) -> DeliveryError {
+    use mail_send::Error as SmtpError;
+    match error {
+        SmtpError::Io(_) | SmtpError::Tls(_) | SmtpError::Timeout | SmtpError::UnparseableReply => {
+            DeliveryError::transient(reason)
+        }
+        SmtpError::UnexpectedReply(response) if response.code < 500 => DeliveryError::transient(reason),
+        _ => DeliveryError::permanent(reason),
+    }
+}
+
+/// Map a [`Priority`] to the `X-Priority` and `Importance` header values.
+fn priority_headers(priority: Priority) -> Option<(&'static str, &'static str)> {
+    match priority {
+        Priority::Low => Some(("5 (Lowest)", "low")),
+        Priority::Normal => None,
+        Priority::High => Some(("2 (High)", "high")),
+        Priority::Critical => Some(("1 (Highest)", "high")),
+    }
+}
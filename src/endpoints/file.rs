@@ -7,13 +7,25 @@
 //! path = 'path/to/file_endpoint.txt'
 //! notifications = ["notification_id1", "notification_id2"]
 //! ```
-
-use crate::endpoints::{Endpoint, EndpointConfig};
-use crate::notifications::{Key, ValidatedNotification};
+//!
+//! Each notification is written as a single line with the title, when there is one, written before the text.
+//! Notifications with a priority other than normal are prefixed with the priority,
+//! e.g. `[CRITICAL] Backup: disk is full`. Metadata and tags are appended after the text,
+//! e.g. `disk is full | host: web01; tags: backup, nightly`.
+
+use crate::endpoints::batch::{Batch, BatchConfigFile};
+use crate::endpoints::dedup::{Dedup, DedupConfigFile};
+use crate::endpoints::filter::{Filter, FilterConfigFile};
+use crate::endpoints::queue::{Queue, QueueConfigFile};
+use crate::endpoints::rate_limit::{RateLimit, RateLimitConfigFile};
+use crate::endpoints::delivery::DeliveryError;
+use crate::endpoints::schedule::{Schedule, ScheduleConfigFile};
+use crate::endpoints::{check_received, format_details, Endpoint, EndpointConfig, EndpointOptions};
+use crate::notifications::{Key, Message, ValidatedNotification};
 use crate::{Error};
 use async_trait::async_trait;
 use tracing::{info, warn};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
@@ -24,10 +36,19 @@ use tokio::sync::{broadcast, watch};
 const LINE_FEED: &[u8] = "\n".as_bytes();
 
 /// Data structure to represent the regular file [`EndpointConfig`].
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(crate) struct FileConfigFile {
     path: String,
     notifications: Vec<String>,
+    name: Option<String>,
+    dedup: Option<DedupConfigFile>,
+    rate_limit: Option<RateLimitConfigFile>,
+    batch: Option<BatchConfigFile>,
+    schedule: Option<ScheduleConfigFile>,
+    queue: Option<QueueConfigFile>,
+    filter: Option<FilterConfigFile>,
 }
 
 /// Data structure to represent the regular file [`Endpoint`].
@@ -35,6 +56,7 @@ pub(crate) struct FileConfigFile {
 pub struct FileEndpoint {
     path: PathBuf,
     notifications: Vec<String>,
+    options: EndpointOptions,
 }
 
 impl FileEndpoint {
@@ -42,8 +64,15 @@ impl FileEndpoint {
     pub fn new(path: &str, notifications: &[String]) -> Self {
         let path = PathBuf::from(path);
         let notifications = notifications.into();
-        Self { path, notifications }
+        Self { path, notifications, options: EndpointOptions::default() }
+    }
+
+    /// Set the [`EndpointOptions`] for this `FileEndpoint`.
+    pub fn with_options(mut self, options: EndpointOptions) -> Self {
+        self.options = options;
+        self
     }
+
     /// Return the file path.
     pub fn path(&self) -> &PathBuf {
         &self.path
@@ -69,11 +98,19 @@ impl TryFrom<&FileConfigFile> for FileEndpoint {
             ));
         }
 
-        Ok(FileEndpoint::new(value.path.as_str(), &value.notifications))
+        let options = EndpointOptions::default()
+            .with_name(value.name.as_ref())
+            .with_dedup(value.dedup.as_ref().map(Dedup::from))
+            .with_rate_limit(value.rate_limit.as_ref().map(RateLimit::try_from).transpose()?)
+            .with_batch(value.batch.as_ref().map(Batch::try_from).transpose()?)
+            .with_schedule(value.schedule.as_ref().map(Schedule::try_from).transpose()?)
+            .with_queue(value.queue.as_ref().map(Queue::try_from).transpose()?)
+            .with_filter(value.filter.as_ref().map(Filter::try_from).transpose()?);
+        Ok(FileEndpoint::new(value.path.as_str(), &value.notifications).with_options(options))
     }
 }
 
-#[typetag::deserialize(name = "file")]
+#[typetag::serde(name = "file")]
 impl EndpointConfig for FileConfigFile {
     fn to_endpoint(&self) -> Result<Box<dyn Endpoint + Send>, Error> {
         Ok(Box::new(FileEndpoint::try_from(self)?))
@@ -108,6 +145,14 @@ impl Endpoint for FileEndpoint {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reports_delivery(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> EndpointOptions {
+        self.options.clone()
+    }
 }
 
 async fn write_file<P: AsRef<Path>>(
@@ -115,7 +160,7 @@ async fn write_file<P: AsRef<Path>>(
     endpoint_rx: broadcast::Receiver<ValidatedNotification>,
     shutdown: watch::Receiver<bool>,
 ) -> Result<(), Error> {
-    let mut rx = endpoint_rx.resubscribe();
+    let mut rx = endpoint_rx;
     let mut shutdown_rx = shutdown.clone();
 
     let file = OpenOptions::new().read(true).append(true).create(true).open(path.as_ref()).await?;
@@ -123,17 +168,20 @@ async fn write_file<P: AsRef<Path>>(
     loop {
         tokio::select! {
             received = rx.recv() => {
-                if let Ok(message) = received {
-                    let line = [message.message().text().as_bytes(), LINE_FEED].concat();
-                    match file.write(line.as_slice()).await {
-                        Ok(_) => (),
-                        Err(e) => warn!("{}", e)
-                    }
-
-                    match file.flush().await {
-                        Ok(_) => (),
-                        Err(e) => warn!("{}", e),
+                if let Some(message) = check_received("File", received) {
+                    let line = [format_line(message.message()).as_bytes(), LINE_FEED].concat();
+                    let result = match file.write_all(line.as_slice()).await {
+                        Ok(_) => file.flush().await,
+                        Err(e) => Err(e),
                     };
+
+                    match result {
+                        Ok(_) => message.report_delivery(Ok(())),
+                        Err(e) => {
+                            warn!("{}", e);
+                            message.report_delivery(Err(DeliveryError::transient(e.to_string())));
+                        }
+                    }
                 }
             }
 
@@ -146,3 +194,19 @@ async fn write_file<P: AsRef<Path>>(
     file.shutdown().await?;
     Ok(())
 }
+
+fn format_line(message: &Message) -> String {
+    let mut text = match message.title() {
+        None => message.text().to_string(),
+        Some(title) => format!("{}: {}", title, message.text()),
+    };
+
+    if let Some(details) = format_details(message, "; ") {
+        text.push_str(format!(" | {}", details).as_str());
+    }
+
+    match message.priority().is_normal() {
+        true => text,
+        false => format!("[{}] {}", message.priority().to_string().to_uppercase(), text),
+    }
+}
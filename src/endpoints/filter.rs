@@ -0,0 +1,112 @@
+//! Filtering the notifications routed to an endpoint by their tags and metadata
+//!
+//! An endpoint receives every notification with one of its notification names. With a filter it only receives
+//! the ones that have at least one of the `tags` and every `metadata` key with the same value, so notifications
+//! with the same name can be routed to different endpoints, for example by environment or service.
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.endpoint]]
+//! type = "file"
+//! path = 'path/to/production.txt'
+//! notifications = ["notification_id1"]
+//!
+//! [server.endpoint.filter]
+//! tags = ["backup", "nightly"]
+//! metadata = { environment = "production" }
+//! ```
+
+use crate::notifications::Message;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Tags and metadata a notification must have to be routed to an endpoint.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Filter {
+    tags: Vec<String>,
+    metadata: BTreeMap<String, String>,
+}
+
+/// Data structure to represent [`Filter`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FilterConfigFile {
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+impl Filter {
+    /// Create a new `Filter` that accepts messages with any of the `tags` and all of the `metadata`.
+    ///
+    /// At least one tag or metadata pair is required.
+    pub fn new(tags: Vec<String>, metadata: BTreeMap<String, String>) -> Result<Self, Error> {
+        if tags.is_empty() && metadata.is_empty() {
+            return Err(Error::invalid_endpoint_configuration("filter requires tags or metadata".to_string()));
+        }
+
+        Ok(Self { tags, metadata })
+    }
+
+    /// Return the tags of which a message needs at least one.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Return the metadata a message needs.
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Return if the `message` has one of the tags and all of the metadata of this `Filter`.
+    pub fn accepts(&self, message: &Message) -> bool {
+        let tagged = self.tags.is_empty() || self.tags.iter().any(|tag| message.tags().contains(tag));
+        tagged && self.metadata.iter().all(|(key, value)| message.metadata().get(key) == Some(value))
+    }
+}
+
+impl TryFrom<&FilterConfigFile> for Filter {
+    type Error = Error;
+
+    fn try_from(value: &FilterConfigFile) -> Result<Self, Self::Error> {
+        Filter::new(value.tags.clone(), value.metadata.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(tags: &[&str], metadata: &[(&str, &str)]) -> Filter {
+        let tags = tags.iter().map(|tag| tag.to_string()).collect();
+        let metadata = metadata.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        Filter::new(tags, metadata).unwrap()
+    }
+
+    #[test]
+    fn filter_requires_tags_or_metadata() {
+        assert!(Filter::new(Vec::new(), BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn message_needs_one_of_the_tags() {
+        let filter = filter(&["backup", "nightly"], &[]);
+
+        assert!(filter.accepts(&Message::new("done").with_tag("nightly")));
+        assert!(!filter.accepts(&Message::new("done").with_tag("weekly")));
+        assert!(!filter.accepts(&Message::new("done")));
+    }
+
+    #[test]
+    fn message_needs_all_of_the_metadata() {
+        let filter = filter(&["backup"], &[("environment", "production"), ("service", "db")]);
+        let message = Message::new("done").with_tag("backup").with_metadata("environment", "production");
+
+        assert!(!filter.accepts(&message));
+        assert!(filter.accepts(&message.clone().with_metadata("service", "db").with_metadata("host", "db1")));
+        assert!(!filter.accepts(&message.with_metadata("service", "web")));
+    }
+}
@@ -18,6 +18,10 @@
 //! room = "#another-room:example.com"
 //! notifications = ["notification_id2"]
 //! ```
+//!
+//! Message titles are rendered as a heading above the message text and metadata and tags are listed below it.
+//! Messages with low priority are sent as `m.notice` so they do not trigger push notifications, all other
+//! priorities are sent as `m.text`.
 
 mod common;
 mod notify;
@@ -25,20 +29,30 @@ pub(crate) mod verify;
 
 use crate::endpoints::matrix::common::{login, print_client_debug, ClientInfo, PersistentSession};
 use crate::endpoints::matrix::notify::{process_rooms, send_messages};
-use crate::endpoints::{Endpoint, EndpointConfig};
+use crate::endpoints::batch::{Batch, BatchConfigFile};
+use crate::endpoints::dedup::{Dedup, DedupConfigFile};
+use crate::endpoints::filter::{Filter, FilterConfigFile};
+use crate::endpoints::queue::{Queue, QueueConfigFile};
+use crate::endpoints::rate_limit::{RateLimit, RateLimitConfigFile};
+use crate::endpoints::retry::{Retry, RetryConfigFile};
+use crate::endpoints::schedule::{Schedule, ScheduleConfigFile};
+use crate::endpoints::{Endpoint, EndpointConfig, EndpointOptions};
 use crate::notifications::{Key, ValidatedNotification};
 use crate::Error;
 use async_trait::async_trait;
 use tracing::{error, info};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
 use std::path::PathBuf;
 use tokio::sync::broadcast::Receiver;
 use tokio::sync::watch;
 
 /// Data structure to represent the Matrix [`EndpointConfig`].
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(crate) struct MatrixConfigFile {
     home_server: String,
     username: String,
@@ -46,9 +60,19 @@ pub(crate) struct MatrixConfigFile {
     session_store_path: String,
     recovery_passphrase: String,
     room: Vec<MatrixRoomConfigFile>,
+    name: Option<String>,
+    dedup: Option<DedupConfigFile>,
+    rate_limit: Option<RateLimitConfigFile>,
+    batch: Option<BatchConfigFile>,
+    schedule: Option<ScheduleConfigFile>,
+    queue: Option<QueueConfigFile>,
+    filter: Option<FilterConfigFile>,
+    retry: Option<RetryConfigFile>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(crate) struct MatrixRoomConfigFile {
     room: String,
     notifications: Vec<String>,
@@ -63,10 +87,11 @@ pub struct MatrixEndpoint {
     session_store_path: PathBuf,
     recovery_passphrase: String,
     rooms: Vec<MatrixRoom>,
+    options: EndpointOptions,
 }
 
 /// Data structure to represent a Matrix room.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct MatrixRoom {
     room: String,
     notifications: HashSet<String>,
@@ -97,7 +122,7 @@ impl MatrixRoomConfigFile {
     }
 }
 
-#[typetag::deserialize(name = "matrix")]
+#[typetag::serde(name = "matrix")]
 impl EndpointConfig for MatrixConfigFile {
     fn to_endpoint(&self) -> Result<Box<dyn Endpoint + Send>, Error> {
         Ok(Box::new(MatrixEndpoint::try_from(self)?))
@@ -119,7 +144,14 @@ impl MatrixEndpoint {
         let password = password.as_ref().into();
         let session_store_path = PathBuf::from(session_store_path.as_ref());
         let recovery_passphrase = recovery_passphrase.as_ref().into();
-        Self { home_server, username, password, session_store_path, recovery_passphrase, rooms }
+        let options = EndpointOptions::default();
+        Self { home_server, username, password, session_store_path, recovery_passphrase, rooms, options }
+    }
+
+    /// Set the [`EndpointOptions`] for this `MatrixEndpoint`.
+    pub fn with_options(mut self, options: EndpointOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// Return the matrix home server.
@@ -174,9 +206,21 @@ impl TryFrom<&MatrixConfigFile> for MatrixEndpoint {
             for (room, notifications) in value.rooms() {
                 rooms.push(MatrixRoom::new(room, notifications));
             }
+            // Keep the same order for the same configuration so a reload can tell it is unchanged
+            rooms.sort_by(|a, b| a.room.cmp(&b.room));
             rooms
         };
 
+        let options = EndpointOptions::default()
+            .with_name(value.name.as_ref())
+            .with_dedup(value.dedup.as_ref().map(Dedup::from))
+            .with_rate_limit(value.rate_limit.as_ref().map(RateLimit::try_from).transpose()?)
+            .with_batch(value.batch.as_ref().map(Batch::try_from).transpose()?)
+            .with_schedule(value.schedule.as_ref().map(Schedule::try_from).transpose()?)
+            .with_retry(value.retry.as_ref().map(Retry::try_from).transpose()?)
+            .with_queue(value.queue.as_ref().map(Queue::try_from).transpose()?)
+            .with_filter(value.filter.as_ref().map(Filter::try_from).transpose()?);
+
         Ok(MatrixEndpoint::new(
             value.home_server.as_str(),
             value.username.as_str(),
@@ -184,7 +228,8 @@ impl TryFrom<&MatrixConfigFile> for MatrixEndpoint {
             value.session_store_path.as_str(),
             value.recovery_passphrase.as_str(),
             rooms,
-        ))
+        )
+        .with_options(options))
     }
 }
 
@@ -205,6 +250,13 @@ impl MatrixRoom {
     }
 }
 
+impl Debug for MatrixRoom {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let notifications: BTreeSet<_> = self.notifications.iter().collect();
+        f.debug_struct("MatrixRoom").field("room", &self.room).field("notifications", &notifications).finish()
+    }
+}
+
 #[async_trait]
 impl Endpoint for MatrixEndpoint {
     async fn notify(
@@ -224,10 +276,11 @@ impl Endpoint for MatrixEndpoint {
 
         print_client_debug(&client).await;
         let room_list = process_rooms(&client, self.rooms()).await;
+        let retry = self.options.retry().unwrap_or_default();
 
         // Monitor for messages to send
         tokio::spawn(async move {
-            let sync_token = send_messages(endpoint_rx, shutdown.clone(), room_list, &client).await;
+            let sync_token = send_messages(endpoint_rx, shutdown.clone(), room_list, &client, retry).await;
             let persist =
                 PersistentSession::new(&client_info, &client.matrix_auth().session().unwrap(), Some(sync_token));
             if let Err(error) = persist.save_session() {
@@ -254,4 +307,12 @@ impl Endpoint for MatrixEndpoint {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn reports_delivery(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> EndpointOptions {
+        self.options.clone()
+    }
 }
@@ -1,5 +1,8 @@
 use crate::endpoints::matrix::MatrixRoom;
-use crate::notifications::ValidatedNotification;
+use crate::endpoints::{check_received, format_details};
+use crate::endpoints::delivery::DeliveryError;
+use crate::endpoints::retry::{deliver, Retry};
+use crate::notifications::{Message, Priority, ValidatedNotification};
 use crate::Error;
 use tracing::{debug, warn};
 use matrix_sdk::config::SyncSettings;
@@ -13,8 +16,9 @@ pub(super) async fn send_messages(
     shutdown: watch::Receiver<bool>,
     room_list: Vec<Room>,
     client: &Client,
+    retry: Retry,
 ) -> String {
-    let mut rx = endpoint_rx.resubscribe();
+    let mut rx = endpoint_rx;
     let mut shutdown_rx = shutdown.clone();
     let mut sync_token = client.sync_once(SyncSettings::default()).await.unwrap().next_batch;
     let client_homeserver = get_default_server(client);
@@ -22,21 +26,30 @@ pub(super) async fn send_messages(
     loop {
         tokio::select! {
             received = rx.recv() => {
-                if let Ok(message) = received {
+                if let Some(message) = check_received("Matrix", received) {
                     debug!("Matrix message received: {} Name: {}", message.message().text(), message.sub_name());
-                    let msg_text = RoomMessageEventContent::text_plain(message.message().text());
+                    let msg_text = create_message_content(message.message());
 
-                    if let Ok(msg_room) = validate_room(message.sub_name(), client_homeserver.as_str()) {
-                        for room in &room_list {
-                            if get_all_room_aliases(room).contains(msg_room.as_str()) {
-                                debug!("Sending Matrix Message to {}", msg_room);
-                                match room.send(msg_text.clone()).await {
-                                    Ok(r) => debug!("OK: {:?}", r),
-                                    Err(e) => debug!("Error: {}", e),
+                    let result = match validate_room(message.sub_name(), client_homeserver.as_str()) {
+                        Ok(msg_room) => {
+                            let mut result = Err(DeliveryError::permanent(format!("Matrix room {} is not joined", msg_room)));
+                            for room in &room_list {
+                                if get_all_room_aliases(room).contains(msg_room.as_str()) {
+                                    debug!("Sending Matrix Message to {}", msg_room);
+                                    let description = format!("matrix message to {}", msg_room);
+                                    result = deliver(&retry, description.as_str(), &shutdown, || async {
+                                        let response = room.send(msg_text.clone()).await
+                                            .map_err(|e| DeliveryError::transient(format!("Error: {}", e)))?;
+                                        debug!("OK: {:?}", response);
+                                        Ok(())
+                                    }).await;
                                 }
                             }
+                            result
                         }
-                    }
+                        Err(e) => Err(DeliveryError::permanent(e.to_string())),
+                    };
+                    message.report_delivery(result);
                     sync_token = client.sync_once(SyncSettings::default().token(&sync_token)).await.unwrap().next_batch;
                 }
             }
@@ -50,6 +63,50 @@ pub(super) async fn send_messages(
     sync_token
 }
 
+fn create_message_content(message: &Message) -> RoomMessageEventContent {
+    let details = format_details(message, "\n");
+    match (message.title(), details, message.priority()) {
+        (None, None, Priority::Low) => RoomMessageEventContent::notice_plain(message.text()),
+        (None, None, _) => RoomMessageEventContent::text_plain(message.text()),
+        (title, details, priority) => {
+            let mut plain = String::new();
+            let mut html = String::new();
+            if let Some(title) = title {
+                plain.push_str(format!("{}\n\n", title).as_str());
+                html.push_str(format!("<h4>{}</h4>\n", escape_html(title)).as_str());
+            }
+
+            plain.push_str(message.text());
+            html.push_str(format!("<p>{}</p>", escape_html(message.text()).replace('\n', "<br>")).as_str());
+
+            if let Some(details) = details {
+                plain.push_str(format!("\n\n{}", details).as_str());
+                html.push_str(format!("\n<p><small>{}</small></p>", escape_html(&details).replace('\n', "<br>")).as_str());
+            }
+
+            match priority {
+                Priority::Low => RoomMessageEventContent::notice_html(plain, html),
+                _ => RoomMessageEventContent::text_html(plain, html),
+            }
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 fn validate_room(room: &str, default_server: &str) -> Result<String, Error> {
     let room = room.trim();
     if room.starts_with('!') || room.starts_with('#') {
@@ -0,0 +1,552 @@
+//! Queue of notifications waiting for an endpoint
+//!
+//! Each endpoint has a queue of notifications it has not received yet, 200 by default. The `size` is rounded up to
+//! the next power of two. When a slow endpoint lets its queue fill up the `overflow` policy decides what happens
+//! to new notifications:
+//!
+//! | Overflow    | Description                                                                                      |
+//! |-------------|--------------------------------------------------------------------------------------------------|
+//! | block       | Wait for room in the queue, new notifications are dropped once 200 more are waiting.             |
+//! | drop-oldest | Drop the oldest notification in the queue to make room. (default)                                |
+//! | spill       | Write the notifications to the file at `spill_path` and send them once there is room.            |
+//!
+//! While an endpoint is paused its notifications are held in memory until as many are held as fit in the queue,
+//! then the `overflow` policy applies to them in the same way.
+//!
+//! Dropped notifications are counted and logged as warnings. A blocked endpoint does not hold up the other endpoints,
+//! the notifications it cannot take are counted as `busy` in the admin API stats. Spilled notifications that have
+//! not been sent when the server stops are sent after the next start, `<spill_path>.offset` records how far the
+//! file was sent.
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.endpoint]]
+//! type = "email"
+//! hostname = "smtp.example.com"
+//! port = 587
+//! username = "test_user"
+//! password = "test_password"
+//! implicit_tls = false
+//! allow_invalid_certs = false
+//! from = "asdf@example.com"
+//! to = ["qwerty@example.com"]
+//! subject = "test_subject"
+//! notifications = ["notification_id1"]
+//!
+//! [server.endpoint.queue]
+//! size = 500
+//! overflow = "spill"
+//! spill_path = '/var/lib/pass-it-on/email.spill'
+//! ```
+
+use crate::endpoints::delivery::Receipt;
+use crate::notifications::{Message, ValidatedNotification};
+use crate::{Error, CHANNEL_BUFFER};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+/// How often a full queue is checked for room.
+const ROOM_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Settings for the queue of notifications waiting for an endpoint.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Queue {
+    size: usize,
+    overflow: QueueOverflow,
+    spill_path: Option<PathBuf>,
+}
+
+/// Policy for notifications that arrive while a [`Queue`] is full.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueOverflow {
+    /// Wait for room in the queue.
+    Block,
+    /// Drop the oldest notification in the queue.
+    #[default]
+    DropOldest,
+    /// Write notifications to disk until there is room in the queue.
+    Spill,
+}
+
+/// Data structure to represent [`Queue`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct QueueConfigFile {
+    size: Option<usize>,
+    #[serde(default)]
+    overflow: QueueOverflow,
+    spill_path: Option<String>,
+}
+
+/// Sends notifications to an endpoint and applies the [`QueueOverflow`] policy when its queue is full.
+#[derive(Debug)]
+pub(crate) struct Outbox {
+    capacity: usize,
+    overflow: QueueOverflow,
+    channel: broadcast::Sender<ValidatedNotification>,
+    held: VecDeque<ValidatedNotification>,
+    spill: Option<SpillFile>,
+    dropped: u64,
+    paused: bool,
+}
+
+/// Notifications written to disk while the queue is full.
+#[derive(Debug)]
+struct SpillFile {
+    writer: File,
+    reader: BufReader<File>,
+    offset_path: PathBuf,
+    offset: u64,
+    pending: usize,
+    inherited: usize,
+    receipts: VecDeque<Option<Receipt>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SpilledNotification {
+    sub_name: String,
+    message: Message,
+}
+
+impl Queue {
+    /// Create a new `Queue` that holds `size` notifications and drops the oldest when it is full, `size` must be
+    /// greater than 0.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::invalid_endpoint_configuration("queue size must be greater than 0".to_string()));
+        }
+
+        Ok(Self { size, overflow: QueueOverflow::default(), spill_path: None })
+    }
+
+    /// Set the [`QueueOverflow`] policy.
+    pub fn with_overflow(mut self, overflow: QueueOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Set the file used by [`QueueOverflow::Spill`].
+    pub fn with_spill_path<P: AsRef<Path>>(mut self, spill_path: Option<P>) -> Self {
+        self.spill_path = spill_path.map(|path| path.as_ref().into());
+        self
+    }
+
+    /// Return the number of notifications the queue holds.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Return the [`QueueOverflow`] policy.
+    pub fn overflow(&self) -> QueueOverflow {
+        self.overflow
+    }
+
+    /// Return the file used by [`QueueOverflow::Spill`].
+    pub fn spill_path(&self) -> Option<&Path> {
+        self.spill_path.as_deref()
+    }
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self { size: CHANNEL_BUFFER, overflow: QueueOverflow::default(), spill_path: None }
+    }
+}
+
+impl TryFrom<&QueueConfigFile> for Queue {
+    type Error = Error;
+
+    fn try_from(value: &QueueConfigFile) -> Result<Self, Self::Error> {
+        let size = value.size.unwrap_or(CHANNEL_BUFFER);
+        if value.overflow == QueueOverflow::Spill && value.spill_path.as_deref().unwrap_or_default().is_empty() {
+            return Err(Error::invalid_endpoint_configuration(
+                "queue overflow is spill but no spill_path is set".to_string(),
+            ));
+        }
+
+        Ok(Queue::new(size)?.with_overflow(value.overflow).with_spill_path(value.spill_path.as_ref()))
+    }
+}
+
+impl Outbox {
+    /// Create a new `Outbox` that sends to the endpoint `channel` created with the size of the `queue`.
+    pub fn new(queue: &Queue, channel: broadcast::Sender<ValidatedNotification>) -> Result<Self, Error> {
+        let spill = match (queue.overflow, queue.spill_path()) {
+            (QueueOverflow::Spill, Some(path)) => Some(SpillFile::open(path)?),
+            _ => None,
+        };
+        Ok(Self {
+            capacity: queue.size.next_power_of_two(),
+            overflow: queue.overflow,
+            channel,
+            held: VecDeque::new(),
+            spill,
+            dropped: 0,
+            paused: false,
+        })
+    }
+
+    /// Pause or resume sending to the endpoint.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Send the notification to the endpoint or handle it with the [`QueueOverflow`] policy when the queue is full.
+    ///
+    /// `prepare` is applied to each notification right before it is sent or spilled. While the endpoint is paused
+    /// notifications are held in memory, and prepared when they arrive, until as many are held as fit in the queue.
+    pub fn send<F>(&mut self, notification: ValidatedNotification, prepare: F)
+    where
+        F: Fn(ValidatedNotification) -> ValidatedNotification,
+    {
+        if self.paused {
+            self.hold(notification, prepare);
+            return;
+        }
+
+        self.flush(&prepare);
+        if self.pending() == 0 && self.has_room() {
+            self.broadcast(prepare(notification));
+            return;
+        }
+
+        match (self.overflow, self.spill.as_mut()) {
+            (QueueOverflow::Block, _) => self.held.push_back(notification),
+            (QueueOverflow::Spill, Some(spill)) => {
+                if let Err(e) = spill.push(prepare(notification)) {
+                    warn!("Unable to spill notification to disk: {}", e);
+                    self.dropped += 1;
+                }
+            }
+            _ => {
+                self.dropped += 1;
+                self.broadcast(prepare(notification));
+            }
+        }
+    }
+
+    /// Hold a notification that arrives while the endpoint is paused.
+    fn hold<F>(&mut self, notification: ValidatedNotification, prepare: F)
+    where
+        F: Fn(ValidatedNotification) -> ValidatedNotification,
+    {
+        let spilled = self.spill.as_ref().map(|spill| spill.pending).unwrap_or_default();
+        if self.held.len() < self.capacity && spilled == 0 {
+            self.held.push_back(prepare(notification));
+            return;
+        }
+
+        match (self.overflow, self.spill.as_mut()) {
+            (QueueOverflow::Block, _) => self.held.push_back(prepare(notification)),
+            (QueueOverflow::Spill, Some(spill)) => {
+                if let Err(e) = spill.push(prepare(notification)) {
+                    warn!("Unable to spill notification to disk: {}", e);
+                    self.dropped += 1;
+                }
+            }
+            _ => {
+                self.held.pop_front();
+                self.dropped += 1;
+                self.held.push_back(prepare(notification));
+            }
+        }
+    }
+
+    /// Send held and spilled notifications while there is room in the queue.
+    pub fn flush<F>(&mut self, prepare: F)
+    where
+        F: Fn(ValidatedNotification) -> ValidatedNotification,
+    {
+        let mut unspilled = false;
+        while self.has_room() {
+            let next = match self.held.pop_front() {
+                Some(notification) => Some(notification),
+                None => self.spill.as_mut().and_then(|spill| match spill.pop() {
+                    Ok(notification) => {
+                        unspilled |= notification.is_some();
+                        notification
+                    }
+                    Err(e) => {
+                        warn!("Unable to read spilled notification from disk: {}", e);
+                        unspilled = true;
+                        None
+                    }
+                }),
+            };
+
+            match next {
+                Some(notification) => self.broadcast(prepare(notification)),
+                None => break,
+            }
+        }
+
+        if let Some(Err(e)) = self.spill.as_mut().filter(|_| unspilled).map(SpillFile::commit) {
+            warn!("Unable to record sent spilled notifications: {}", e);
+        }
+    }
+
+    /// Return if no more notifications should be sent until there is room in the queue.
+    ///
+    /// While the endpoint is paused that is once the held notifications fill the queue with the block policy.
+    pub fn is_blocked(&self) -> bool {
+        match self.paused {
+            true => self.overflow == QueueOverflow::Block && self.held.len() >= self.capacity,
+            false => !self.held.is_empty(),
+        }
+    }
+
+    /// Return when the queue should next be checked for room.
+    pub fn next_deadline(&self, now: Instant) -> Option<Instant> {
+        match self.pending() {
+            _ if self.paused => None,
+            0 => None,
+            _ => Some(now + ROOM_CHECK_INTERVAL),
+        }
+    }
+
+    /// Return the total number of notifications dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Take the notifications held in memory until there is room in the queue, spilled notifications stay on disk.
+    pub fn take_held(&mut self) -> Vec<ValidatedNotification> {
+        self.held.drain(..).collect()
+    }
+
+    /// Return the number of notifications held or spilled.
+    pub fn pending(&self) -> usize {
+        self.held.len() + self.spill.as_ref().map(|spill| spill.pending).unwrap_or_default()
+    }
+
+    fn has_room(&self) -> bool {
+        !self.paused && self.channel.len() < self.capacity
+    }
+
+    fn broadcast(&self, notification: ValidatedNotification) {
+        match self.channel.send(notification) {
+            Ok(ok) => debug!("Message sent to endpoint. Subscribers: {}", ok),
+            Err(e) => warn!("Error sending validated message to endpoint: {}", e),
+        }
+    }
+}
+
+impl SpillFile {
+    /// Open the spill file at `path`, counting the notifications left in it after those already sent.
+    fn open(path: &Path) -> Result<Self, Error> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let writer = OpenOptions::new().create(true).append(true).open(path)?;
+        let offset_path = offset_path(path);
+        let offset = std::fs::read_to_string(&offset_path)
+            .ok()
+            .and_then(|offset| offset.trim().parse().ok())
+            .filter(|offset| *offset <= writer.metadata().map(|metadata| metadata.len()).unwrap_or_default())
+            .unwrap_or_default();
+
+        let mut reader = BufReader::new(File::open(path)?);
+        reader.seek(SeekFrom::Start(offset))?;
+        let pending = (&mut reader).lines().map_while(Result::ok).filter(|l| !l.is_empty()).count();
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok(Self { writer, reader, offset_path, offset, pending, inherited: pending, receipts: VecDeque::new() })
+    }
+
+    /// Write the notification to the end of the file and keep its [`Receipt`] in memory until it is read again.
+    fn push(&mut self, notification: ValidatedNotification) -> Result<(), Error> {
+        let spilled =
+            SpilledNotification { sub_name: notification.sub_name().into(), message: notification.message().clone() };
+        writeln!(self.writer, "{}", serde_json::to_string(&spilled)?)?;
+        self.pending += 1;
+        self.receipts.push_back(notification.receipt().cloned());
+        Ok(())
+    }
+
+    /// Read the next spilled notification, [`SpillFile::commit`] records that it was sent.
+    fn pop(&mut self) -> Result<Option<ValidatedNotification>, Error> {
+        let mut line = String::new();
+        while self.pending > 0 {
+            line.clear();
+            let read = self.reader.read_line(&mut line)?;
+            if read == 0 {
+                self.pending = 0;
+                self.inherited = 0;
+                self.receipts.clear();
+                break;
+            }
+            self.offset += read as u64;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            self.pending -= 1;
+            // Notifications spilled before the server started are tracked again when they are sent
+            let receipt = match self.inherited {
+                0 => self.receipts.pop_front().flatten(),
+                _ => {
+                    self.inherited -= 1;
+                    None
+                }
+            };
+            let spilled: SpilledNotification = serde_json::from_str(line.trim())?;
+            let notification = ValidatedNotification::new(spilled.sub_name, spilled.message);
+            return Ok(Some(match receipt {
+                Some(receipt) => notification.with_receipt(receipt),
+                None => notification,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Record how far the spilled notifications were sent so a restart does not send them again, and empty the
+    /// file once they all are.
+    fn commit(&mut self) -> Result<(), Error> {
+        if self.pending == 0 {
+            self.writer.set_len(0)?;
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.offset = 0;
+            return match std::fs::remove_file(&self.offset_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+
+        // Replace the offset file as a whole so a crash leaves either the old or the new offset
+        let temporary = self.offset_path.with_extension("offset.tmp");
+        std::fs::write(&temporary, self.offset.to_string())?;
+        std::fs::rename(&temporary, &self.offset_path)?;
+        Ok(())
+    }
+}
+
+/// Return the path of the file recording how far the spill file at `path` was sent.
+fn offset_path(path: &Path) -> PathBuf {
+    let mut offset_path = path.as_os_str().to_owned();
+    offset_path.push(".offset");
+    PathBuf::from(offset_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spill_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(offset_path(&path));
+        path
+    }
+
+    fn text(notification: Option<ValidatedNotification>) -> String {
+        notification.unwrap().message().text().to_string()
+    }
+
+    #[test]
+    fn queue_size_must_be_greater_than_zero() {
+        assert!(Queue::new(0).is_err());
+        assert_eq!(Queue::new(1).unwrap().size(), 1);
+    }
+
+    #[test]
+    fn spill_resumes_after_sent_notifications() {
+        let path = spill_file("pass-it-on-spill-resume-test.spill");
+        let mut spill = SpillFile::open(&path).unwrap();
+        for text in ["1", "2", "3"] {
+            spill.push(ValidatedNotification::new("test", Message::new(text))).unwrap();
+        }
+        assert_eq!(text(spill.pop().unwrap()), "1");
+        spill.commit().unwrap();
+        // Read but not committed, so it is sent again after a restart
+        assert_eq!(text(spill.pop().unwrap()), "2");
+        drop(spill);
+
+        let mut spill = SpillFile::open(&path).unwrap();
+        assert_eq!(spill.pending, 2);
+        assert_eq!(text(spill.pop().unwrap()), "2");
+        assert_eq!(text(spill.pop().unwrap()), "3");
+        spill.commit().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        assert!(!offset_path(&path).exists());
+        assert!(spill.pop().unwrap().is_none());
+    }
+
+    fn paused_outbox(overflow: QueueOverflow) -> (Outbox, broadcast::Receiver<ValidatedNotification>) {
+        let (channel, receiver) = broadcast::channel(2);
+        let mut outbox = Outbox::new(&Queue::new(2).unwrap().with_overflow(overflow), channel).unwrap();
+        outbox.set_paused(true);
+        for text in ["1", "2", "3"] {
+            outbox.send(ValidatedNotification::new("test", Message::new(text)), |n| n);
+        }
+        (outbox, receiver)
+    }
+
+    #[test]
+    fn paused_outbox_drops_oldest_once_queue_is_full() {
+        let (mut outbox, mut receiver) = paused_outbox(QueueOverflow::DropOldest);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!((outbox.pending(), outbox.dropped(), outbox.is_blocked()), (2, 1, false));
+
+        outbox.set_paused(false);
+        outbox.flush(|n| n);
+        assert_eq!(text(receiver.try_recv().ok()), "2");
+        assert_eq!(text(receiver.try_recv().ok()), "3");
+        assert_eq!(outbox.pending(), 0);
+    }
+
+    #[test]
+    fn paused_outbox_blocks_once_queue_is_full() {
+        let (outbox, _receiver) = paused_outbox(QueueOverflow::Block);
+        assert_eq!((outbox.pending(), outbox.dropped(), outbox.is_blocked()), (3, 0, true));
+    }
+
+    #[test]
+    fn paused_outbox_prepares_notifications_when_they_arrive() {
+        let (channel, _receiver) = broadcast::channel(2);
+        let mut outbox = Outbox::new(&Queue::new(2).unwrap(), channel).unwrap();
+        outbox.set_paused(true);
+        outbox.send(ValidatedNotification::new("test", Message::new("1")), |n| {
+            ValidatedNotification::new("prepared", n.message().clone())
+        });
+        assert_eq!(outbox.take_held()[0].sub_name(), "prepared");
+    }
+
+    #[test]
+    fn spilled_notification_keeps_its_receipt() {
+        let path = spill_file("pass-it-on-spill-receipt-test.spill");
+        let (channel, mut receiver) = broadcast::channel(1);
+        let queue = Queue::new(1).unwrap().with_overflow(QueueOverflow::Spill).with_spill_path(Some(&path));
+        let mut outbox = Outbox::new(&queue, channel).unwrap();
+        let (outcomes, mut outcomes_rx) = tokio::sync::mpsc::unbounded_channel();
+        let next_id = std::cell::Cell::new(1);
+        let track = |notification: ValidatedNotification| match notification.has_receipt() {
+            true => notification,
+            false => {
+                next_id.set(next_id.get() + 1);
+                notification.with_receipt(Receipt::new("file", next_id.get() - 1, outcomes.clone()))
+            }
+        };
+
+        // Like a notification replayed from the spool, which already has its identifier in the journal
+        let replayed = ValidatedNotification::new("test", Message::new("2"));
+        outbox.send(ValidatedNotification::new("test", Message::new("1")), track);
+        outbox.send(replayed.with_receipt(Receipt::new("file", 20, outcomes.clone())), track);
+        outbox.send(ValidatedNotification::new("test", Message::new("3")), track);
+        assert_eq!(outbox.pending(), 2);
+
+        for _ in 0..3 {
+            outbox.flush(track);
+            receiver.try_recv().unwrap().report_delivery(Ok(()));
+        }
+        let ids: Vec<_> = std::iter::from_fn(|| outcomes_rx.try_recv().ok()).map(|outcome| outcome.id()).collect();
+        assert_eq!(ids, vec![1, 20, 2]);
+    }
+}
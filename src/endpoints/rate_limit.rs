@@ -0,0 +1,286 @@
+//! Rate limiting of notifications passed to an endpoint
+//!
+//! Each endpoint can be given a token bucket that allows up to `max_messages` notifications every `period_secs`.
+//! Notifications that arrive while the bucket is empty are handled by the `overflow` policy:
+//!
+//! | Overflow | Description                                                                                  |
+//! |----------|----------------------------------------------------------------------------------------------|
+//! | drop     | Drop the notification and log a warning with the number of dropped notifications. (default) |
+//! | queue    | Hold the notification until the bucket has capacity again.                                   |
+//! | digest   | Collect the notifications and send them as a single message once the bucket has capacity.   |
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.endpoint]]
+//! type = "discord"
+//! url = "https://discord.com/api/webhooks/webhook_id/webhook_token"
+//! notifications = ["notification_id1"]
+//!
+//! [server.endpoint.rate_limit]
+//! max_messages = 5
+//! period_secs = 60
+//! overflow = "digest"
+//! ```
+
+use crate::endpoints::batch::combine;
+use crate::endpoints::Stage;
+use crate::notifications::ValidatedNotification;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Tolerance for floating point error when a token is due.
+const TOKEN_EPSILON: f64 = 1e-6;
+/// Maximum number of notifications held by the queue and digest policies before the oldest are dropped.
+const MAX_PENDING: usize = 1000;
+
+/// Token bucket settings that limit how many notifications are passed to an endpoint.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct RateLimit {
+    max_messages: u32,
+    period: Duration,
+    overflow: Overflow,
+}
+
+/// Policy for notifications that exceed a [`RateLimit`].
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    /// Drop the notification.
+    #[default]
+    Drop,
+    /// Hold the notification until there is capacity.
+    Queue,
+    /// Collect the notifications into a single digest message sent when there is capacity.
+    Digest,
+}
+
+/// Data structure to represent [`RateLimit`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RateLimitConfigFile {
+    max_messages: u32,
+    period_secs: u64,
+    #[serde(default)]
+    overflow: Overflow,
+}
+
+/// Token bucket that applies a [`RateLimit`] to notifications.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+    pending: VecDeque<ValidatedNotification>,
+    dropped: u64,
+}
+
+impl RateLimit {
+    /// Create a new `RateLimit` that allows `max_messages` every `period`, both must be greater than 0.
+    pub fn new(max_messages: u32, period: Duration, overflow: Overflow) -> Result<Self, Error> {
+        if max_messages == 0 || period.is_zero() {
+            return Err(Error::invalid_endpoint_configuration(
+                "rate_limit max_messages and period_secs must be greater than 0".to_string(),
+            ));
+        }
+
+        Ok(Self { max_messages, period, overflow })
+    }
+
+    /// Return the maximum number of notifications allowed in a period.
+    pub fn max_messages(&self) -> u32 {
+        self.max_messages
+    }
+
+    /// Return the period over which `max_messages` are allowed.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Return the [`Overflow`] policy.
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    fn tokens_per_second(&self) -> f64 {
+        self.max_messages as f64 / self.period.as_secs_f64()
+    }
+}
+
+impl TryFrom<&RateLimitConfigFile> for RateLimit {
+    type Error = Error;
+
+    fn try_from(value: &RateLimitConfigFile) -> Result<Self, Self::Error> {
+        RateLimit::new(value.max_messages, Duration::from_secs(value.period_secs), value.overflow)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self { limit, tokens: limit.max_messages as f64, updated: now, pending: VecDeque::new(), dropped: 0 }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.tokens_per_second()).min(self.limit.max_messages as f64);
+        self.updated = now;
+    }
+
+    fn take(&mut self) -> bool {
+        if self.tokens >= 1.0 - TOKEN_EPSILON {
+            self.tokens = (self.tokens - 1.0).max(0.0);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn hold(&mut self, notification: ValidatedNotification) {
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+            self.dropped += 1;
+        }
+        self.pending.push_back(notification);
+    }
+
+    /// Collapse all pending notifications into one digest per sub-group.
+    fn digest(&mut self) -> Vec<ValidatedNotification> {
+        let mut groups: BTreeMap<String, Vec<ValidatedNotification>> = BTreeMap::new();
+        for notification in self.pending.drain(..) {
+            groups.entry(notification.sub_name().to_string()).or_default().push(notification);
+        }
+
+        groups
+            .into_iter()
+            .map(|(sub_name, notifications)| combine(sub_name.as_str(), notifications, "rate limited notifications"))
+            .collect()
+    }
+}
+
+impl Stage for RateLimiter {
+    fn name(&self) -> &'static str {
+        "rate limit"
+    }
+
+    fn push(&mut self, notification: ValidatedNotification, now: Instant) -> Vec<ValidatedNotification> {
+        self.refill(now);
+        if self.pending.is_empty() && self.take() {
+            return vec![notification];
+        }
+
+        match self.limit.overflow {
+            Overflow::Drop => self.dropped += 1,
+            Overflow::Queue | Overflow::Digest => self.hold(notification),
+        }
+        Vec::new()
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<ValidatedNotification> {
+        self.refill(now);
+        let mut ready = Vec::new();
+        match self.limit.overflow {
+            Overflow::Drop => (),
+            Overflow::Queue => {
+                while !self.pending.is_empty() && self.take() {
+                    ready.extend(self.pending.pop_front());
+                }
+            }
+            Overflow::Digest => {
+                if !self.pending.is_empty() && self.take() {
+                    ready = self.digest();
+                }
+            }
+        }
+        ready
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let missing = (1.0 - self.tokens).max(0.0);
+        Some(self.updated + Duration::from_secs_f64(missing / self.limit.tokens_per_second()))
+    }
+
+    fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn take_pending(&mut self) -> Vec<ValidatedNotification> {
+        self.pending.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::Message;
+
+    fn notification(text: &str) -> ValidatedNotification {
+        ValidatedNotification::new("test", Message::new(text))
+    }
+
+    #[test]
+    fn limit_must_allow_messages() {
+        assert!(RateLimit::new(0, Duration::from_secs(60), Overflow::Drop).is_err());
+        assert!(RateLimit::new(5, Duration::ZERO, Overflow::Drop).is_err());
+    }
+
+    #[test]
+    fn bucket_refills_over_the_period() {
+        let start = Instant::now();
+        let limit = RateLimit::new(2, Duration::from_secs(10), Overflow::Drop).unwrap();
+        let mut limiter = RateLimiter::new(limit, start);
+
+        assert_eq!(limiter.push(notification("1"), start).len(), 1);
+        assert_eq!(limiter.push(notification("2"), start).len(), 1);
+        assert!(limiter.push(notification("3"), start).is_empty());
+        assert_eq!(limiter.dropped(), 1);
+
+        // One token every 5 seconds
+        assert!(limiter.push(notification("4"), start + Duration::from_secs(4)).is_empty());
+        assert_eq!(limiter.push(notification("5"), start + Duration::from_secs(10)).len(), 1);
+    }
+
+    #[test]
+    fn queue_deadline_is_when_a_token_is_due() {
+        let start = Instant::now();
+        let limit = RateLimit::new(1, Duration::from_secs(10), Overflow::Queue).unwrap();
+        let mut limiter = RateLimiter::new(limit, start);
+
+        assert_eq!(limiter.next_deadline(), None);
+        assert_eq!(limiter.push(notification("1"), start).len(), 1);
+        assert!(limiter.push(notification("2"), start + Duration::from_secs(4)).is_empty());
+        assert_eq!(limiter.next_deadline(), Some(start + Duration::from_secs(10)));
+
+        assert!(limiter.poll(start + Duration::from_secs(9)).is_empty());
+        let ready = limiter.poll(start + Duration::from_secs(10));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].message().text(), "2");
+        assert_eq!(limiter.next_deadline(), None);
+    }
+
+    #[test]
+    fn digest_combines_held_notifications() {
+        let start = Instant::now();
+        let limit = RateLimit::new(1, Duration::from_secs(10), Overflow::Digest).unwrap();
+        let mut limiter = RateLimiter::new(limit, start);
+
+        limiter.push(notification("1"), start);
+        limiter.push(notification("2"), start);
+        limiter.push(notification("3"), start);
+        assert_eq!(limiter.pending(), 2);
+
+        let ready = limiter.poll(start + Duration::from_secs(10));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(limiter.pending(), 0);
+    }
+}
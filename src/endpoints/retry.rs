@@ -0,0 +1,242 @@
+//! Retrying failed deliveries with exponential backoff
+//!
+//! Endpoints that deliver over the network retry failed attempts up to `max_attempts` times in total.
+//! The wait before each retry starts at `initial_backoff_secs` and doubles after every attempt up to
+//! `max_backoff_secs`. With `jitter` enabled the wait is randomly reduced by up to half so that many failed
+//! deliveries do not retry at the same moment. A wait requested by the service, like a Discord rate limit, is used
+//! instead when there is one, up to `max_backoff_secs`. Failures that cannot succeed on a retry, like an invalid
+//! webhook, are not retried. A retry that is waiting gives up when the server shuts down.
+//!
+//! Without a `retry` table an endpoint makes 3 attempts with a backoff from 1 to 60 seconds and jitter.
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.endpoint]]
+//! type = "discord"
+//! url = "https://discord.com/api/webhooks/webhook_id/webhook_token"
+//! notifications = ["notification_id1"]
+//!
+//! [server.endpoint.retry]
+//! max_attempts = 5
+//! initial_backoff_secs = 2
+//! max_backoff_secs = 300
+//! jitter = true
+//! ```
+
+#[cfg(any(feature = "discord", feature = "email", feature = "matrix"))]
+use crate::endpoints::delivery::DeliveryError;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "discord", feature = "email", feature = "matrix"))]
+use std::future::Future;
+use std::time::Duration;
+#[cfg(any(feature = "discord", feature = "email", feature = "matrix"))]
+use tokio::sync::watch;
+#[cfg(any(feature = "discord", feature = "email", feature = "matrix"))]
+use tracing::{error, warn};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Settings for retrying a failed delivery.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Retry {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+/// Data structure to represent [`Retry`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RetryConfigFile {
+    max_attempts: Option<u32>,
+    initial_backoff_secs: Option<u64>,
+    max_backoff_secs: Option<u64>,
+    jitter: Option<bool>,
+}
+
+impl Retry {
+    /// Create a new `Retry` that makes up to `max_attempts` with a backoff from `initial_backoff` to `max_backoff`.
+    pub fn new(max_attempts: u32, initial_backoff: Duration, max_backoff: Duration, jitter: bool) -> Self {
+        Self { max_attempts, initial_backoff, max_backoff, jitter }
+    }
+
+    /// Return the maximum number of attempts including the first.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Return the wait before the first retry.
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+
+    /// Return the longest wait between attempts.
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+
+    /// Return if the wait between attempts is randomized.
+    pub fn jitter(&self) -> bool {
+        self.jitter
+    }
+
+    /// Return the wait after the failed `attempt`, starting at 1.
+    #[cfg(any(feature = "discord", feature = "email", feature = "matrix"))]
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self.initial_backoff.saturating_mul(factor).min(self.max_backoff);
+        match self.jitter {
+            true => backoff.mul_f64(0.5 + fastrand::f64() / 2.0),
+            false => backoff,
+        }
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ATTEMPTS, DEFAULT_INITIAL_BACKOFF, DEFAULT_MAX_BACKOFF, true)
+    }
+}
+
+impl TryFrom<&RetryConfigFile> for Retry {
+    type Error = Error;
+
+    fn try_from(value: &RetryConfigFile) -> Result<Self, Self::Error> {
+        let max_attempts = value.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS);
+        if max_attempts == 0 {
+            return Err(Error::invalid_endpoint_configuration("retry max_attempts must be greater than 0".to_string()));
+        }
+
+        let initial_backoff = value.initial_backoff_secs.map(Duration::from_secs).unwrap_or(DEFAULT_INITIAL_BACKOFF);
+        let max_backoff = value.max_backoff_secs.map(Duration::from_secs).unwrap_or(DEFAULT_MAX_BACKOFF);
+        if max_backoff < initial_backoff {
+            return Err(Error::invalid_endpoint_configuration(
+                "retry max_backoff_secs must not be less than initial_backoff_secs".to_string(),
+            ));
+        }
+
+        Ok(Retry::new(max_attempts, initial_backoff, max_backoff, value.jitter.unwrap_or(true)))
+    }
+}
+
+/// Make delivery `attempt`s until one succeeds, a permanent failure or [`Retry::max_attempts`] is reached.
+///
+/// Failed attempts are logged as warnings and a delivery that is given up on is logged as an error with
+/// the `description`. The wait before a retry is never longer than [`Retry::max_backoff`], even when the service
+/// asks for more, and ends early on `shutdown` with the last failure.
+#[cfg(any(feature = "discord", feature = "email", feature = "matrix"))]
+pub(crate) async fn deliver<F, Fut>(
+    retry: &Retry,
+    description: &str,
+    shutdown: &watch::Receiver<bool>,
+    mut attempt: F,
+) -> Result<(), DeliveryError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), DeliveryError>>,
+{
+    let mut shutdown = shutdown.clone();
+    let mut attempts = 1;
+    loop {
+        match attempt().await {
+            Ok(()) => return Ok(()),
+            Err(e) if e.is_permanent() || attempts >= retry.max_attempts => {
+                error!("Giving up on {} after {} attempt(s): {}", description, attempts, e);
+                return Err(e);
+            }
+            Err(e) => {
+                let wait = match e.retry_after() {
+                    Some(retry_after) => retry_after.min(retry.max_backoff),
+                    None => retry.backoff(attempts),
+                };
+                warn!(
+                    "Attempt {} of {} for {} failed, retrying in {:.1}s: {}",
+                    attempts,
+                    retry.max_attempts,
+                    description,
+                    wait.as_secs_f64(),
+                    e
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => attempts += 1,
+                    _ = shutdown.changed() => {
+                        warn!("Stopped retrying {} after {} attempt(s) for shutdown: {}", description, attempts, e);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "discord", feature = "email", feature = "matrix")))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const MILLISECOND: Duration = Duration::from_millis(1);
+
+    async fn attempts(retry: &Retry, shutdown: &watch::Receiver<bool>, error: DeliveryError) -> (u32, bool) {
+        let attempts = AtomicU32::new(0);
+        let delivered = deliver(retry, "test", shutdown, || async {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err(error.clone())
+        });
+        let delivered = tokio::time::timeout(Duration::from_secs(5), delivered).await.expect("deliver did not finish");
+        (attempts.load(Ordering::Relaxed), delivered.is_ok())
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let retry = Retry::new(10, Duration::from_secs(1), Duration::from_secs(5), false);
+        let backoff: Vec<_> = (1..=5).map(|attempt| retry.backoff(attempt).as_secs()).collect();
+        assert_eq!(backoff, vec![1, 2, 4, 5, 5]);
+    }
+
+    #[test]
+    fn jitter_reduces_backoff_by_up_to_half() {
+        let retry = Retry::new(10, Duration::from_secs(4), Duration::from_secs(60), true);
+        for _ in 0..100 {
+            let backoff = retry.backoff(2);
+            assert!(backoff >= Duration::from_secs(4) && backoff <= Duration::from_secs(8));
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried_up_to_max_attempts() {
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let retry = Retry::new(3, MILLISECOND, MILLISECOND, false);
+        assert_eq!(attempts(&retry, &shutdown, DeliveryError::transient("busy")).await, (3, false));
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried() {
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let retry = Retry::new(3, MILLISECOND, MILLISECOND, false);
+        assert_eq!(attempts(&retry, &shutdown, DeliveryError::permanent("invalid")).await, (1, false));
+    }
+
+    #[tokio::test]
+    async fn retry_after_is_limited_to_max_backoff() {
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let retry = Retry::new(2, MILLISECOND, MILLISECOND, false);
+        let error = DeliveryError::transient("rate limited").with_retry_after(Some(Duration::from_secs(3600)));
+        assert_eq!(attempts(&retry, &shutdown, error).await, (2, false));
+    }
+
+    #[tokio::test]
+    async fn shutdown_ends_the_wait() {
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let retry = Retry::new(3, Duration::from_secs(3600), Duration::from_secs(3600), false);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let _ = shutdown_tx.send(true);
+        });
+        assert_eq!(attempts(&retry, &shutdown, DeliveryError::transient("busy")).await, (1, false));
+    }
+}
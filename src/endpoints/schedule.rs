@@ -0,0 +1,468 @@
+//! Time windows when an endpoint is active
+//!
+//! An endpoint with a schedule only receives notifications during its windows. Notifications that arrive outside
+//! every window are handled by the `outside` policy:
+//!
+//! | Outside  | Description                                                                             |
+//! |----------|-----------------------------------------------------------------------------------------|
+//! | hold     | Hold the notifications and send them when the next window opens. (default)             |
+//! | drop     | Drop the notifications.                                                                 |
+//! | fallback | Send the notifications to every sub-group of the endpoint named by `fallback` instead. |
+//!
+//! Notifications with a priority of at least `bypass_priority`, critical by default, are always sent.
+//! The `timezone` is an IANA time zone name and the local time zone of the server is used when it is not set.
+//! Window `days` are written like `mon` or `monday` and a window applies to every day when `days` is not set.
+//! A window with an `end` before its `start` runs past midnight into the next day.
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.endpoint]]
+//! type = "discord"
+//! name = "discord-pager"
+//! url = "https://discord.com/api/webhooks/webhook_id/webhook_token"
+//! notifications = ["notification_id1"]
+//!
+//! [server.endpoint.schedule]
+//! timezone = "America/Edmonton"
+//! outside = "fallback"
+//! fallback = "email-digest"
+//!
+//! [[server.endpoint.schedule.window]]
+//! days = ["mon", "tue", "wed", "thu", "fri"]
+//! start = "08:00"
+//! end = "22:00"
+//!
+//! [[server.endpoint.schedule.window]]
+//! days = ["sat", "sun"]
+//! start = "10:00"
+//! end = "20:00"
+//! ```
+
+use crate::endpoints::Stage;
+use crate::notifications::{Priority, ValidatedNotification};
+use crate::Error;
+use jiff::civil::{Time, Weekday};
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, ToSpan};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Maximum number of notifications held outside the windows before the oldest are dropped.
+const MAX_HELD: usize = 1000;
+
+/// Time windows when an endpoint is active and the policy for notifications outside of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    timezone: TimeZone,
+    windows: Vec<TimeWindow>,
+    outside: Outside,
+    fallback: Option<String>,
+    bypass_priority: Priority,
+}
+
+/// A daily window of time on a set of days.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TimeWindow {
+    days: Vec<Weekday>,
+    start: Time,
+    end: Time,
+}
+
+/// Policy for notifications outside of a [`Schedule`].
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Outside {
+    /// Hold notifications until the next window opens.
+    #[default]
+    Hold,
+    /// Drop notifications.
+    Drop,
+    /// Send notifications to the fallback endpoint.
+    Fallback,
+}
+
+/// Data structure to represent [`Schedule`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ScheduleConfigFile {
+    timezone: Option<String>,
+    #[serde(default)]
+    window: Vec<TimeWindowConfigFile>,
+    #[serde(default)]
+    outside: Outside,
+    fallback: Option<String>,
+    bypass_priority: Option<Priority>,
+}
+
+/// Data structure to represent [`TimeWindow`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TimeWindowConfigFile {
+    #[serde(default)]
+    days: Vec<String>,
+    start: String,
+    end: String,
+}
+
+/// Applies a [`Schedule`] to notifications.
+#[derive(Debug)]
+pub(crate) struct Scheduler {
+    schedule: Schedule,
+    held: VecDeque<ValidatedNotification>,
+    rerouted: Vec<ValidatedNotification>,
+    next_open: Option<Instant>,
+    dropped: u64,
+}
+
+impl Schedule {
+    /// Create a new `Schedule` that holds notifications outside `windows` and lets critical notifications through.
+    pub fn new(timezone: TimeZone, windows: Vec<TimeWindow>) -> Self {
+        Self { timezone, windows, outside: Outside::default(), fallback: None, bypass_priority: Priority::Critical }
+    }
+
+    /// Set the [`Outside`] policy.
+    pub fn with_outside(mut self, outside: Outside) -> Self {
+        self.outside = outside;
+        self
+    }
+
+    /// Set the name of the endpoint used by [`Outside::Fallback`].
+    pub fn with_fallback<S: AsRef<str>>(mut self, fallback: Option<S>) -> Self {
+        self.fallback = fallback.map(|name| name.as_ref().into());
+        self
+    }
+
+    /// Set the lowest [`Priority`] that is sent regardless of the windows.
+    pub fn with_bypass_priority(mut self, priority: Priority) -> Self {
+        self.bypass_priority = priority;
+        self
+    }
+
+    /// Return the time zone the windows are in.
+    pub fn timezone(&self) -> &TimeZone {
+        &self.timezone
+    }
+
+    /// Return the [`TimeWindow`]s.
+    pub fn windows(&self) -> &[TimeWindow] {
+        &self.windows
+    }
+
+    /// Return the [`Outside`] policy.
+    pub fn outside(&self) -> Outside {
+        self.outside
+    }
+
+    /// Return the name of the fallback endpoint.
+    pub fn fallback(&self) -> Option<&str> {
+        self.fallback.as_deref()
+    }
+
+    /// Return the lowest [`Priority`] that is sent regardless of the windows.
+    pub fn bypass_priority(&self) -> Priority {
+        self.bypass_priority
+    }
+
+    /// Return if `timestamp` is inside one of the windows.
+    pub fn is_active(&self, timestamp: Timestamp) -> bool {
+        let local = timestamp.to_zoned(self.timezone.clone());
+        let day = local.weekday();
+        let time = local.time();
+        self.windows.iter().any(|window| window.contains(day, time))
+    }
+
+    /// Return the next time after `timestamp` that a window opens.
+    pub fn next_open(&self, timestamp: Timestamp) -> Option<Timestamp> {
+        let local = timestamp.to_zoned(self.timezone.clone());
+        let today = local.date();
+        (0..=7i64)
+            .filter_map(|offset| today.checked_add(offset.days()).ok())
+            .flat_map(|date| {
+                self.windows
+                    .iter()
+                    .filter(move |window| window.applies_to(date.weekday()))
+                    .filter_map(move |window| date.to_datetime(window.start).to_zoned(self.timezone.clone()).ok())
+            })
+            .map(|zoned| zoned.timestamp())
+            .filter(|start| *start > timestamp)
+            .min()
+    }
+}
+
+impl TimeWindow {
+    /// Create a new `TimeWindow` from `start` to `end` on `days`, every day when `days` is empty.
+    pub fn new(days: Vec<Weekday>, start: Time, end: Time) -> Self {
+        Self { days, start, end }
+    }
+
+    /// Return the days the window starts on.
+    pub fn days(&self) -> &[Weekday] {
+        &self.days
+    }
+
+    /// Return the local time the window starts.
+    pub fn start(&self) -> Time {
+        self.start
+    }
+
+    /// Return the local time the window ends.
+    pub fn end(&self) -> Time {
+        self.end
+    }
+
+    fn applies_to(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn contains(&self, day: Weekday, time: Time) -> bool {
+        if self.start < self.end {
+            self.applies_to(day) && time >= self.start && time < self.end
+        } else if self.start > self.end {
+            (self.applies_to(day) && time >= self.start) || (self.applies_to(day.previous()) && time < self.end)
+        } else {
+            self.applies_to(day)
+        }
+    }
+}
+
+impl TryFrom<&ScheduleConfigFile> for Schedule {
+    type Error = Error;
+
+    fn try_from(value: &ScheduleConfigFile) -> Result<Self, Self::Error> {
+        let timezone = match value.timezone.as_ref() {
+            None => TimeZone::system(),
+            Some(name) => TimeZone::get(name).map_err(|error| {
+                Error::invalid_endpoint_configuration(format!("schedule timezone {} is invalid: {}", name, error))
+            })?,
+        };
+
+        if value.window.is_empty() {
+            return Err(Error::invalid_endpoint_configuration("schedule has no windows setup".to_string()));
+        }
+
+        if value.outside == Outside::Fallback && value.fallback.is_none() {
+            return Err(Error::invalid_endpoint_configuration(
+                "schedule outside is fallback but no fallback endpoint is set".to_string(),
+            ));
+        }
+
+        let windows = value.window.iter().map(TimeWindow::try_from).collect::<Result<Vec<_>, _>>()?;
+        Ok(Schedule::new(timezone, windows)
+            .with_outside(value.outside)
+            .with_fallback(value.fallback.as_ref())
+            .with_bypass_priority(value.bypass_priority.unwrap_or(Priority::Critical)))
+    }
+}
+
+impl TryFrom<&TimeWindowConfigFile> for TimeWindow {
+    type Error = Error;
+
+    fn try_from(value: &TimeWindowConfigFile) -> Result<Self, Self::Error> {
+        let days = value.days.iter().map(|day| parse_weekday(day)).collect::<Result<Vec<_>, _>>()?;
+        Ok(TimeWindow::new(days, parse_time(value.start.as_str())?, parse_time(value.end.as_str())?))
+    }
+}
+
+fn parse_weekday(day: &str) -> Result<Weekday, Error> {
+    match day.to_lowercase().as_str() {
+        "mon" | "monday" => Ok(Weekday::Monday),
+        "tue" | "tuesday" => Ok(Weekday::Tuesday),
+        "wed" | "wednesday" => Ok(Weekday::Wednesday),
+        "thu" | "thursday" => Ok(Weekday::Thursday),
+        "fri" | "friday" => Ok(Weekday::Friday),
+        "sat" | "saturday" => Ok(Weekday::Saturday),
+        "sun" | "sunday" => Ok(Weekday::Sunday),
+        _ => Err(Error::invalid_endpoint_configuration(format!("schedule day {} is invalid", day))),
+    }
+}
+
+fn parse_time(time: &str) -> Result<Time, Error> {
+    time.parse()
+        .map_err(|error| Error::invalid_endpoint_configuration(format!("schedule time {} is invalid: {}", time, error)))
+}
+
+impl Scheduler {
+    pub fn new(schedule: Schedule) -> Self {
+        Self { schedule, held: VecDeque::new(), rerouted: Vec::new(), next_open: None, dropped: 0 }
+    }
+
+    /// Apply the schedule to a notification that arrives at the `now` instant and wall clock `timestamp`.
+    fn push_at(
+        &mut self,
+        notification: ValidatedNotification,
+        now: Instant,
+        timestamp: Timestamp,
+    ) -> Vec<ValidatedNotification> {
+        if notification.message().priority() >= self.schedule.bypass_priority || self.schedule.is_active(timestamp) {
+            return vec![notification];
+        }
+
+        match self.schedule.outside {
+            Outside::Hold => self.hold(notification, now, timestamp),
+            Outside::Drop => self.dropped += 1,
+            Outside::Fallback => self.rerouted.push(notification),
+        }
+        Vec::new()
+    }
+
+    /// Release the held notifications when a window is open at the `now` instant and wall clock `timestamp`.
+    fn poll_at(&mut self, now: Instant, timestamp: Timestamp) -> Vec<ValidatedNotification> {
+        if self.held.is_empty() {
+            self.next_open = None;
+            return Vec::new();
+        }
+
+        if self.schedule.is_active(timestamp) {
+            self.next_open = None;
+            self.held.drain(..).collect()
+        } else {
+            self.next_open = self.next_open_instant(now, timestamp);
+            Vec::new()
+        }
+    }
+
+    fn hold(&mut self, notification: ValidatedNotification, now: Instant, timestamp: Timestamp) {
+        if self.held.len() >= MAX_HELD {
+            self.held.pop_front();
+            self.dropped += 1;
+        }
+        self.held.push_back(notification);
+        if self.next_open.is_none() {
+            self.next_open = self.next_open_instant(now, timestamp);
+        }
+    }
+
+    fn next_open_instant(&self, now: Instant, timestamp: Timestamp) -> Option<Instant> {
+        let next_open = self.schedule.next_open(timestamp)?;
+        let wait = Duration::try_from(next_open.duration_since(timestamp)).unwrap_or_default();
+        Some(now + wait)
+    }
+}
+
+impl Stage for Scheduler {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn push(&mut self, notification: ValidatedNotification, now: Instant) -> Vec<ValidatedNotification> {
+        self.push_at(notification, now, Timestamp::now())
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<ValidatedNotification> {
+        self.poll_at(now, Timestamp::now())
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.next_open
+    }
+
+    fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn pending(&self) -> usize {
+        self.held.len()
+    }
+
+    fn take_rerouted(&mut self) -> Vec<ValidatedNotification> {
+        std::mem::take(&mut self.rerouted)
+    }
+
+    fn take_pending(&mut self) -> Vec<ValidatedNotification> {
+        self.next_open = None;
+        self.held.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::Message;
+    use jiff::Zoned;
+
+    fn schedule(days: Vec<Weekday>, start: &str, end: &str) -> Schedule {
+        let timezone = TimeZone::get("America/Edmonton").unwrap();
+        Schedule::new(timezone, vec![TimeWindow::new(days, parse_time(start).unwrap(), parse_time(end).unwrap())])
+    }
+
+    fn at(time: &str) -> Timestamp {
+        format!("{}[America/Edmonton]", time).parse::<Zoned>().unwrap().timestamp()
+    }
+
+    fn notification(priority: Priority) -> ValidatedNotification {
+        ValidatedNotification::new("test", Message::new("test").with_priority(priority))
+    }
+
+    #[test]
+    fn window_is_active_on_its_days_and_hours() {
+        let weekdays = [Weekday::Monday, Weekday::Tuesday, Weekday::Wednesday, Weekday::Thursday, Weekday::Friday];
+        let schedule = schedule(weekdays.to_vec(), "08:00", "22:00");
+        assert!(schedule.is_active(at("2024-03-11T08:00")));
+        assert!(schedule.is_active(at("2024-03-11T21:59")));
+        assert!(!schedule.is_active(at("2024-03-11T07:59")));
+        assert!(!schedule.is_active(at("2024-03-11T22:00")));
+        assert!(!schedule.is_active(at("2024-03-16T12:00")));
+    }
+
+    #[test]
+    fn window_runs_past_midnight() {
+        let schedule = schedule(vec![Weekday::Friday], "22:00", "06:00");
+        assert!(schedule.is_active(at("2024-03-15T23:00")));
+        assert!(schedule.is_active(at("2024-03-16T05:59")));
+        assert!(!schedule.is_active(at("2024-03-16T06:00")));
+        assert!(!schedule.is_active(at("2024-03-16T23:00")));
+        assert!(!schedule.is_active(at("2024-03-15T05:00")));
+        assert_eq!(schedule.next_open(at("2024-03-16T06:00")), Some(at("2024-03-22T22:00")));
+    }
+
+    #[test]
+    fn next_open_follows_daylight_saving_changes() {
+        // 02:30 does not exist when the clocks go forward, so the window opens an hour later
+        let night = schedule(Vec::new(), "02:30", "04:00");
+        assert_eq!(night.next_open(at("2024-03-09T12:00")), Some(at("2024-03-10T03:30")));
+        assert!(night.is_active(at("2024-03-10T03:45")));
+
+        // The night the clocks go back is an hour longer
+        let daytime = schedule(Vec::new(), "09:00", "17:00");
+        let evening = at("2024-11-02T20:00");
+        let next_open = daytime.next_open(evening).unwrap();
+        assert_eq!(next_open, "2024-11-03T09:00-07:00".parse::<Timestamp>().unwrap());
+        assert_eq!(next_open.duration_since(evening).as_secs(), 14 * 3600);
+    }
+
+    #[test]
+    fn scheduler_holds_notifications_until_the_window_opens() {
+        let mut scheduler = Scheduler::new(schedule(Vec::new(), "08:00", "22:00"));
+        let now = Instant::now();
+        let morning = at("2024-03-11T07:00");
+        assert!(scheduler.push_at(notification(Priority::High), now, morning).is_empty());
+        assert_eq!(scheduler.push_at(notification(Priority::Critical), now, morning).len(), 1);
+        assert_eq!(scheduler.pending(), 1);
+        assert_eq!(scheduler.next_deadline(), Some(now + Duration::from_secs(3600)));
+
+        let later = now + Duration::from_secs(1800);
+        assert!(scheduler.poll_at(later, at("2024-03-11T07:30")).is_empty());
+        assert_eq!(scheduler.next_deadline(), Some(now + Duration::from_secs(3600)));
+
+        let open = now + Duration::from_secs(3600);
+        assert_eq!(scheduler.poll_at(open, at("2024-03-11T08:00")).len(), 1);
+        assert_eq!((scheduler.pending(), scheduler.next_deadline()), (0, None));
+    }
+
+    #[test]
+    fn scheduler_drops_or_reroutes_outside_the_windows() {
+        let night = at("2024-03-11T23:00");
+        let mut scheduler = Scheduler::new(schedule(Vec::new(), "08:00", "22:00").with_outside(Outside::Drop));
+        assert!(scheduler.push_at(notification(Priority::Normal), Instant::now(), night).is_empty());
+        assert_eq!((scheduler.pending(), scheduler.dropped()), (0, 1));
+
+        let fallback = schedule(Vec::new(), "08:00", "22:00").with_outside(Outside::Fallback);
+        let mut scheduler = Scheduler::new(fallback.with_fallback(Some("fallback")));
+        assert!(scheduler.push_at(notification(Priority::Normal), Instant::now(), night).is_empty());
+        assert_eq!(scheduler.take_rerouted().len(), 1);
+    }
+}
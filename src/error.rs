@@ -17,6 +17,10 @@ pub enum ErrorKind {
     #[error("Invalid Interface Configuration: {0}")]
     InvalidInterfaceConfiguration(String),
 
+    /// Validation failed for a server wide setting.
+    #[error("Invalid Server Configuration: {0}")]
+    InvalidServerConfiguration(String),
+
     /// Validation failed for an [`EndpointConfig`][`crate::endpoints::EndpointConfig`].
     #[error("Invalid Endpoint Configuration: {0}")]
     InvalidEndpointConfiguration(String),
@@ -37,6 +41,26 @@ pub enum ErrorKind {
     #[error("Endpoint feature {0} is not enabled")]
     DisabledEndpointFeature(String),
 
+    /// Return when a configuration file format is used but its feature is not enabled
+    #[error("Configuration format feature {0} is not enabled")]
+    DisabledConfigFormat(String),
+
+    /// A running server reported that it is not ready.
+    #[error("Server is not ready: {0}")]
+    ServerNotReady(String),
+
+    /// The configuration of a running server could not be reloaded.
+    #[error("Configuration reload failed: {0}")]
+    ReloadFailed(String),
+
+    /// An environment variable or secret file referenced by a configuration file could not be read.
+    #[error("Unable to resolve configuration secret: {0}")]
+    UnresolvedSecret(String),
+
+    /// A file included by a configuration file could not be included.
+    #[error("Invalid configuration include: {0}")]
+    InvalidInclude(String),
+
     // ### Converting from other error types ###
     /// Pass-thru [`std::io::Error`].
     #[error("std::io Error: {0}")]
@@ -51,6 +75,16 @@ pub enum ErrorKind {
     #[error("Serde Toml Error: {0}")]
     SerdeTomlError(#[from] toml::de::Error),
 
+    #[cfg(feature = "parse-cfg")]
+    /// Pass-thru `toml::ser::Error`.
+    #[error("Serde Toml Serialize Error: {0}")]
+    SerdeTomlSerializeError(#[from] toml::ser::Error),
+
+    #[cfg(feature = "parse-yaml")]
+    /// Pass-thru `serde_yaml_ng::Error`.
+    #[error("Serde Yaml Error: {0}")]
+    SerdeYamlError(#[from] serde_yaml_ng::Error),
+
     #[cfg(feature = "matrix")]
     /// Pass-thru `matrix_sdk::Error`.
     #[error("Matrix_SDK Error: {0}")]
@@ -81,6 +115,11 @@ pub enum ErrorKind {
     #[error("Url Parse Error: {0}")]
     UrlParseError(#[from] url::ParseError),
 
+    #[cfg(feature = "http-client")]
+    /// Pass-thru `reqwest::Error`.
+    #[error("Reqwest Error: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+
     #[cfg(feature = "email")]
     /// Pass-thru `mail_send::Error`.
     #[error("Mail Send Error: {0}")]
@@ -1,6 +1,8 @@
 //! Interfaces for the server and client
 
 use crate::notifications::Notification;
+#[cfg(feature = "server")]
+use crate::ServerStatus;
 use crate::Error;
 use async_trait::async_trait;
 use dyn_clone::DynClone;
@@ -20,7 +22,7 @@ pub(crate) const SECOND: Duration = Duration::from_secs(1);
 pub(crate) const NANOSECOND: Duration = Duration::from_nanos(1);
 
 /// A data structure that can be deserialized and converted into an [`Interface`].
-#[typetag::deserialize(tag = "type")]
+#[typetag::serde(tag = "type")]
 pub trait InterfaceConfig: Debug {
     /// Convert this `InterfaceConfig` into an [`Interface`].
     fn to_interface(&self) -> Result<Box<dyn Interface + Send>, Error>;
@@ -32,26 +34,85 @@ pub trait Interface: DynClone + Send + Debug {
     /// Implements the server receiving notifications from the `Interface`.
     async fn receive(&self, interface_tx: mpsc::Sender<String>, shutdown: watch::Receiver<bool>) -> Result<(), Error>;
 
+    /// Pass the [`ServerStatus`] to the `Interface` before the server starts receiving on it.
+    ///
+    /// Does nothing unless the `Interface` serves the health or metrics of the server.
+    #[cfg(feature = "server")]
+    fn with_status(&mut self, _status: ServerStatus) {}
+
     /// Implements the client sending notifications to the `Interface`.
     async fn send(
         &self,
         interface_rx: broadcast::Receiver<Notification>,
         shutdown: watch::Receiver<bool>,
     ) -> Result<(), Error>;
+
+    /// Return a name that identifies this `Interface` in logs and statistics.
+    fn name(&self) -> String {
+        "unknown".into()
+    }
 }
 
 dyn_clone::clone_trait_object!(Interface);
 
+/// Raw data received by the server on an [`Interface`].
 #[cfg(feature = "server")]
-pub(crate) async fn setup_server_interfaces(
-    interfaces: Vec<Box<dyn Interface + Send>>,
-    interface_tx: mpsc::Sender<String>,
-    shutdown: watch::Receiver<bool>,
-) -> Result<(), Error> {
-    for interface in interfaces {
-        interface.receive(interface_tx.clone(), shutdown.clone()).await?;
+#[derive(Debug, Clone)]
+pub(crate) struct InterfaceMessage {
+    interface: String,
+    payload: String,
+}
+
+#[cfg(feature = "server")]
+impl InterfaceMessage {
+    #[cfg(feature = "http-server")]
+    pub fn new<S: AsRef<str>>(interface: S, payload: String) -> Self {
+        Self { interface: interface.as_ref().into(), payload }
     }
-    Ok(())
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+}
+
+/// Start the server receiving on the `interface` until `shutdown` and return the task that ends when it stops.
+///
+/// The interface is reported in the health of the server `status` while it receives.
+#[cfg(feature = "server")]
+pub(crate) async fn setup_server_interface(
+    interface: &(dyn Interface + Send),
+    interface_tx: mpsc::Sender<InterfaceMessage>,
+    status: &ServerStatus,
+    shutdown: watch::Receiver<bool>,
+) -> Result<tokio::task::JoinHandle<()>, Error> {
+    use crate::CHANNEL_BUFFER;
+
+    let (payload_tx, mut payload_rx) = mpsc::channel(CHANNEL_BUFFER);
+    let mut interface = dyn_clone::clone_box(interface);
+    interface.with_status(status.clone());
+    interface.receive(payload_tx, shutdown.clone()).await?;
+
+    // Tag everything received with the interface it came from until the interface stops
+    let name = interface.name();
+    let status = status.clone();
+    let id = status.health().add_interface(name.as_str());
+    Ok(tokio::spawn(async move {
+        while let Some(payload) = payload_rx.recv().await {
+            if interface_tx.send(InterfaceMessage { interface: name.clone(), payload }).await.is_err() {
+                break;
+            }
+        }
+
+        // An interface stopped on purpose is no longer part of the server
+        match *shutdown.borrow() {
+            true => status.health().remove_interface(id),
+            false => status.health().interface_stopped(id),
+        }
+    }))
 }
 
 #[cfg(feature = "client")]
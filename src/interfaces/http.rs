@@ -35,6 +35,8 @@ pub(crate) mod http_server;
 
 use crate::interfaces::{Interface, InterfaceConfig};
 use crate::notifications::Notification;
+#[cfg(all(feature = "server", feature = "http-server"))]
+use crate::ServerStatus;
 use crate::{Error, CRATE_VERSION};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -50,6 +52,12 @@ const DEFAULT_PORT: u16 = 8080;
 const BASE_PATH: &str = "pass-it-on";
 const NOTIFICATION_PATH: &str = "notification";
 const VERSION_PATH: &str = "version";
+#[cfg(feature = "server")]
+const HEALTH_PATH: &str = "health";
+#[cfg(feature = "server")]
+const READY_PATH: &str = "ready";
+#[cfg(feature = "metrics")]
+const METRICS_PATH: &str = "metrics";
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Version {
@@ -64,11 +72,14 @@ pub struct HttpSocketInterface {
     port: u16,
     tls_cert_path: Option<PathBuf>,
     tls_key_path: Option<PathBuf>,
+    #[cfg(all(feature = "server", feature = "http-server"))]
+    status: ServerStatus,
 }
 
 /// Data structure to represent the HTTP Socket [`InterfaceConfig`].
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
-#[serde(default)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default, deny_unknown_fields)]
 pub(crate) struct HttpSocketConfigFile {
     pub host: String,
     pub tls: Option<bool>,
@@ -91,7 +102,15 @@ impl HttpSocketInterface {
         let port = host.port().unwrap_or(DEFAULT_PORT);
         let tls_cert_path = cert_path.map(|p| PathBuf::from(p.as_ref()));
         let tls_key_path = key_path.map(|p| PathBuf::from(p.as_ref()));
-        Self { host, tls, port, tls_cert_path, tls_key_path }
+        Self {
+            host,
+            tls,
+            port,
+            tls_cert_path,
+            tls_key_path,
+            #[cfg(all(feature = "server", feature = "http-server"))]
+            status: ServerStatus::default(),
+        }
     }
 
     /// Return the IP address.
@@ -123,6 +142,28 @@ impl HttpSocketInterface {
     pub fn tls_key_path(&self) -> &Option<PathBuf> {
         &self.tls_key_path
     }
+
+    /// Serve the HTTP routes with `state` on every socket of the interface until `shutdown`.
+    #[cfg(feature = "http-server")]
+    fn serve(&self, state: http_server::HttpState, shutdown: watch::Receiver<bool>) -> Result<(), Error> {
+        use crate::interfaces::http::http_server::start_monitoring;
+
+        if self.tls && (self.tls_cert_path().is_none() || self.tls_cert_path().is_none()) {
+            Err(Error::invalid_interface_configuration(
+                "Both tls_cert_path and tls_cert_path must be provided for a TLS server",
+            ))
+        } else {
+            for socket in self.sockets()? {
+                let tls = self.tls;
+                let state = state.clone();
+                let srx = shutdown.clone();
+                let cert_path = self.tls_cert_path.clone();
+                let key_path = self.tls_key_path.clone();
+                tokio::spawn(async move { start_monitoring(state, srx, socket, tls, cert_path, key_path).await });
+            }
+            Ok(())
+        }
+    }
 }
 
 impl Default for HttpSocketConfigFile {
@@ -145,6 +186,8 @@ impl Default for HttpSocketInterface {
             port: DEFAULT_PORT,
             tls_cert_path: None,
             tls_key_path: None,
+            #[cfg(all(feature = "server", feature = "http-server"))]
+            status: ServerStatus::default(),
         }
     }
 }
@@ -170,7 +213,7 @@ impl TryFrom<&HttpSocketConfigFile> for HttpSocketInterface {
     }
 }
 
-#[typetag::deserialize(name = "http")]
+#[typetag::serde(name = "http")]
 impl InterfaceConfig for HttpSocketConfigFile {
     fn to_interface(&self) -> Result<Box<dyn Interface + Send>, Error> {
         Ok(Box::new(HttpSocketInterface::try_from(self)?))
@@ -181,23 +224,17 @@ impl InterfaceConfig for HttpSocketConfigFile {
 impl Interface for HttpSocketInterface {
     #[cfg(feature = "http-server")]
     async fn receive(&self, interface_tx: mpsc::Sender<String>, shutdown: watch::Receiver<bool>) -> Result<(), Error> {
-        use crate::interfaces::http::http_server::start_monitoring;
+        use crate::interfaces::http::http_server::HttpState;
 
-        if self.tls && (self.tls_cert_path().is_none() || self.tls_cert_path().is_none()) {
-            Err(Error::invalid_interface_configuration(
-                "Both tls_cert_path and tls_cert_path must be provided for a TLS server",
-            ))
-        } else {
-            for socket in self.sockets()? {
-                let tls = self.tls;
-                let itx = interface_tx.clone();
-                let srx = shutdown.clone();
-                let cert_path = self.tls_cert_path.clone();
-                let key_path = self.tls_key_path.clone();
-                tokio::spawn(async move { start_monitoring(itx, srx, socket, tls, cert_path, key_path).await });
-            }
-            Ok(())
-        }
+        let state = HttpState::new(interface_tx);
+        #[cfg(feature = "server")]
+        let state = state.with_status(self.status.clone());
+        self.serve(state, shutdown)
+    }
+
+    #[cfg(all(feature = "server", feature = "http-server"))]
+    fn with_status(&mut self, status: ServerStatus) {
+        self.status = status;
     }
 
     #[cfg(not(feature = "http-server"))]
@@ -234,6 +271,10 @@ impl Interface for HttpSocketInterface {
     ) -> Result<(), Error> {
         Err(Error::disabled_interface_feature("http-client".to_string()))
     }
+
+    fn name(&self) -> String {
+        format!("http {}", self.host)
+    }
 }
 
 fn parse_url(value: &str) -> Result<Url, Error> {
@@ -1,7 +1,17 @@
 use crate::interfaces::http::{BASE_PATH, NOTIFICATION_PATH, VERSION_PATH, Version};
+#[cfg(feature = "metrics")]
+use crate::interfaces::http::METRICS_PATH;
+#[cfg(feature = "server")]
+use crate::interfaces::http::{HEALTH_PATH, READY_PATH};
+#[cfg(feature = "server")]
+use crate::server::health::{self, Liveness, Readiness};
+#[cfg(feature = "server")]
+use crate::ServerStatus;
 use crate::notifications::Notification;
 use axum::extract::State;
 use axum::http::StatusCode;
+#[cfg(feature = "metrics")]
+use axum::http::header;
 use axum::routing::{get, post};
 use axum::{Json, Router};
 use axum_server::Address;
@@ -15,8 +25,33 @@ use crate::Error;
 
 const GRACE_PERIOD: Duration = Duration::from_secs(1);
 
-pub(super) async fn start_monitoring<P: AsRef<Path>> (
+/// State shared by the routes of the HTTP server.
+#[derive(Debug, Clone)]
+pub(super) struct HttpState {
     tx: mpsc::Sender<String>,
+    #[cfg(feature = "server")]
+    status: ServerStatus,
+}
+
+impl HttpState {
+    /// Pass the notifications received to `tx` and serve the status of a server that has not started.
+    pub fn new(tx: mpsc::Sender<String>) -> Self {
+        Self {
+            tx,
+            #[cfg(feature = "server")]
+            status: ServerStatus::default(),
+        }
+    }
+
+    /// Serve the health and metrics of the server `status`.
+    #[cfg(feature = "server")]
+    pub fn with_status(self, status: ServerStatus) -> Self {
+        Self { status, ..self }
+    }
+}
+
+pub(super) async fn start_monitoring<P: AsRef<Path>> (
+    state: HttpState,
     shutdown: watch::Receiver<bool>,
     socket: SocketAddr,
     tls: bool,
@@ -29,8 +64,14 @@ pub(super) async fn start_monitoring<P: AsRef<Path>> (
 
     let routes = Router::new()
         .route(format!("/{}/{}", BASE_PATH, VERSION_PATH).as_str(), get(version_handler))
-        .route(format!("/{}/{}", BASE_PATH, NOTIFICATION_PATH).as_str(), post(notification_handler))
-        .with_state(tx);
+        .route(format!("/{}/{}", BASE_PATH, NOTIFICATION_PATH).as_str(), post(notification_handler));
+    #[cfg(feature = "server")]
+    let routes = routes
+        .route(format!("/{}/{}", BASE_PATH, HEALTH_PATH).as_str(), get(health_handler))
+        .route(format!("/{}/{}", BASE_PATH, READY_PATH).as_str(), get(ready_handler));
+    #[cfg(feature = "metrics")]
+    let routes = routes.route(format!("/{}/{}", BASE_PATH, METRICS_PATH).as_str(), get(metrics_handler));
+    let routes = routes.with_state(state);
 
     info!("Setting up Interface: HttpSocket on -> {} | TLS Enabled -> {}", socket, tls);
     let listener = std::net::TcpListener::bind(socket)?;
@@ -58,12 +99,31 @@ async fn version_handler() -> Json<Version> {
     Json(Version::new())
 }
 
+#[cfg(feature = "server")]
+async fn health_handler() -> Json<Liveness> {
+    Json(health::liveness())
+}
+
+#[cfg(feature = "server")]
+async fn ready_handler(State(state): State<HttpState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = state.status.health().readiness();
+    match readiness.is_ready() {
+        true => (StatusCode::OK, Json(readiness)),
+        false => (StatusCode::SERVICE_UNAVAILABLE, Json(readiness)),
+    }
+}
+
+#[cfg(feature = "metrics")]
+async fn metrics_handler(State(state): State<HttpState>) -> ([(header::HeaderName, &'static str); 1], String) {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.status.metrics().render())
+}
+
 async fn notification_handler(
-    State(tx): State<mpsc::Sender<String>>,
+    State(state): State<HttpState>,
     Json(notification): Json<Notification>,
 ) -> StatusCode {
     trace!("HTTP server received {:?}", notification);
-    match tx.send(notification.to_json().unwrap_or_default()).await {
+    match state.tx.send(notification.to_json().unwrap_or_default()).await {
         Ok(_) => StatusCode::OK,
         Err(e) => {
             warn!("bad JSON received from http socket: {}", e);
@@ -83,3 +143,36 @@ async fn shutdown_server<A: Address>(handle: axum_server::Handle<A>, mut shutdow
         }
     }
 }
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ready_route_is_unavailable_until_the_server_is_ready() {
+        let (tx, _rx) = mpsc::channel(1);
+        let status = ServerStatus::default();
+        let state = HttpState::new(tx).with_status(status.clone());
+        let interface = status.health().add_interface("pipe");
+        status.health().interface_stopped(interface);
+
+        let (code, Json(readiness)) = ready_handler(State(state.clone())).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            serde_json::to_string(&readiness).unwrap(),
+            r#"{"status":"starting","interfaces":{"pipe":"down"},"endpoints":{}}"#
+        );
+
+        status.health().set_started();
+        let (code, Json(readiness)) = ready_handler(State(state.clone())).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            serde_json::to_string(&readiness).unwrap(),
+            r#"{"status":"down","interfaces":{"pipe":"down"},"endpoints":{}}"#
+        );
+
+        status.health().remove_interface(interface);
+        let (code, _) = ready_handler(State(state)).await;
+        assert_eq!(code, StatusCode::OK);
+    }
+}
@@ -28,7 +28,7 @@ use crate::Error;
 use async_trait::async_trait;
 #[cfg(feature = "pipe-server")]
 use nix::sys::stat::Mode;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "pipe-server")]
 use std::path::Path;
 use std::path::PathBuf;
@@ -47,7 +47,9 @@ pub struct PipeInterface {
 }
 
 /// Data structure to represent the Named Pipe [`InterfaceConfig`].
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub(crate) struct PipeConfigFile {
     path: String,
     group_read_permission: Option<bool>,
@@ -107,7 +109,7 @@ impl TryFrom<&PipeConfigFile> for PipeInterface {
     }
 }
 
-#[typetag::deserialize(name = "pipe")]
+#[typetag::serde(name = "pipe")]
 impl InterfaceConfig for PipeConfigFile {
     fn to_interface(&self) -> Result<Box<dyn Interface + Send>, Error> {
         Ok(Box::new(PipeInterface::try_from(self)?))
@@ -191,6 +193,10 @@ impl Interface for PipeInterface {
     ) -> Result<(), Error> {
         Err(Error::disabled_interface_feature("pipe-client".to_string()))
     }
+
+    fn name(&self) -> String {
+        format!("pipe {}", self.path.display())
+    }
 }
 
 #[cfg(feature = "pipe-server")]
@@ -9,7 +9,7 @@
 //! [`Notification`][crate::notifications::Notification] values and send them in the expected format to server.
 //!
 //! ```
-//! # use pass_it_on::notifications::{Key, Notification};
+//! # use pass_it_on::notifications::{Key, Message, Notification, Priority};
 //! # use pass_it_on::{ClientConfigFileParser, start_client, Error};
 //! # use tokio::sync::mpsc;
 //! #
@@ -31,7 +31,7 @@
 //!
 //!     let messages = vec![
 //!         Message::new("A message to be sent").to_client_ready_message(NOTIFICATION_NAME),
-//!         Message::new("Another message").to_client_ready_message(NOTIFICATION_NAME),
+//!         Message::new("Another message").with_priority(Priority::High).to_client_ready_message(NOTIFICATION_NAME),
 //!     ];
 //!
 //!     for message in messages {
@@ -60,10 +60,14 @@
 //! | http-server             | Enables the HTTP interface for just server.                                                                            |
 //! | interfaces              | Enables the Interface and InterfaceConfig traits.                                                                      |
 //! | matrix                  | Enables the matrix endpoint.                                                                                           |
+//! | metrics                 | Enables Prometheus metrics served by the HTTP interface server at `/pass-it-on/metrics`.                               |
 //! | parse-cfg               | Enables parsing of client or server configurations from TOML when those features are also enabled.                     |
+//! | parse-json              | Enables parsing of client or server configurations from JSON as well as TOML.                                          |
+//! | parse-yaml              | Enables parsing of client or server configurations from YAML as well as TOML.                                          |
 //! | pipe                    | Enables the named pipe interface client and server. **(Unix only)**                                                    |
 //! | pipe-client             | Enables the named pipe interface client. **(Unix only)**                                                               |
 //! | pipe-server             | Enables the named pipe interface server. **(Unix only)**                                                               |
+//! | schema                  | Enables `ServerConfigFile::schema` and `ClientConfigFile::schema` returning a JSON Schema of the configurations.       |
 //! | server                  | Enables the server but not any particular interface or endpoint.                                                       |
 //! | server-bin-full         | Enables the building of the provided `pass-it-on-server` binary with all available interfaces and endpoints            |
 //! | server-bin-minimal      | Enables the building of the provided `pass-it-on-server` binary while not requiring any specific interface or endpoint |
@@ -88,16 +92,34 @@ pub(crate) mod shutdown;
 pub use self::client::{start_client, start_client_arc};
 #[cfg(all(feature = "client", feature = "parse-cfg"))]
 pub use self::configuration::client_configuration_file::ClientConfigFile;
+#[cfg(all(any(feature = "client", feature = "server"), feature = "parse-cfg"))]
+pub use self::configuration::format::ConfigFormat;
 #[cfg(all(feature = "server", feature = "parse-cfg"))]
-pub use self::configuration::server_configuration_file::ServerConfigFile;
+pub use self::configuration::server_configuration_file::{ConfigProblem, NotificationRoute, ServerConfigFile};
 #[cfg(feature = "client")]
 pub use self::configuration::ClientConfiguration;
 #[cfg(feature = "server")]
 pub use self::configuration::ServerConfiguration;
 pub use self::error::Error;
 #[cfg(feature = "server")]
+pub use self::server::admin::Admin;
+#[cfg(feature = "server")]
+pub use self::server::dead_letter::DeadLetter;
+#[cfg(feature = "server")]
+pub use self::server::escalation::Escalation;
+#[cfg(feature = "server")]
+pub use self::server::healthcheck;
+#[cfg(feature = "server")]
+pub use self::server::replay::ReplayProtection;
+#[cfg(feature = "server")]
+pub use self::server::spool::Spool;
+#[cfg(feature = "server")]
+pub use self::server::ServerStatus;
+#[cfg(feature = "server")]
 pub use self::server::start_server;
 #[cfg(feature = "server")]
+pub use self::server::start_server_with_reload;
+#[cfg(feature = "server")]
 pub use self::server::verify_matrix_devices;
 
 #[allow(dead_code)]
@@ -105,3 +127,4 @@ const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
 #[allow(dead_code)]
 const CHANNEL_BUFFER: usize = 200;
 const KEY_CONTEXT: &str = "pass-it-on 2024-02-18 client-server shared-key";
+const KEY_ID_CONTEXT: &str = "pass-it-on notification key identifier";
@@ -1,10 +1,15 @@
 //! Representation of notification messages.
 
-use crate::{Error, KEY_CONTEXT};
+#[cfg(feature = "endpoints")]
+use crate::endpoints::delivery::Receipt;
+#[cfg(feature = "endpoints")]
+use crate::endpoints::delivery::DeliveryError;
+use crate::{Error, KEY_CONTEXT, KEY_ID_CONTEXT};
 use blake3::Hash;
 use serde::{Deserialize, Serialize};
 use serde_json::StreamDeserializer;
-use std::collections::HashSet;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// The actual message data that is being transmitted in a [`Notification`].
@@ -12,6 +17,30 @@ use std::time::{SystemTime, UNIX_EPOCH};
 pub struct Message {
     text: String,
     time: u128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    priority: Priority,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+/// Priority level of a [`Message`] that endpoints can use to decide how prominently it is delivered.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Copy, Default, PartialOrd, Ord)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Informational message that does not need attention.
+    Low,
+    /// Default priority.
+    #[default]
+    Normal,
+    /// Message that should be noticed soon.
+    High,
+    /// Message that needs immediate attention.
+    Critical,
 }
 
 /// A [`Message`] that has been assigned a notification name
@@ -26,13 +55,21 @@ pub struct ClientReadyMessage {
 pub struct ValidatedNotification {
     message: Message,
     sub_name: String,
+    #[cfg(feature = "endpoints")]
+    receipt: Option<Receipt>,
 }
 
 /// Notification ready to be send with [`Message`] data and a hash value for validation.
+///
+/// The `key_id` identifies the notification name [`Key`] used to create the `key` so that the server can find
+/// the matching key without trying every configured notification name.
+/// Notifications without a `key_id` are still accepted and validated against every key.
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash, Clone)]
 pub struct Notification {
     message: Message,
     key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
 }
 
 /// Convenience wrapper around a [BLAKE3] [`Hash`] used for validation.
@@ -48,7 +85,8 @@ impl Notification {
     /// Create a new `Notification` from a text value and key for notification name.
     pub fn new(message: Message, notification_key: &Key) -> Notification {
         let key = message.create_key(notification_key).to_hex();
-        Notification { message, key }
+        let key_id = Some(notification_key.identifier());
+        Notification { message, key, key_id }
     }
 
     /// Parse single `Notification` from JSON.
@@ -81,17 +119,6 @@ impl Notification {
         let new_key = self.message.create_key(hash_key);
         self.key == new_key.to_hex()
     }
-    
-    /// Compare provided set of  notification name ['Key']s to this notification.
-    pub(crate) fn validate_set(&self, hash_keys: &HashSet<Key>) -> bool {
-        for hash_key in hash_keys {
-            match self.validate(hash_key) {
-                true => return true,
-                false => (),
-            }
-        }
-        false
-    }
 
     /// Return inner [`Message`].
     pub fn message(&self) -> Message {
@@ -102,6 +129,11 @@ impl Notification {
     pub fn key(&self) -> &str {
         &self.key
     }
+
+    /// Return the identifier of the notification name [`Key`] if the client provided one.
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
 }
 
 impl Message {
@@ -109,7 +141,40 @@ impl Message {
     pub fn new<S: AsRef<str>>(text: S) -> Message {
         let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
         let body = String::from(text.as_ref());
-        Self { text: body, time }
+        Self {
+            text: body,
+            time,
+            title: None,
+            priority: Priority::default(),
+            metadata: BTreeMap::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// Set a short title for this `Message` that endpoints can show as a headline or subject.
+    pub fn with_title<S: AsRef<str>>(mut self, title: S) -> Message {
+        self.title = Some(title.as_ref().into());
+        self
+    }
+
+    /// Add a metadata key/value pair, like host or service, to this `Message`.
+    ///
+    /// Setting a key that already exists replaces the previous value.
+    pub fn with_metadata<K: AsRef<str>, V: AsRef<str>>(mut self, key: K, value: V) -> Message {
+        self.metadata.insert(key.as_ref().into(), value.as_ref().into());
+        self
+    }
+
+    /// Add a tag to this `Message`.
+    pub fn with_tag<S: AsRef<str>>(mut self, tag: S) -> Message {
+        self.tags.push(tag.as_ref().into());
+        self
+    }
+
+    /// Set the [`Priority`] of this `Message`.
+    pub fn with_priority(mut self, priority: Priority) -> Message {
+        self.priority = priority;
+        self
     }
 
     /// Return inner text value.
@@ -122,9 +187,54 @@ impl Message {
         self.time
     }
 
+    /// Return the title if one was set.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Return the [`Priority`] of this `Message`.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Create a new `Message` with the provided text that keeps the title, priority, metadata and tags of this one.
+    #[allow(dead_code)]
+    pub(crate) fn with_text<S: AsRef<str>>(&self, text: S) -> Message {
+        let message = Message::new(text);
+        Message {
+            title: self.title.clone(),
+            priority: self.priority,
+            metadata: self.metadata.clone(),
+            tags: self.tags.clone(),
+            ..message
+        }
+    }
+
+    /// Return the metadata key/value pairs.
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Return the tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
     /// Create a [`Key`] for this [`Message`] based on the [`Key`] for the notification name.
+    ///
+    /// Messages that only have a text and time hash them as before so that they still validate against servers
+    /// and clients that do not know about the optional fields. Otherwise every field is hashed as a JSON array,
+    /// which cannot be mistaken for the text and time or for other values of the fields.
     fn create_key(&self, notification_key: &Key) -> Key {
-        let hash_string = format!("{}{}", self.text, self.time);
+        let plain =
+            self.title.is_none() && self.priority.is_normal() && self.metadata.is_empty() && self.tags.is_empty();
+        let hash_string = match plain {
+            true => format!("{}{}", self.text, self.time),
+            false => {
+                let fields = (&self.text, self.time, &self.title, self.priority, &self.metadata, &self.tags);
+                serde_json::to_string(&fields).unwrap_or_default()
+            }
+        };
         Key::generate(hash_string.as_str(), notification_key)
     }
 
@@ -134,6 +244,25 @@ impl Message {
     }
 }
 
+impl Priority {
+    /// Return true if this is the default [`Priority::Normal`].
+    pub fn is_normal(&self) -> bool {
+        *self == Priority::Normal
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let priority = match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Critical => "critical",
+        };
+        write!(f, "{}", priority)
+    }
+}
+
 impl ClientReadyMessage {
     /// Create a new `ClientReadyMessage`
     pub(crate) fn new<S: AsRef<str>>(notification_name: S, message: Message) -> Self {
@@ -161,7 +290,42 @@ impl ClientReadyMessage {
 impl ValidatedNotification {
     /// Create a new `ValidatedNotification`.
     pub fn new<S: AsRef<str>>(name_id: S, message: Message) -> ValidatedNotification {
-        Self { sub_name: name_id.as_ref().into(), message }
+        Self {
+            sub_name: name_id.as_ref().into(),
+            message,
+            #[cfg(feature = "endpoints")]
+            receipt: None,
+        }
+    }
+
+    /// Attach the [`Receipt`] used to report the delivery of this `ValidatedNotification`.
+    #[cfg(feature = "endpoints")]
+    pub(crate) fn with_receipt(mut self, receipt: Receipt) -> ValidatedNotification {
+        self.receipt = Some(receipt);
+        self
+    }
+
+    /// Return `true` when a [`Receipt`] is attached to this `ValidatedNotification`.
+    #[cfg(feature = "endpoints")]
+    pub(crate) fn has_receipt(&self) -> bool {
+        self.receipt.is_some()
+    }
+
+    /// Return the [`Receipt`] attached to this `ValidatedNotification`.
+    #[cfg(feature = "endpoints")]
+    pub(crate) fn receipt(&self) -> Option<&Receipt> {
+        self.receipt.as_ref()
+    }
+
+    /// Report the result of delivering this `ValidatedNotification` to the server.
+    ///
+    /// Endpoints that [report delivery][crate::endpoints::Endpoint::reports_delivery] call this once for every
+    /// notification after their last attempt. Does nothing when the server is not tracking the notification.
+    #[cfg(feature = "endpoints")]
+    pub fn report_delivery(&self, result: Result<(), DeliveryError>) {
+        if let Some(receipt) = self.receipt.as_ref() {
+            receipt.report(ValidatedNotification::new(self.sub_name(), self.message.clone()), result);
+        }
     }
 
     /// Return inner [`Message`] value.
@@ -169,6 +333,16 @@ impl ValidatedNotification {
         &self.message
     }
 
+    /// Return the metadata key/value pairs of the inner [`Message`].
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        self.message.metadata()
+    }
+
+    /// Return the tags of the inner [`Message`].
+    pub fn tags(&self) -> &[String] {
+        self.message.tags()
+    }
+
     /// Return the sub-name for this `ValidatedNotification`.
     pub fn sub_name(&self) -> &str {
         &self.sub_name
@@ -188,6 +362,14 @@ impl Key {
         Self::from_bytes(&blake3::derive_key(KEY_CONTEXT, key_string.as_ref().as_bytes()))
     }
 
+    /// Return a non-reversible identifier for this `Key` that can be sent in the clear.
+    ///
+    /// The identifier is derived with a keyed hash so it reveals nothing about the key or the notification name.
+    pub fn identifier(&self) -> String {
+        let id = Key::generate(KEY_ID_CONTEXT, self);
+        id.to_hex()[..32].to_string()
+    }
+
     /// Create `Key` from a byte array.
     pub fn from_bytes(key: &[u8; 32]) -> Key {
         let hash = Hash::from(*key);
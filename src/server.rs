@@ -1,14 +1,69 @@
+pub(crate) mod admin;
+pub(crate) mod dead_letter;
+pub(crate) mod dispatch;
+pub(crate) mod escalation;
+pub(crate) mod health;
+#[cfg(feature = "metrics")]
+pub(crate) mod metrics;
+pub(crate) mod outcomes;
+pub(crate) mod reload;
+pub(crate) mod replay;
+pub(crate) mod routing;
+pub(crate) mod spool;
+pub(crate) mod stats;
+
 use crate::configuration::ServerConfiguration;
-use crate::endpoints::{setup_endpoints, EndpointChannel};
-use crate::interfaces::setup_server_interfaces;
+use crate::interfaces::InterfaceMessage;
 use crate::notifications::{Notification, ValidatedNotification};
+use crate::server::admin::{Admin, Controls};
+use crate::server::dead_letter::DeadLetters;
+use crate::server::dispatch::{Dispatch, Tracking};
+use crate::server::health::Health;
+#[cfg(feature = "metrics")]
+use crate::server::metrics::{Metrics, ValidationFailure};
+use crate::server::outcomes::OutcomeHandler;
+use crate::server::reload::{run_reloads, LoadConfiguration, Reloader, Running};
+use crate::server::replay::ReplayGuard;
+use crate::server::routing::Routes;
+use crate::server::spool::{replay_spool, Journal};
+use crate::server::stats::ServerStats;
 use crate::shutdown::listen_for_shutdown;
 use crate::{Error, CHANNEL_BUFFER};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{debug, info, warn};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, watch};
 
 const DEFAULT_WAIT_FOR_SHUTDOWN_SECS: u64 = 2;
 
+/// Health, statistics and metrics of a running server for an [`Interface`](crate::interfaces::Interface) to serve.
+///
+/// The server passes its status to [`Interface::with_status`](crate::interfaces::Interface::with_status) before it
+/// starts an interface.
+#[derive(Debug, Clone, Default)]
+pub struct ServerStatus {
+    health: Arc<Health>,
+    stats: Arc<ServerStats>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>,
+}
+
+impl ServerStatus {
+    pub(crate) fn health(&self) -> &Health {
+        &self.health
+    }
+
+    pub(crate) fn stats(&self) -> &ServerStats {
+        &self.stats
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
+
 /// Start the server with provided [`ServerConfiguration`].
 ///
 /// Server listens for shutdown signals SIGTERM & SIGINT on Unix or CTRL-BREAK and CTRL-C on Windows.
@@ -18,64 +73,265 @@ pub async fn start_server(
     server_config: ServerConfiguration,
     shutdown: Option<watch::Receiver<bool>>,
     wait_for_shutdown_secs: Option<u64>,
+) -> Result<(), Error> {
+    run_server(server_config, None, shutdown, wait_for_shutdown_secs).await
+}
+
+/// Start the server with provided [`ServerConfiguration`] and reload it with the configuration returned by `reload`.
+///
+/// The configuration is reloaded when the server receives SIGHUP on Unix or through the admin API. Interfaces and
+/// endpoints that did not change keep running and a configuration that cannot be loaded is rejected, see
+/// [`start_server`] for how the server is shut down.
+pub async fn start_server_with_reload<F>(
+    server_config: ServerConfiguration,
+    reload: F,
+    shutdown: Option<watch::Receiver<bool>>,
+    wait_for_shutdown_secs: Option<u64>,
+) -> Result<(), Error>
+where
+    F: Fn() -> Result<ServerConfiguration, Error> + Send + Sync + 'static,
+{
+    run_server(server_config, Some(Box::new(reload)), shutdown, wait_for_shutdown_secs).await
+}
+
+async fn run_server(
+    server_config: ServerConfiguration,
+    load: Option<LoadConfiguration>,
+    shutdown: Option<watch::Receiver<bool>>,
+    wait_for_shutdown_secs: Option<u64>,
 ) -> Result<(), Error> {
     // Setup channels
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
     let (interface_tx, interface_rx) = mpsc::channel(CHANNEL_BUFFER);
+    let (routes_tx, routes_rx) = watch::channel(Arc::new(Routes::default()));
+    let shutdown_secs = wait_for_shutdown_secs.unwrap_or(DEFAULT_WAIT_FOR_SHUTDOWN_SECS);
+
+    // Collect the delivery results of the endpoints to keep notifications in the spool until they are delivered,
+    // escalate the ones that fail and record the ones that cannot be escalated in the dead letters
+    let journal = server_config.spool().map(Journal::open).transpose()?;
+    let spooled = journal.as_ref().map(Journal::pending).unwrap_or_default();
+    let journal = journal.map(Journal::start);
+    let mut dead_letters = server_config.dead_letter().map(DeadLetters::open).transpose()?;
+    let (outcome_tx, outcome_rx) = mpsc::unbounded_channel();
+    let tracking = Tracking::new(journal.clone(), outcome_tx.clone());
 
-    // Start monitoring the configured interfaces
-    let interfaces = server_config.interfaces();
-    setup_server_interfaces(interfaces, interface_tx.clone(), shutdown_rx.clone()).await?;
+    // Start the endpoints to receive messages and then the interfaces to monitor
+    let status = ServerStatus::default();
+    let controls = Arc::new(Controls::new(server_config.key().clone()));
+    let mut running = Running::new(
+        &server_config,
+        interface_tx.clone(),
+        tracking,
+        routes_tx,
+        controls.clone(),
+        status.clone(),
+        shutdown_rx.clone(),
+        Duration::from_secs(shutdown_secs),
+    );
+    running.apply(&server_config).await?;
 
-    // Setup endpoints to receive messages
-    let endpoints = server_config.endpoint_channels();
-    setup_endpoints(endpoints.clone(), shutdown_rx.clone()).await?;
+    let routes = routes_rx.borrow().clone();
+    replay_spool(spooled, &routes, &outcome_tx).await;
+    match (server_config.dead_letter(), dead_letters.as_mut()) {
+        (Some(dead_letter), Some(dead_letters)) if dead_letter.reinject() => {
+            reinject_dead_letters(dead_letters, &routes).await?
+        }
+        _ => (),
+    }
+
+    let outcomes = OutcomeHandler::new(journal, dead_letters, routes_rx.clone(), status.clone());
+    tokio::spawn(async move { outcomes.run(outcome_rx).await });
+    drop(outcome_tx);
+
+    // Reload the configuration on SIGHUP when there is a configuration to reload
+    let (reloader, reload_requests) = Reloader::new();
+    #[cfg(unix)]
+    if load.is_some() {
+        tokio::spawn(reload::listen_for_reload(reloader.clone(), shutdown_rx.clone()));
+    }
+
+    // Start the admin API to inspect and control the running server
+    if let Some(admin) = server_config.admin() {
+        let routes = routes_rx.clone();
+        start_admin(admin, &controls, &status, routes, reloader, interface_tx.clone(), shutdown_rx.clone())?;
+    }
 
     // Monitor for messages on the interface channel
+    let replay_guard = ReplayGuard::new(server_config.replay_protection().clone());
+    let started = status.clone();
     tokio::spawn(async move {
-        process_incoming_notifications(interface_rx, endpoints).await;
+        process_incoming_notifications(interface_rx, routes_rx, replay_guard, status, controls).await;
     });
+    started.health().set_started();
 
     // Shutdown
-    let shutdown_secs = wait_for_shutdown_secs.unwrap_or(DEFAULT_WAIT_FOR_SHUTDOWN_SECS);
     info!("Listening for shutdown signals");
-    listen_for_shutdown(shutdown_tx, shutdown, shutdown_secs).await;
+    tokio::join!(
+        run_reloads(running, load, reload_requests, shutdown_rx),
+        listen_for_shutdown(shutdown_tx, shutdown, shutdown_secs)
+    );
 
     Ok(())
 }
 
-async fn process_incoming_notifications(mut msg_rx: mpsc::Receiver<String>, endpoints: Vec<EndpointChannel>) {
+async fn process_incoming_notifications(
+    mut msg_rx: mpsc::Receiver<InterfaceMessage>,
+    routes: watch::Receiver<Arc<Routes>>,
+    mut replay_guard: ReplayGuard,
+    status: ServerStatus,
+    controls: Arc<Controls>,
+) {
     info!("Processing Notifications");
 
     while let Some(msg) = msg_rx.recv().await {
-        let notifications = Notification::from_json_multi(msg.as_str());
+        let notifications = Notification::from_json_multi(msg.payload());
+        let routes = routes.borrow().clone();
 
         for notification in notifications {
             match notification {
                 Ok(note) => {
-                    debug!("Notification received: {:?}", note);
-                    for endpoint in &endpoints {
-                        for (sub_name, keys) in endpoint.keys() {
-                            if note.validate_set(keys) {
-                                let channel = endpoint.channel_sender();
-                                match channel.send(ValidatedNotification::new(sub_name, note.message())) {
-                                    Ok(ok) => {
-                                        debug!("Message sent to endpoint. Subscribers: {}", ok)
-                                    }
-                                    Err(e) => warn!(
-                                        
-                                        "Error sending validated message to endpoint: {}", e
-                                    ),
-                                };
-                            }
+                    debug!("Notification received on {}: {:?}", msg.interface(), note);
+                    status.stats().record_received(msg.interface());
+                    #[cfg(feature = "metrics")]
+                    status.metrics().record_received(msg.interface());
+                    let key_routes = match routes.table().find(&note) {
+                        Some(key_routes) => key_routes,
+                        None => {
+                            debug!("Notification received on {} did not match any notification name", msg.interface());
+                            #[cfg(feature = "metrics")]
+                            status.metrics().record_validation_failure(msg.interface(), ValidationFailure::Unmatched);
+                            continue;
                         }
+                    };
+
+                    if let Err(rejection) = replay_guard.check(&note) {
+                        let count = status.stats().record_replay_rejection(msg.interface(), rejection);
+                        #[cfg(feature = "metrics")]
+                        status.metrics().record_validation_failure(msg.interface(), rejection.into());
+                        warn!(
+                            "Rejected {} notification received on {} ({} rejected on this interface)",
+                            rejection,
+                            msg.interface(),
+                            count
+                        );
+                        continue;
+                    }
+
+                    if controls.is_muted(key_routes.id()) {
+                        debug!("Dropped notification received on {} for a muted notification name", msg.interface());
+                        status.stats().record_muted(msg.interface());
+                        continue;
+                    }
+
+                    let message = note.message();
+                    for route in key_routes.routes_for(&message) {
+                        let notification = ValidatedNotification::new(route.sub_name(), message.clone());
+                        let dispatch = Dispatch::new(key_routes.id(), notification);
+                        let endpoint = routes.endpoint(route.endpoint());
+                        pass_on(endpoint.label(), endpoint.dispatcher(), dispatch, status.stats());
                     }
                 }
 
-                Err(e) => warn!("Notification processing error: {}", e),
+                Err(e) => {
+                    warn!("Notification processing error: {}", e);
+                    #[cfg(feature = "metrics")]
+                    {
+                        status.metrics().record_received(msg.interface());
+                        status.metrics().record_validation_failure(msg.interface(), ValidationFailure::Invalid);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Pass a notification to the dispatcher of the endpoint with `label` without waiting for it.
+///
+/// A dispatcher that has no room is not keeping up with its endpoint, like one with a full queue and the `block`
+/// overflow policy. The notification is dropped and counted for that endpoint so the other endpoints still get
+/// their notifications.
+fn pass_on(label: &str, dispatcher: &mpsc::Sender<Dispatch>, dispatch: Dispatch, stats: &ServerStats) {
+    match dispatcher.try_send(dispatch) {
+        Ok(_) => (),
+        Err(TrySendError::Full(_)) => {
+            let busy = stats.record_busy(label);
+            warn!("Dropped notification for endpoint {} which is not keeping up ({} dropped in total)", label, busy);
+        }
+        Err(e) => warn!("Error sending validated message to endpoint dispatcher: {}", e),
+    }
+}
+
+#[cfg(feature = "http-server")]
+/// Bind the admin API and serve it until shutdown.
+fn start_admin(
+    admin: &Admin,
+    controls: &Arc<Controls>,
+    status: &ServerStatus,
+    routes: watch::Receiver<Arc<Routes>>,
+    reloader: Reloader,
+    interface_tx: mpsc::Sender<InterfaceMessage>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(), Error> {
+    use crate::server::admin::api::{self, AdminState};
+
+    let state = AdminState::new(admin, controls.clone(), status.clone(), routes, reloader, interface_tx);
+    api::start_admin(admin, state, shutdown)
+}
+
+#[cfg(not(feature = "http-server"))]
+/// Bind the admin API and serve it until shutdown.
+fn start_admin(
+    _admin: &Admin,
+    _controls: &Arc<Controls>,
+    _status: &ServerStatus,
+    _routes: watch::Receiver<Arc<Routes>>,
+    _reloader: Reloader,
+    _interface_tx: mpsc::Sender<InterfaceMessage>,
+    _shutdown: watch::Receiver<bool>,
+) -> Result<(), Error> {
+    Err(Error::disabled_interface_feature("http-server".to_string()))
+}
+
+/// Send dead letters to the dispatchers of their endpoints again and remove the ones that were sent from the file.
+async fn reinject_dead_letters(dead_letters: &mut DeadLetters, routes: &Routes) -> Result<(), Error> {
+    let records = dead_letters.read(|record| routes.find_endpoint(record.endpoint()).is_some())?;
+    if !records.is_empty() {
+        info!("Reinjecting {} dead letter(s)", records.len());
+    }
+
+    let mut sent = Vec::new();
+    for record in records {
+        if let Some(endpoint) = routes.find_endpoint(record.endpoint()) {
+            let dispatch = Dispatch::new("dead letter", record.notification());
+            match endpoint.dispatcher().send(dispatch).await {
+                Ok(_) => sent.push(record),
+                Err(e) => warn!("Error sending dead letter to endpoint dispatcher: {}", e),
             }
         }
     }
+    dead_letters.remove(&sent)
+}
+
+#[cfg(feature = "http-client")]
+/// Check the ready route at `url` of a running server and return an error when it is not ready.
+pub async fn healthcheck<S: AsRef<str>>(url: S) -> Result<(), Error> {
+    let client = reqwest::Client::builder().use_rustls_tls().build()?;
+    let response = client.get(url.as_ref()).send().await?;
+    let ready = response.status().is_success();
+    let body = response.text().await?;
+    match ready {
+        true => {
+            info!("Server is ready: {}", body);
+            Ok(())
+        }
+        false => Err(Error::server_not_ready(body)),
+    }
+}
+
+#[cfg(not(feature = "http-client"))]
+/// Check the ready route at `url` of a running server and return an error when it is not ready.
+pub async fn healthcheck<S: AsRef<str>>(_url: S) -> Result<(), Error> {
+    Err(Error::disabled_interface_feature("http-client".to_string()))
 }
 
 #[cfg(feature = "matrix")]
@@ -93,3 +349,40 @@ pub async fn verify_matrix_devices(_server_config: ServerConfiguration) -> Resul
     info!("Running Matrix device verification process");
     Err(Error::disabled_endpoint_feature("matrix".to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::Message;
+
+    fn dispatch(text: &str) -> Dispatch {
+        Dispatch::new("key", ValidatedNotification::new("test", Message::new(text)))
+    }
+
+    #[test]
+    fn busy_endpoint_does_not_hold_up_the_others() {
+        let stats = ServerStats::default();
+        let (busy, _busy_rx) = mpsc::channel(1);
+        let (idle, mut idle_rx) = mpsc::channel(2);
+
+        for text in ["one", "two"] {
+            pass_on("busy", &busy, dispatch(text), &stats);
+            pass_on("idle", &idle, dispatch(text), &stats);
+        }
+
+        assert!(idle_rx.try_recv().is_ok());
+        assert!(idle_rx.try_recv().is_ok());
+        assert_eq!(stats.record_busy("busy"), 2);
+        assert_eq!(stats.record_busy("idle"), 1);
+    }
+
+    #[test]
+    fn stopped_endpoint_is_not_counted_as_busy() {
+        let stats = ServerStats::default();
+        let (stopped, stopped_rx) = mpsc::channel(1);
+        drop(stopped_rx);
+
+        pass_on("stopped", &stopped, dispatch("one"), &stats);
+        assert_eq!(stats.record_busy("stopped"), 1);
+    }
+}
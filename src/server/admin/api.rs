@@ -0,0 +1,360 @@
+use crate::interfaces::InterfaceMessage;
+use crate::notifications::Message;
+use crate::server::admin::{Admin, Controls};
+use crate::server::health::Status;
+use crate::server::reload::{ReloadSummary, Reloader};
+use crate::server::routing::Routes;
+use crate::server::stats::StatsSnapshot;
+use crate::{Error, ServerStatus};
+use axum::extract::{Path, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, error, info};
+
+const ADMIN_INTERFACE: &str = "admin";
+const GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Everything the admin API handlers need from the running server.
+#[derive(Clone)]
+pub(crate) struct AdminState {
+    token: blake3::Hash,
+    controls: Arc<Controls>,
+    status: ServerStatus,
+    routes: watch::Receiver<Arc<Routes>>,
+    reloader: Reloader,
+    interface_tx: mpsc::Sender<InterfaceMessage>,
+}
+
+#[derive(Debug, Serialize)]
+struct InterfaceView {
+    name: String,
+    status: Status,
+}
+
+#[derive(Debug, Serialize)]
+struct EndpointView {
+    name: String,
+    status: Status,
+    paused: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct MuteView {
+    notification: String,
+    remaining_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MuteRequest {
+    notification: String,
+    duration_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct InjectRequest {
+    notification: String,
+    text: String,
+    title: Option<String>,
+}
+
+impl AdminState {
+    pub fn new(
+        admin: &Admin,
+        controls: Arc<Controls>,
+        status: ServerStatus,
+        routes: watch::Receiver<Arc<Routes>>,
+        reloader: Reloader,
+        interface_tx: mpsc::Sender<InterfaceMessage>,
+    ) -> Self {
+        let token = blake3::hash(admin.token().as_bytes());
+        Self { token, controls, status, routes, reloader, interface_tx }
+    }
+
+    /// Return an error response when the notification `name` is not configured on any endpoint.
+    fn check_notification(&self, name: &str) -> Result<String, (StatusCode, String)> {
+        let key_id = self.controls.key_id(name);
+        match self.routes.borrow().table().contains(key_id.as_str()) {
+            true => Ok(key_id),
+            false => Err((StatusCode::NOT_FOUND, format!("notification name {} is not configured", name))),
+        }
+    }
+}
+
+/// Bind the admin API to its address and serve it until shutdown.
+pub(crate) fn start_admin(admin: &Admin, state: AdminState, shutdown: watch::Receiver<bool>) -> Result<(), Error> {
+    let routes = admin_routes(state);
+
+    info!("Setting up admin API on -> {}", admin.address());
+    let listener = std::net::TcpListener::bind(admin.address())?;
+    listener.set_nonblocking(true)?;
+    let server = axum_server::from_tcp(listener)?;
+
+    let handle = axum_server::Handle::new();
+    tokio::spawn(shutdown_admin(handle.clone(), shutdown));
+    tokio::spawn(async move {
+        if let Err(e) = server.handle(handle).serve(routes.into_make_service()).await {
+            error!("Admin API error: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// Return the admin API routes, every route requires the admin bearer token.
+fn admin_routes(state: AdminState) -> Router {
+    Router::new()
+        .route("/admin/interfaces", get(interfaces_handler))
+        .route("/admin/endpoints", get(endpoints_handler))
+        .route("/admin/endpoints/{name}/pause", post(pause_handler))
+        .route("/admin/endpoints/{name}/resume", post(resume_handler))
+        .route("/admin/stats", get(stats_handler))
+        .route("/admin/mutes", get(mutes_handler).post(mute_handler))
+        .route("/admin/mutes/{notification}", delete(unmute_handler))
+        .route("/admin/notifications", post(inject_handler))
+        .route("/admin/reload", post(reload_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+/// Reject requests without the admin bearer token.
+async fn authorize(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    // Compare hashes so the comparison takes the same time however much of the token matches
+    match token.is_some_and(|token| blake3::hash(token.as_bytes()) == state.token) {
+        true => next.run(request).await,
+        false => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+async fn interfaces_handler(State(state): State<AdminState>) -> Json<Vec<InterfaceView>> {
+    let readiness = state.status.health().readiness();
+    let interfaces = readiness
+        .interfaces()
+        .iter()
+        .map(|(name, status)| InterfaceView { name: name.clone(), status: *status })
+        .collect();
+    Json(interfaces)
+}
+
+async fn endpoints_handler(State(state): State<AdminState>) -> Json<Vec<EndpointView>> {
+    let readiness = state.status.health().readiness();
+    let endpoints = readiness
+        .endpoints()
+        .iter()
+        .map(|(name, status)| EndpointView {
+            name: name.clone(),
+            status: *status,
+            paused: state.controls.is_paused(name),
+        })
+        .collect();
+    Json(endpoints)
+}
+
+async fn stats_handler(State(state): State<AdminState>) -> Json<StatsSnapshot> {
+    Json(state.status.stats().snapshot())
+}
+
+async fn pause_handler(State(state): State<AdminState>, Path(name): Path<String>) -> StatusCode {
+    set_paused(&state, name.as_str(), true)
+}
+
+async fn resume_handler(State(state): State<AdminState>, Path(name): Path<String>) -> StatusCode {
+    set_paused(&state, name.as_str(), false)
+}
+
+fn set_paused(state: &AdminState, name: &str, paused: bool) -> StatusCode {
+    match state.controls.set_paused(name, paused) {
+        true => {
+            info!("Admin API {} endpoint {}", if paused { "paused" } else { "resumed" }, name);
+            StatusCode::NO_CONTENT
+        }
+        false => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn mutes_handler(State(state): State<AdminState>) -> Json<Vec<MuteView>> {
+    let mutes = state
+        .controls
+        .mutes()
+        .iter()
+        .map(|mute| MuteView { notification: mute.name().into(), remaining_secs: mute.remaining().as_secs() })
+        .collect();
+    Json(mutes)
+}
+
+async fn mute_handler(
+    State(state): State<AdminState>,
+    Json(request): Json<MuteRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.check_notification(request.notification.as_str())?;
+    if !state.controls.mute(request.notification.as_str(), Duration::from_secs(request.duration_secs)) {
+        return Err((StatusCode::BAD_REQUEST, format!("duration_secs {} is too long", request.duration_secs)));
+    }
+    info!("Admin API muted notification name {} for {} seconds", request.notification, request.duration_secs);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unmute_handler(State(state): State<AdminState>, Path(notification): Path<String>) -> StatusCode {
+    match state.controls.unmute(notification.as_str()) {
+        true => {
+            info!("Admin API unmuted notification name {}", notification);
+            StatusCode::NO_CONTENT
+        }
+        false => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn inject_handler(
+    State(state): State<AdminState>,
+    Json(request): Json<InjectRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.check_notification(request.notification.as_str())?;
+    let message = match request.title {
+        Some(title) => Message::new(request.text).with_title(title),
+        None => Message::new(request.text),
+    };
+    let notification = message.to_client_ready_message(request.notification).to_notification(state.controls.key());
+    let payload = notification.to_json().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    debug!("Admin API injecting notification {:?}", notification);
+    match state.interface_tx.send(InterfaceMessage::new(ADMIN_INTERFACE, payload)).await {
+        Ok(_) => Ok(StatusCode::ACCEPTED),
+        Err(e) => Err((StatusCode::SERVICE_UNAVAILABLE, e.to_string())),
+    }
+}
+
+async fn reload_handler(State(state): State<AdminState>) -> Result<Json<ReloadSummary>, (StatusCode, String)> {
+    info!("Admin API reloading configuration");
+    match state.reloader.reload().await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string())),
+    }
+}
+
+async fn shutdown_admin(handle: axum_server::Handle<std::net::SocketAddr>, mut shutdown: watch::Receiver<bool>) {
+    match shutdown.changed().await {
+        Ok(_) => {
+            debug!("admin API starting graceful shutdown");
+            handle.graceful_shutdown(Some(GRACE_PERIOD));
+        }
+        Err(e) => error!("Shutdown Receive Error: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::Key;
+    use crate::server::routing::Routes;
+    use axum::body::Body;
+    use axum::http::Method;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "admin-token-for-tests";
+
+    fn controls() -> Arc<Controls> {
+        Arc::new(Controls::new(Key::derive_shared_key("sdfsf4633ghf44dfhdfhQdhdfhewaasg")))
+    }
+
+    fn routes(controls: Arc<Controls>) -> Router {
+        let admin = Admin::new("127.0.0.1:0".parse().unwrap(), TOKEN).unwrap();
+        let (_, routes) = watch::channel(Arc::new(Routes::new(&[], Vec::new(), Vec::new())));
+        let (reloader, _) = Reloader::new();
+        let (interface_tx, _) = mpsc::channel(1);
+        admin_routes(AdminState::new(&admin, controls, ServerStatus::default(), routes, reloader, interface_tx))
+    }
+
+    async fn send(routes: Router, method: Method, uri: &str, authorization: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        routes.oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn request_needs_the_bearer_token() {
+        let routes = routes(controls());
+        let bearer = format!("Bearer {}", TOKEN);
+        let wrong = format!("Bearer {}-wrong", TOKEN);
+
+        assert_eq!(send(routes.clone(), Method::GET, "/admin/endpoints", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(routes.clone(), Method::GET, "/admin/endpoints", Some(&wrong)).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(routes.clone(), Method::GET, "/admin/endpoints", Some(TOKEN)).await, StatusCode::UNAUTHORIZED);
+        let basic = format!("Basic {}", TOKEN);
+        assert_eq!(send(routes.clone(), Method::GET, "/admin/endpoints", Some(&basic)).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(routes, Method::GET, "/admin/endpoints", Some(&bearer)).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn endpoint_is_paused_and_resumed() {
+        let controls = controls();
+        controls.set_endpoints(vec![("file".to_string(), watch::channel(false).0)]);
+        let routes = routes(controls.clone());
+        let bearer = format!("Bearer {}", TOKEN);
+
+        assert_eq!(
+            send(routes.clone(), Method::POST, "/admin/endpoints/file/pause", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(!controls.is_paused("file"));
+
+        assert_eq!(
+            send(routes.clone(), Method::POST, "/admin/endpoints/file/pause", Some(&bearer)).await,
+            StatusCode::NO_CONTENT
+        );
+        assert!(controls.is_paused("file"));
+        assert_eq!(
+            send(routes.clone(), Method::POST, "/admin/endpoints/file/resume", Some(&bearer)).await,
+            StatusCode::NO_CONTENT
+        );
+        assert!(!controls.is_paused("file"));
+
+        assert_eq!(
+            send(routes, Method::POST, "/admin/endpoints/matrix/pause", Some(&bearer)).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn notification_name_is_unmuted() {
+        let controls = controls();
+        let routes = routes(controls.clone());
+        let bearer = format!("Bearer {}", TOKEN);
+        assert!(controls.mute("backup", Duration::from_secs(60)));
+
+        assert_eq!(
+            send(routes.clone(), Method::DELETE, "/admin/mutes/backup", Some(&bearer)).await,
+            StatusCode::NO_CONTENT
+        );
+        assert!(!controls.is_muted(controls.key_id("backup").as_str()));
+        assert_eq!(send(routes, Method::DELETE, "/admin/mutes/backup", Some(&bearer)).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn unknown_notification_name_is_not_muted() {
+        let controls = controls();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/admin/mutes")
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"notification": "backup", "duration_secs": 60}"#))
+            .unwrap();
+
+        let response = routes(controls.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(controls.mutes().is_empty());
+    }
+}
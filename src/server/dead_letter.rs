@@ -0,0 +1,226 @@
+//! Dead-letter file for notifications that could not be delivered
+//!
+//! When a dead-letter file is configured the server appends a JSON line for every notification an endpoint
+//! fails to deliver permanently, like a Discord webhook that no longer exists or an SMTP login that is rejected.
+//! Each line records the time, the endpoint, the error and the notification. Without a [spool][crate::Spool]
+//! notifications are also written when an endpoint gives up after its retries, with a spool those stay in the
+//! spool and are sent again on the next start instead.
+//!
+//! Once the problem is fixed start the server with `--reinject-dead-letters` to send the dead letters to their
+//! endpoints again. Dead letters are matched to endpoints the same way as the spool, by `name` or position, and
+//! stay in the file until they are handed to their endpoints. Lines that cannot be read are kept as they are.
+//!
+//! # Configuration Example
+//! ```toml
+//! [server.dead_letter]
+//! path = '/var/lib/pass-it-on/dead_letters.jsonl'
+//! ```
+
+use crate::notifications::{Message, ValidatedNotification};
+use crate::Error;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Settings for the dead-letter file.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DeadLetter {
+    path: PathBuf,
+    reinject: bool,
+}
+
+/// Data structure to represent [`DeadLetter`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DeadLetterConfigFile {
+    path: String,
+}
+
+/// Notification that could not be delivered to an endpoint.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub(crate) struct DeadLetterRecord {
+    time: String,
+    endpoint: String,
+    error: String,
+    permanent: bool,
+    sub_name: String,
+    message: Message,
+}
+
+/// Append only file of [`DeadLetterRecord`]s.
+#[derive(Debug)]
+pub(crate) struct DeadLetters {
+    path: PathBuf,
+    file: File,
+}
+
+impl DeadLetter {
+    /// Create a new `DeadLetter` that writes to the file at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().into(), reinject: false }
+    }
+
+    /// Send the dead letters to their endpoints again when the server starts.
+    pub fn with_reinject(mut self, reinject: bool) -> Self {
+        self.reinject = reinject;
+        self
+    }
+
+    /// Return the path of the dead-letter file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return if the dead letters are sent again when the server starts.
+    pub fn reinject(&self) -> bool {
+        self.reinject
+    }
+}
+
+impl TryFrom<&DeadLetterConfigFile> for DeadLetter {
+    type Error = Error;
+
+    fn try_from(value: &DeadLetterConfigFile) -> Result<Self, Self::Error> {
+        if value.path.is_empty() {
+            return Err(Error::invalid_server_configuration("dead_letter path is blank".to_string()));
+        }
+        Ok(DeadLetter::new(value.path.as_str()))
+    }
+}
+
+impl DeadLetterRecord {
+    pub fn new<S: AsRef<str>>(endpoint: S, notification: &ValidatedNotification, error: S, permanent: bool) -> Self {
+        Self {
+            time: Timestamp::now().to_string(),
+            endpoint: endpoint.as_ref().into(),
+            error: error.as_ref().into(),
+            permanent,
+            sub_name: notification.sub_name().into(),
+            message: notification.message().clone(),
+        }
+    }
+
+    /// Label of the endpoint that failed to deliver the notification.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn notification(&self) -> ValidatedNotification {
+        ValidatedNotification::new(self.sub_name.as_str(), self.message.clone())
+    }
+}
+
+impl DeadLetters {
+    /// Open the dead-letter file for appending, creating it and its directory when needed.
+    pub fn open(dead_letter: &DeadLetter) -> Result<Self, Error> {
+        if let Some(parent) = dead_letter.path().parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let path = dead_letter.path().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self { path, file })
+    }
+
+    /// Append the `record` to the file.
+    pub fn record(&mut self, record: &DeadLetterRecord) -> Result<(), Error> {
+        let line = serde_json::to_string(record)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Return the records that `matches` accepts, they stay in the file until they are [removed][Self::remove].
+    pub fn read<F: Fn(&DeadLetterRecord) -> bool>(&self, matches: F) -> Result<Vec<DeadLetterRecord>, Error> {
+        let mut records = Vec::new();
+        for (number, line) in BufReader::new(File::open(&self.path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<DeadLetterRecord>(line.as_str()) {
+                Ok(record) if matches(&record) => records.push(record),
+                Ok(record) => warn!("Keeping dead letter for unknown endpoint {}", record.endpoint()),
+                Err(e) => warn!("Keeping unreadable dead letter line {}: {}", number + 1, e),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Remove the `records` from the file, keeping every other line as it is.
+    ///
+    /// The remaining lines are written to a temporary file that replaces the dead-letter file so a crash leaves
+    /// either the old or the new file.
+    pub fn remove(&mut self, records: &[DeadLetterRecord]) -> Result<(), Error> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut removed = records.to_vec();
+        let mut kept = Vec::new();
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            let line = line?;
+            let record = serde_json::from_str::<DeadLetterRecord>(line.as_str()).ok();
+            match record.and_then(|record| removed.iter().position(|removed| *removed == record)) {
+                Some(index) => drop(removed.swap_remove(index)),
+                None if line.trim().is_empty() => (),
+                None => kept.push(line),
+            }
+        }
+
+        let mut temporary_path = self.path.as_os_str().to_owned();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+        let mut temporary = File::create(&temporary_path)?;
+        for line in kept.iter() {
+            writeln!(temporary, "{}", line)?;
+        }
+        temporary.sync_data()?;
+        std::fs::rename(&temporary_path, &self.path)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(endpoint: &str, text: &str) -> DeadLetterRecord {
+        let notification = ValidatedNotification::new("test", Message::new(text));
+        DeadLetterRecord::new(endpoint, &notification, "failed", true)
+    }
+
+    #[test]
+    fn removes_only_sent_dead_letters() {
+        let path = std::env::temp_dir().join("pass-it-on-dead-letter-test.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let mut dead_letters = DeadLetters::open(&DeadLetter::new(&path)).unwrap();
+        dead_letters.record(&record("file", "1")).unwrap();
+        dead_letters.record(&record("unknown", "2")).unwrap();
+        writeln!(dead_letters.file, "not a dead letter").unwrap();
+        dead_letters.record(&record("file", "3")).unwrap();
+
+        let records = dead_letters.read(|record| record.endpoint() == "file").unwrap();
+        assert_eq!(records.len(), 2);
+        // Reading leaves the file as it is
+        assert_eq!(dead_letters.read(|record| record.endpoint() == "file").unwrap(), records);
+
+        dead_letters.remove(&records[..1]).unwrap();
+        dead_letters.record(&record("file", "4")).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "not a dead letter");
+        let texts: Vec<_> = dead_letters
+            .read(|record| record.endpoint() == "file")
+            .unwrap()
+            .iter()
+            .map(|record| record.notification().message().text().to_string())
+            .collect();
+        assert_eq!(texts, vec!["3", "4"]);
+    }
+}
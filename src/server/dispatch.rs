@@ -0,0 +1,333 @@
+use crate::endpoints::batch::Batcher;
+use crate::endpoints::dedup::{Dedup, Deduplicator};
+use crate::endpoints::delivery::{DeliveryOutcome, Receipt};
+use crate::endpoints::queue::Outbox;
+use crate::endpoints::rate_limit::RateLimiter;
+use crate::endpoints::schedule::Scheduler;
+use crate::endpoints::{EndpointChannel, EndpointOptions, Stage};
+use crate::notifications::ValidatedNotification;
+use crate::server::routing::Routes;
+use crate::server::spool::JournalWriter;
+use crate::{Error, ServerStatus, CHANNEL_BUFFER};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// A [`ValidatedNotification`] on its way to an endpoint with the identifier of its notification name key.
+#[derive(Debug, Clone)]
+pub(crate) struct Dispatch {
+    key_id: String,
+    notification: ValidatedNotification,
+}
+
+/// Stages applied to the notifications for a single endpoint before they are sent to its queue.
+struct Dispatcher {
+    label: String,
+    outbox: Outbox,
+    dedup: Option<Deduplicator>,
+    stages: Vec<Box<dyn Stage>>,
+    reported_drops: Vec<u64>,
+    reported_queue_drops: u64,
+    fallback: Option<String>,
+    routes: watch::Receiver<Arc<Routes>>,
+    tracking: Option<Tracking>,
+    #[cfg(feature = "metrics")]
+    status: ServerStatus,
+}
+
+/// Where a dispatcher records the notifications it passes to an endpoint until they are delivered.
+#[derive(Clone)]
+pub(crate) struct Tracking {
+    journal: Option<JournalWriter>,
+    next_id: Arc<AtomicU64>,
+    outcomes: mpsc::UnboundedSender<DeliveryOutcome>,
+}
+
+impl Dispatch {
+    pub fn new<S: AsRef<str>>(key_id: S, notification: ValidatedNotification) -> Self {
+        Self { key_id: key_id.as_ref().into(), notification }
+    }
+
+    #[cfg(test)]
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    #[cfg(test)]
+    pub fn notification(&self) -> &ValidatedNotification {
+        &self.notification
+    }
+}
+
+impl Tracking {
+    /// Track notifications in the `journal` when there is one, otherwise only report their delivery to `outcomes`.
+    pub fn new(journal: Option<JournalWriter>, outcomes: mpsc::UnboundedSender<DeliveryOutcome>) -> Self {
+        Self { journal, next_id: Arc::new(AtomicU64::new(1)), outcomes }
+    }
+
+    /// Record the notification for the endpoint in the spool journal and attach a [`Receipt`] to report its delivery.
+    ///
+    /// Notifications that already have a receipt, like the ones replayed from the spool, are tracked already.
+    fn track(&self, label: &str, notification: ValidatedNotification) -> ValidatedNotification {
+        if notification.has_receipt() {
+            return notification;
+        }
+
+        let id = match self.journal.as_ref() {
+            None => self.next_id.fetch_add(1, Ordering::Relaxed),
+            Some(journal) => journal.add(label, &notification),
+        };
+        notification.with_receipt(Receipt::new(label, id, self.outcomes.clone()))
+    }
+}
+
+/// Label that identifies an endpoint in logs and the spool, its name or otherwise its position.
+pub(crate) fn endpoint_label(index: usize, options: &EndpointOptions) -> String {
+    options.name().map(String::from).unwrap_or_else(|| format!("#{}", index))
+}
+
+/// Start the dispatcher for the endpoint with `label` and return the channel to send it notifications.
+///
+/// The dispatcher runs until `shutdown` and the returned task gives back the notifications it still held, so they
+/// can be passed to the endpoint that replaces it when the configuration is reloaded. The fallback endpoint of its
+/// schedule is looked up in the current `routes` each time notifications are rerouted.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn start_dispatcher(
+    endpoint: &EndpointChannel,
+    label: &str,
+    default_dedup: Option<Dedup>,
+    tracking: Tracking,
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))] status: ServerStatus,
+    pause: watch::Receiver<bool>,
+    routes: watch::Receiver<Arc<Routes>>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(mpsc::Sender<Dispatch>, JoinHandle<Vec<ValidatedNotification>>), Error> {
+    let (dispatch_tx, dispatch_rx) = mpsc::channel(CHANNEL_BUFFER);
+    let options = endpoint.endpoint().options();
+    let dedup = options.dedup().or(default_dedup).filter(Dedup::is_enabled).map(Deduplicator::new);
+
+    let mut stages: Vec<Box<dyn Stage>> = Vec::new();
+    if let Some(schedule) = options.schedule() {
+        stages.push(Box::new(Scheduler::new(schedule.clone())));
+    }
+    if let Some(batch) = options.batch() {
+        stages.push(Box::new(Batcher::new(batch)));
+    }
+    if let Some(rate_limit) = options.rate_limit() {
+        stages.push(Box::new(RateLimiter::new(rate_limit, Instant::now())));
+    }
+
+    let reported_drops = vec![0; stages.len()];
+    let mut outbox = Outbox::new(&options.queue().cloned().unwrap_or_default(), endpoint.channel_sender())?;
+    outbox.set_paused(*pause.borrow());
+    let tracking = Some(tracking).filter(|_| endpoint.endpoint().reports_delivery());
+    let dispatcher = Dispatcher {
+        label: label.into(),
+        outbox,
+        dedup,
+        stages,
+        reported_drops,
+        reported_queue_drops: 0,
+        fallback: options.schedule().and_then(|schedule| schedule.fallback()).map(String::from),
+        routes,
+        tracking,
+        #[cfg(feature = "metrics")]
+        status,
+    };
+    let task = tokio::spawn(async move { dispatcher.run(dispatch_rx, pause, shutdown).await });
+    Ok((dispatch_tx, task))
+}
+
+impl Dispatcher {
+    async fn run(
+        mut self,
+        mut dispatch_rx: mpsc::Receiver<Dispatch>,
+        mut pause: watch::Receiver<bool>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Vec<ValidatedNotification> {
+        loop {
+            let deadline = self.next_deadline();
+            tokio::select! {
+                received = dispatch_rx.recv(), if !self.outbox.is_blocked() => {
+                    match received {
+                        Some(dispatch) => {
+                            let ready = self.push(dispatch, Instant::now());
+                            self.send(ready);
+                            self.report_drops();
+                            self.reroute().await;
+                        }
+                        None => break,
+                    }
+                }
+
+                _ = wait_until(deadline) => {
+                    let ready = self.poll(Instant::now());
+                    self.send(ready);
+                    self.report_drops();
+                    self.reroute().await;
+                }
+
+                Ok(_) = pause.changed() => {
+                    let paused = *pause.borrow_and_update();
+                    debug!("Endpoint {} {}", self.label, if paused { "paused" } else { "resumed" });
+                    self.outbox.set_paused(paused);
+                    self.send(Vec::new());
+                }
+
+                _ = shutdown.changed() => {
+                    break;
+                }
+            }
+        }
+        self.take_pending(&mut dispatch_rx)
+    }
+
+    /// Take the notifications held by the queue and the stages, and the ones not read yet.
+    fn take_pending(&mut self, dispatch_rx: &mut mpsc::Receiver<Dispatch>) -> Vec<ValidatedNotification> {
+        let mut pending = self.outbox.take_held();
+        for stage in self.stages.iter_mut().rev() {
+            pending.extend(stage.take_pending());
+        }
+        while let Ok(dispatch) = dispatch_rx.try_recv() {
+            pending.push(dispatch.notification);
+        }
+        pending
+    }
+
+    fn push(&mut self, dispatch: Dispatch, now: Instant) -> Vec<ValidatedNotification> {
+        // Notifications with a receipt, like the ones replayed from the spool, went through the stages before they
+        // were tracked and the stages would lose their receipt
+        if dispatch.notification.has_receipt() {
+            return vec![dispatch.notification];
+        }
+
+        let ready = match self.dedup.as_mut() {
+            None => vec![dispatch.notification],
+            Some(dedup) => {
+                let ready = dedup.push(dispatch.key_id.as_str(), dispatch.notification, now);
+                if ready.is_empty() {
+                    debug!("Suppressed repeated notification");
+                }
+                ready
+            }
+        };
+        self.forward(0, ready, now)
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<ValidatedNotification> {
+        let released = self.dedup.as_mut().map(|dedup| dedup.poll(now)).unwrap_or_default();
+        let mut ready = self.forward(0, released, now);
+        for index in 0..self.stages.len() {
+            let released = self.stages[index].poll(now);
+            ready.extend(self.forward(index + 1, released, now));
+        }
+        ready
+    }
+
+    /// Pass notifications through the stages starting at `start`.
+    fn forward(
+        &mut self,
+        start: usize,
+        mut notifications: Vec<ValidatedNotification>,
+        now: Instant,
+    ) -> Vec<ValidatedNotification> {
+        for stage in self.stages.iter_mut().skip(start) {
+            notifications = notifications.into_iter().flat_map(|notification| stage.push(notification, now)).collect();
+        }
+        notifications
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let dedup = self.dedup.as_ref().and_then(Deduplicator::next_deadline);
+        let queue = self.outbox.next_deadline(Instant::now());
+        self.stages.iter().filter_map(|stage| stage.next_deadline()).chain(dedup).chain(queue).min()
+    }
+
+    fn report_drops(&mut self) {
+        for (stage, reported) in self.stages.iter().zip(self.reported_drops.iter_mut()) {
+            let dropped = stage.dropped();
+            if dropped > *reported {
+                warn!(
+                    "{} dropped {} notification(s) for endpoint {}, {} dropped in total, {} held",
+                    stage.name(),
+                    dropped - *reported,
+                    self.label,
+                    dropped,
+                    stage.pending()
+                );
+                *reported = dropped;
+            }
+        }
+
+        let dropped = self.outbox.dropped();
+        if dropped > self.reported_queue_drops {
+            warn!(
+                "queue dropped {} notification(s) for endpoint {}, {} dropped in total, {} waiting",
+                dropped - self.reported_queue_drops,
+                self.label,
+                dropped,
+                self.outbox.pending()
+            );
+            self.reported_queue_drops = dropped;
+        }
+    }
+
+    /// Send notifications rerouted by the stages to the fallback endpoint.
+    async fn reroute(&mut self) {
+        let rerouted: Vec<_> = self.stages.iter_mut().flat_map(|stage| stage.take_rerouted()).collect();
+        if rerouted.is_empty() {
+            return;
+        }
+
+        let fallback = self.fallback.as_deref().and_then(|name| self.routes.borrow().find_endpoint(name).cloned());
+        let fallback = match fallback {
+            Some(fallback) => fallback,
+            None => {
+                warn!("No fallback endpoint for endpoint {}, dropped {} notification(s)", self.label, rerouted.len());
+                return;
+            }
+        };
+
+        let source = format!("fallback from {}", self.label);
+        for notification in rerouted {
+            debug!("Rerouting notification from endpoint {} to fallback", self.label);
+            for sub_name in fallback.sub_names() {
+                let dispatch =
+                    Dispatch::new(&source, ValidatedNotification::new(sub_name, notification.message().clone()));
+                if let Err(e) = fallback.dispatcher().send(dispatch).await {
+                    warn!("Error sending rerouted message to fallback endpoint dispatcher: {}", e)
+                }
+            }
+        }
+    }
+
+    /// Send notifications to the endpoint queue, tracking their delivery when the endpoint reports it.
+    ///
+    /// Notifications are held by the queue while the endpoint is paused and tracked when they arrive, so the spool
+    /// keeps them when the server stops before the endpoint is resumed.
+    fn send(&mut self, notifications: Vec<ValidatedNotification>) {
+        let tracking = self.tracking.as_ref();
+        let label = self.label.as_str();
+        let prepare = |notification| match tracking {
+            None => notification,
+            Some(tracking) => tracking.track(label, notification),
+        };
+
+        self.outbox.flush(prepare);
+        for notification in notifications {
+            self.outbox.send(notification, prepare);
+        }
+        #[cfg(feature = "metrics")]
+        self.status.metrics().set_queue_waiting(self.label.as_str(), self.outbox.pending());
+    }
+}
+
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
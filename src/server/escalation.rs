@@ -0,0 +1,95 @@
+//! Escalation chains between named endpoints
+//!
+//! An escalation chain lists endpoints by `name` in the order they are tried. When an endpoint in a chain fails to
+//! deliver a notification, after its own retries, the notification is sent to every sub-group of the next endpoint
+//! in the chain. A notification that the last endpoint fails to deliver is handled like any other failed
+//! delivery and recorded in the [dead letters][crate::DeadLetter] when they are configured.
+//!
+//! Every endpoint except the last must report its delivery results, which all the built-in endpoints do.
+//! An endpoint can only be in one chain and only once.
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.escalation]]
+//! chain = ["matrix-ops", "email-ops", "file-log"]
+//! ```
+
+use crate::endpoints::Endpoint;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Endpoints that a notification is sent to in turn until one delivers it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Escalation {
+    chain: Vec<String>,
+}
+
+/// Data structure to represent [`Escalation`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EscalationConfigFile {
+    chain: Vec<String>,
+}
+
+impl Escalation {
+    /// Create a new `Escalation` that tries the endpoints named in `chain` in order.
+    pub fn new<S: AsRef<str>>(chain: &[S]) -> Self {
+        Self { chain: chain.iter().map(|name| name.as_ref().into()).collect() }
+    }
+
+    /// Return the names of the endpoints in the order they are tried.
+    pub fn chain(&self) -> &[String] {
+        &self.chain
+    }
+}
+
+impl From<&EscalationConfigFile> for Escalation {
+    fn from(value: &EscalationConfigFile) -> Self {
+        Escalation::new(value.chain.as_slice())
+    }
+}
+
+/// Return the position of the endpoint each endpoint escalates to, in the same order as `endpoints`.
+pub(crate) fn escalation_targets(
+    escalations: &[Escalation],
+    endpoints: &[Box<dyn Endpoint + Send>],
+) -> Result<Vec<Option<usize>>, Error> {
+    let position = |name: &str| {
+        endpoints.iter().position(|endpoint| endpoint.options().name() == Some(name)).ok_or_else(|| {
+            Error::invalid_server_configuration(format!("escalation endpoint {} is not defined", name))
+        })
+    };
+
+    let mut targets = vec![None; endpoints.len()];
+    let mut seen = HashSet::new();
+    for escalation in escalations {
+        if escalation.chain.len() < 2 {
+            return Err(Error::invalid_server_configuration(
+                "escalation chain requires at least 2 endpoints".to_string(),
+            ));
+        }
+
+        let chain = escalation.chain.iter().map(|name| position(name)).collect::<Result<Vec<_>, _>>()?;
+        for (name, index) in escalation.chain.iter().zip(&chain) {
+            if !seen.insert(*index) {
+                return Err(Error::invalid_server_configuration(format!(
+                    "endpoint {} is used more than once in escalation chains",
+                    name
+                )));
+            }
+        }
+
+        for (pair, names) in chain.windows(2).zip(escalation.chain.windows(2)) {
+            if !endpoints[pair[0]].reports_delivery() {
+                return Err(Error::invalid_server_configuration(format!(
+                    "endpoint {} does not report delivery and cannot escalate",
+                    names[0]
+                )));
+            }
+            targets[pair[0]] = Some(pair[1]);
+        }
+    }
+    Ok(targets)
+}
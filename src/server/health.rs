@@ -0,0 +1,233 @@
+//! Health and readiness of the server
+//!
+//! The HTTP interface serves two routes for container orchestration next to the `version` route:
+//!
+//! | Route                | Description                                                                   |
+//! |----------------------|-------------------------------------------------------------------------------|
+//! | `/pass-it-on/health` | Returns 200 while the server process is running.                              |
+//! | `/pass-it-on/ready`  | Returns 200 when every interface and endpoint is running, otherwise 503.      |
+//!
+//! Both routes return the status as JSON and the ready route includes the status of each interface and endpoint.
+//!
+//! An interface is down when it stops receiving, like a pipe reader that failed, and an endpoint is down when it
+//! stops taking notifications. Interfaces are identified by the name used in the logs and endpoints by their
+//! `name`, or by their position in the configuration when they do not have one. The server is not ready until it
+//! has finished starting.
+//!
+//! ```json
+//! {"status":"down","interfaces":{"http http://0.0.0.0:8080/":"up"},"endpoints":{"matrix-ops":"down","#1":"up"}}
+//! ```
+//!
+//! The `pass-it-on-server healthcheck` command checks the ready route of a running server and exits with a
+//! failure when it is not ready, for use as a Docker `HEALTHCHECK`.
+
+#![cfg_attr(not(feature = "http-server"), allow(dead_code))]
+
+use crate::notifications::ValidatedNotification;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Running state of the server components.
+#[derive(Debug, Default)]
+pub(crate) struct Health {
+    components: Mutex<Components>,
+}
+
+/// Interfaces and endpoints checked for readiness.
+#[derive(Debug, Default)]
+struct Components {
+    started: bool,
+    next_interface: u64,
+    interfaces: Vec<Component>,
+    endpoints: Vec<(String, broadcast::WeakSender<ValidatedNotification>)>,
+}
+
+/// Interface that is up until it stops receiving.
+#[derive(Debug)]
+struct Component {
+    id: u64,
+    name: String,
+    up: bool,
+}
+
+/// Status of the server or one of its interfaces or endpoints.
+#[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Up,
+    Down,
+    Starting,
+}
+
+/// Status returned by the health route.
+#[derive(Debug, Serialize, Clone, Eq, PartialEq)]
+pub(crate) struct Liveness {
+    status: Status,
+}
+
+/// Breakdown of the status of each interface and endpoint returned by the ready route.
+#[derive(Debug, Serialize, Clone, Eq, PartialEq)]
+pub(crate) struct Readiness {
+    status: Status,
+    interfaces: BTreeMap<String, Status>,
+    endpoints: BTreeMap<String, Status>,
+}
+
+impl From<bool> for Status {
+    fn from(value: bool) -> Self {
+        match value {
+            true => Status::Up,
+            false => Status::Down,
+        }
+    }
+}
+
+impl Readiness {
+    /// Return if the server and all of its interfaces and endpoints are up.
+    pub fn is_ready(&self) -> bool {
+        self.status == Status::Up
+    }
+
+    pub fn interfaces(&self) -> &BTreeMap<String, Status> {
+        &self.interfaces
+    }
+
+    pub fn endpoints(&self) -> &BTreeMap<String, Status> {
+        &self.endpoints
+    }
+}
+
+impl Health {
+    /// Record that the server has finished starting.
+    pub fn set_started(&self) {
+        self.components.lock().unwrap().started = true;
+    }
+
+    /// Add a receiving interface with `name` and return the identifier to report when it stops.
+    pub fn add_interface(&self, name: &str) -> u64 {
+        let mut components = self.components.lock().unwrap();
+        let id = components.next_interface;
+        components.next_interface += 1;
+        components.interfaces.push(Component { id, name: name.into(), up: true });
+        id
+    }
+
+    /// Record that the interface with `id` stopped receiving.
+    pub fn interface_stopped(&self, id: u64) {
+        let mut components = self.components.lock().unwrap();
+        if let Some(interface) = components.interfaces.iter_mut().find(|interface| interface.id == id) {
+            interface.up = false;
+        }
+    }
+
+    /// Forget the interface with `id` after it was stopped on purpose.
+    pub fn remove_interface(&self, id: u64) {
+        self.components.lock().unwrap().interfaces.retain(|interface| interface.id != id);
+    }
+
+    /// Replace the endpoints checked for readiness with the `channels` of the endpoints with `labels`.
+    ///
+    /// An endpoint is up while it holds a receiver for its channel.
+    pub fn register_endpoints(&self, labels: &[String], channels: Vec<broadcast::Sender<ValidatedNotification>>) {
+        let endpoints = labels.iter().cloned().zip(channels.iter().map(broadcast::Sender::downgrade)).collect();
+        self.components.lock().unwrap().endpoints = endpoints;
+    }
+
+    /// Return the status of the server and each of its interfaces and endpoints.
+    pub fn readiness(&self) -> Readiness {
+        let components = self.components.lock().unwrap();
+        let mut interfaces = BTreeMap::new();
+        for interface in components.interfaces.iter() {
+            let status = interfaces.entry(interface.name.clone()).or_insert(Status::Up);
+            if !interface.up {
+                *status = Status::Down;
+            }
+        }
+        let endpoints: BTreeMap<_, _> = components
+            .endpoints
+            .iter()
+            .map(|(label, channel)| {
+                let up = channel.upgrade().is_some_and(|channel| channel.receiver_count() > 0);
+                (label.clone(), up.into())
+            })
+            .collect();
+
+        let status = match components.started {
+            false => Status::Starting,
+            true => interfaces.values().chain(endpoints.values()).all(|status| *status == Status::Up).into(),
+        };
+        Readiness { status, interfaces, endpoints }
+    }
+}
+
+/// Return the status of the server process, which is up while it can answer.
+pub(crate) fn liveness() -> Liveness {
+    Liveness { status: Status::Up }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(readiness: &Readiness) -> serde_json::Value {
+        serde_json::to_value(readiness).unwrap()
+    }
+
+    #[test]
+    fn server_is_starting_until_it_has_started() {
+        let health = Health::default();
+        health.add_interface("pipe");
+        assert_eq!(health.readiness().status, Status::Starting);
+        assert!(!health.readiness().is_ready());
+
+        health.set_started();
+        assert!(health.readiness().is_ready());
+    }
+
+    #[test]
+    fn server_is_down_when_an_interface_stops() {
+        let health = Health::default();
+        health.set_started();
+        let first = health.add_interface("http http://0.0.0.0:8080/");
+        health.add_interface("http http://0.0.0.0:8080/");
+        let pipe = health.add_interface("pipe");
+
+        health.interface_stopped(first);
+        let readiness = health.readiness();
+        assert_eq!(
+            status(&readiness),
+            serde_json::json!({
+                "status": "down",
+                "interfaces": {"http http://0.0.0.0:8080/": "down", "pipe": "up"},
+                "endpoints": {}
+            })
+        );
+
+        // An interface stopped on purpose is left out
+        health.remove_interface(first);
+        health.remove_interface(pipe);
+        assert_eq!(
+            status(&health.readiness()),
+            serde_json::json!({"status": "up", "interfaces": {"http http://0.0.0.0:8080/": "up"}, "endpoints": {}})
+        );
+    }
+
+    #[test]
+    fn endpoint_is_up_while_it_receives() {
+        let health = Health::default();
+        health.set_started();
+        let (running, _receiver) = broadcast::channel(1);
+        let (stopped, _) = broadcast::channel(1);
+        health.register_endpoints(&["ops".to_string(), "#1".to_string()], vec![running.clone(), stopped]);
+
+        assert_eq!(
+            status(&health.readiness()),
+            serde_json::json!({"status": "down", "interfaces": {}, "endpoints": {"ops": "up", "#1": "down"}})
+        );
+
+        health.register_endpoints(&[], Vec::new());
+        assert!(health.readiness().is_ready());
+    }
+}
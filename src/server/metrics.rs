@@ -0,0 +1,345 @@
+//! Prometheus metrics for the server
+//!
+//! With the `metrics` feature the HTTP interface serves the metrics below in the Prometheus text format at
+//! `/pass-it-on/metrics`, next to the `version` route. Endpoints are labelled by their `name`, or by their
+//! position in the configuration when they do not have one.
+//!
+//! | Metric                                  | Type      | Labels            | Description                                                                   |
+//! |-----------------------------------------|-----------|-------------------|-------------------------------------------------------------------------------|
+//! | pass_it_on_notifications_received_total | counter   | interface         | Notifications received.                                                       |
+//! | pass_it_on_validation_failures_total    | counter   | interface, reason | Notifications not passed on as `invalid`, `unmatched`, `stale` or `duplicate`. |
+//! | pass_it_on_deliveries_total             | counter   | endpoint, outcome | Delivery results as `delivered`, `transient` or `permanent`.                  |
+//! | pass_it_on_queue_depth                  | gauge     | endpoint          | Notifications in the endpoint queue.                                          |
+//! | pass_it_on_queue_waiting                | gauge     | endpoint          | Notifications held, spilled or paused until they can be queued.               |
+//! | pass_it_on_delivery_latency_seconds     | histogram | endpoint          | Time from passing a notification to an endpoint to its delivery result.       |
+//!
+//! # Configuration Example
+//! ```toml
+//! [[server.interface]]
+//! type = "http"
+//! port = 8080
+//! ```
+//!
+//! ```text
+//! curl http://localhost:8080/pass-it-on/metrics
+//! ```
+
+use crate::endpoints::delivery::DeliveryError;
+use crate::notifications::ValidatedNotification;
+use crate::server::replay::ReplayRejection;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Upper bounds in seconds of the delivery latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 12] = [0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Counters and gauges collected by the server.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    received: Mutex<BTreeMap<String, u64>>,
+    validation_failures: Mutex<BTreeMap<(String, &'static str), u64>>,
+    deliveries: Mutex<BTreeMap<(String, &'static str), u64>>,
+    latency: Mutex<BTreeMap<String, Histogram>>,
+    queues: Mutex<Vec<Queue>>,
+}
+
+/// Queue of an endpoint sampled when the metrics are rendered.
+#[derive(Debug)]
+struct Queue {
+    label: String,
+    channel: broadcast::WeakSender<ValidatedNotification>,
+    waiting: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Reason a received notification was not passed on to any endpoint.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum ValidationFailure {
+    /// The notification could not be parsed.
+    Invalid,
+    /// The notification did not match any notification name.
+    Unmatched,
+    /// Rejected by replay protection as too old.
+    Stale,
+    /// Rejected by replay protection as already received.
+    Duplicate,
+}
+
+impl ValidationFailure {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ValidationFailure::Invalid => "invalid",
+            ValidationFailure::Unmatched => "unmatched",
+            ValidationFailure::Stale => "stale",
+            ValidationFailure::Duplicate => "duplicate",
+        }
+    }
+}
+
+impl From<ReplayRejection> for ValidationFailure {
+    fn from(value: ReplayRejection) -> Self {
+        match value {
+            ReplayRejection::Stale => ValidationFailure::Stale,
+            ReplayRejection::Duplicate => ValidationFailure::Duplicate,
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+impl Metrics {
+    /// Count a notification received on the `interface`.
+    pub fn record_received(&self, interface: &str) {
+        let mut received = self.received.lock().unwrap();
+        *received.entry(interface.into()).or_default() += 1;
+    }
+
+    /// Count a notification received on the `interface` that was not passed on.
+    pub fn record_validation_failure(&self, interface: &str, failure: ValidationFailure) {
+        let mut failures = self.validation_failures.lock().unwrap();
+        *failures.entry((interface.into(), failure.as_str())).or_default() += 1;
+    }
+
+    /// Count the delivery result reported by the endpoint with `label` and observe how long it took.
+    pub fn record_delivery(&self, label: &str, result: &Result<(), DeliveryError>, latency: Duration) {
+        let outcome = match result {
+            Ok(_) => "delivered",
+            Err(e) if e.is_permanent() => "permanent",
+            Err(_) => "transient",
+        };
+        *self.deliveries.lock().unwrap().entry((label.into(), outcome)).or_default() += 1;
+        self.latency.lock().unwrap().entry(label.into()).or_default().observe(latency.as_secs_f64());
+    }
+
+    /// Replace the endpoint queues sampled for the queue depth with the `channels` of the endpoints with `labels`.
+    pub fn register_queues(&self, labels: &[String], channels: Vec<broadcast::Sender<ValidatedNotification>>) {
+        let queues = labels
+            .iter()
+            .zip(channels)
+            .map(|(label, channel)| Queue { label: label.clone(), channel: channel.downgrade(), waiting: 0 })
+            .collect();
+        *self.queues.lock().unwrap() = queues;
+    }
+
+    /// Set the number of notifications waiting for room in the queue of the endpoint with `label`.
+    pub fn set_queue_waiting(&self, label: &str, waiting: usize) {
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(queue) = queues.iter_mut().find(|queue| queue.label == label) {
+            queue.waiting = waiting;
+        }
+    }
+
+    /// Render the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(&mut out, "pass_it_on_notifications_received_total", "counter", "Notifications received.");
+        for (interface, count) in self.received.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "pass_it_on_notifications_received_total{{interface=\"{}\"}} {}",
+                escape(interface),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "pass_it_on_validation_failures_total",
+            "counter",
+            "Notifications that were not passed on to any endpoint.",
+        );
+        for ((interface, reason), count) in self.validation_failures.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "pass_it_on_validation_failures_total{{interface=\"{}\",reason=\"{}\"}} {}",
+                escape(interface),
+                reason,
+                count
+            );
+        }
+
+        header(&mut out, "pass_it_on_deliveries_total", "counter", "Delivery results reported by endpoints.");
+        for ((endpoint, outcome), count) in self.deliveries.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "pass_it_on_deliveries_total{{endpoint=\"{}\",outcome=\"{}\"}} {}",
+                escape(endpoint),
+                outcome,
+                count
+            );
+        }
+
+        let queues = self.queues.lock().unwrap();
+        header(&mut out, "pass_it_on_queue_depth", "gauge", "Notifications in the endpoint queue.");
+        for queue in queues.iter() {
+            let depth = queue.channel.upgrade().map(|channel| channel.len()).unwrap_or_default();
+            let _ = writeln!(out, "pass_it_on_queue_depth{{endpoint=\"{}\"}} {}", escape(&queue.label), depth);
+        }
+
+        header(&mut out, "pass_it_on_queue_waiting", "gauge", "Notifications waiting for room in the endpoint queue.");
+        for queue in queues.iter() {
+            let _ =
+                writeln!(out, "pass_it_on_queue_waiting{{endpoint=\"{}\"}} {}", escape(&queue.label), queue.waiting);
+        }
+        drop(queues);
+
+        header(
+            &mut out,
+            "pass_it_on_delivery_latency_seconds",
+            "histogram",
+            "Time from passing a notification to an endpoint to its delivery result.",
+        );
+        for (endpoint, histogram) in self.latency.lock().unwrap().iter() {
+            let endpoint = escape(endpoint);
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "pass_it_on_delivery_latency_seconds_bucket{{endpoint=\"{}\",le=\"{}\"}} {}",
+                    endpoint, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "pass_it_on_delivery_latency_seconds_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}",
+                endpoint, histogram.count
+            );
+            let _ =
+                writeln!(out, "pass_it_on_delivery_latency_seconds_sum{{endpoint=\"{}\"}} {}", endpoint, histogram.sum);
+            let _ = writeln!(
+                out,
+                "pass_it_on_delivery_latency_seconds_count{{endpoint=\"{}\"}} {}",
+                endpoint, histogram.count
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value for the Prometheus text format.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(rendered: &str, metric: &str) -> Vec<String> {
+        rendered.lines().filter(|line| line.starts_with(metric)).map(String::from).collect()
+    }
+
+    #[test]
+    fn counters_are_rendered_per_label() {
+        let metrics = Metrics::default();
+        metrics.record_received("http");
+        metrics.record_received("http");
+        metrics.record_received("pipe");
+        metrics.record_validation_failure("http", ValidationFailure::Unmatched);
+        metrics.record_validation_failure("http", ReplayRejection::Duplicate.into());
+        metrics.record_delivery("ops", &Ok(()), Duration::ZERO);
+        metrics.record_delivery("ops", &Err(DeliveryError::permanent("gone")), Duration::ZERO);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE pass_it_on_notifications_received_total counter\n"));
+        assert_eq!(
+            lines(&rendered, "pass_it_on_notifications_received_total{"),
+            vec![
+                r#"pass_it_on_notifications_received_total{interface="http"} 2"#,
+                r#"pass_it_on_notifications_received_total{interface="pipe"} 1"#
+            ]
+        );
+        assert_eq!(
+            lines(&rendered, "pass_it_on_validation_failures_total{"),
+            vec![
+                r#"pass_it_on_validation_failures_total{interface="http",reason="duplicate"} 1"#,
+                r#"pass_it_on_validation_failures_total{interface="http",reason="unmatched"} 1"#
+            ]
+        );
+        assert_eq!(
+            lines(&rendered, "pass_it_on_deliveries_total{"),
+            vec![
+                r#"pass_it_on_deliveries_total{endpoint="ops",outcome="delivered"} 1"#,
+                r#"pass_it_on_deliveries_total{endpoint="ops",outcome="permanent"} 1"#
+            ]
+        );
+    }
+
+    #[test]
+    fn latency_is_counted_in_every_bucket_it_fits() {
+        let metrics = Metrics::default();
+        metrics.record_delivery("ops", &Ok(()), Duration::from_millis(300));
+        metrics.record_delivery("ops", &Err(DeliveryError::transient("busy")), Duration::from_secs(600));
+
+        let rendered = metrics.render();
+        let buckets = lines(&rendered, "pass_it_on_delivery_latency_seconds_bucket");
+        assert_eq!(buckets.len(), LATENCY_BUCKETS.len() + 1);
+        assert_eq!(buckets[3], r#"pass_it_on_delivery_latency_seconds_bucket{endpoint="ops",le="0.25"} 0"#);
+        assert_eq!(buckets[4], r#"pass_it_on_delivery_latency_seconds_bucket{endpoint="ops",le="0.5"} 1"#);
+        assert_eq!(buckets[11], r#"pass_it_on_delivery_latency_seconds_bucket{endpoint="ops",le="300"} 1"#);
+        assert_eq!(buckets[12], r#"pass_it_on_delivery_latency_seconds_bucket{endpoint="ops",le="+Inf"} 2"#);
+        assert_eq!(
+            lines(&rendered, "pass_it_on_delivery_latency_seconds_sum"),
+            vec![r#"pass_it_on_delivery_latency_seconds_sum{endpoint="ops"} 600.3"#]
+        );
+        assert_eq!(
+            lines(&rendered, "pass_it_on_delivery_latency_seconds_count"),
+            vec![r#"pass_it_on_delivery_latency_seconds_count{endpoint="ops"} 2"#]
+        );
+    }
+
+    #[test]
+    fn queues_are_sampled_while_their_endpoint_runs() {
+        let metrics = Metrics::default();
+        let (channel, _receiver) = broadcast::channel(4);
+        let (stopped, _) = broadcast::channel(4);
+        let labels = vec!["ops".to_string(), "stopped".to_string()];
+        metrics.register_queues(&labels, vec![channel.clone(), stopped]);
+        channel.send(ValidatedNotification::new("test", crate::notifications::Message::new("one"))).unwrap();
+        metrics.set_queue_waiting("ops", 3);
+
+        let rendered = metrics.render();
+        assert_eq!(
+            lines(&rendered, "pass_it_on_queue_depth{"),
+            vec![r#"pass_it_on_queue_depth{endpoint="ops"} 1"#, r#"pass_it_on_queue_depth{endpoint="stopped"} 0"#]
+        );
+        assert_eq!(
+            lines(&rendered, "pass_it_on_queue_waiting{"),
+            vec![r#"pass_it_on_queue_waiting{endpoint="ops"} 3"#, r#"pass_it_on_queue_waiting{endpoint="stopped"} 0"#]
+        );
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+
+        let metrics = Metrics::default();
+        metrics.record_received("pipe \"in\"");
+        assert!(metrics.render().contains(r#"pass_it_on_notifications_received_total{interface="pipe \"in\""} 1"#));
+    }
+}
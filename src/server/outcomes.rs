@@ -0,0 +1,192 @@
+use crate::endpoints::delivery::DeliveryOutcome;
+use crate::endpoints::delivery::DeliveryError;
+use crate::notifications::ValidatedNotification;
+use crate::server::dead_letter::{DeadLetterRecord, DeadLetters};
+use crate::server::dispatch::Dispatch;
+use crate::server::routing::Routes;
+use crate::server::spool::JournalWriter;
+use crate::ServerStatus;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, info, warn};
+
+/// Handles the delivery results endpoints report for notifications with a receipt.
+pub(crate) struct OutcomeHandler {
+    journal: Option<JournalWriter>,
+    dead_letters: Option<DeadLetters>,
+    routes: watch::Receiver<Arc<Routes>>,
+    status: ServerStatus,
+}
+
+impl OutcomeHandler {
+    /// Create a new `OutcomeHandler` that escalates notifications along the chains in the current `routes`.
+    pub fn new(
+        journal: Option<JournalWriter>,
+        dead_letters: Option<DeadLetters>,
+        routes: watch::Receiver<Arc<Routes>>,
+        status: ServerStatus,
+    ) -> Self {
+        Self { journal, dead_letters, routes, status }
+    }
+
+    /// Handle delivery results until every endpoint has stopped.
+    ///
+    /// A notification that failed is sent to the next endpoint in its escalation chain. Without one a notification
+    /// that failed with an error that may succeed later stays in the spool when there is one so it is sent again on
+    /// the next start, otherwise it is recorded in the dead letters.
+    pub async fn run(mut self, mut outcomes: mpsc::UnboundedReceiver<DeliveryOutcome>) {
+        while let Some(outcome) = outcomes.recv().await {
+            let label = outcome.endpoint();
+            self.status.stats().record_delivery(label, outcome.result().is_ok());
+            #[cfg(feature = "metrics")]
+            self.status.metrics().record_delivery(label, outcome.result(), outcome.latency());
+            let routes = self.routes.borrow().clone();
+            match outcome.result() {
+                Ok(_) => {
+                    debug!("Notification {} delivered to endpoint {} in {:?}", outcome.id(), label, outcome.latency())
+                }
+                Err(e) if routes.escalation(label).is_some() => {
+                    self.escalate(&routes, label, outcome.notification(), e.to_string().as_str()).await;
+                }
+                Err(e) if !e.is_permanent() && self.journal.is_some() => {
+                    debug!("Keeping notification {} for endpoint {} in the spool: {}", outcome.id(), label, e);
+                    continue;
+                }
+                Err(e) => self.record_dead_letter(label, outcome.notification(), e),
+            }
+
+            self.acknowledge(outcome.id());
+        }
+    }
+
+    /// Send the notification that the endpoint with `label` failed to deliver to the next endpoint in its
+    /// escalation chain.
+    async fn escalate(&self, routes: &Routes, label: &str, notification: &ValidatedNotification, reason: &str) {
+        let escalate = match routes.escalation(label) {
+            None => return,
+            Some(escalate) => escalate,
+        };
+
+        info!("Escalating notification from endpoint {} to {}: {}", label, escalate.label(), reason);
+        let source = format!("escalation from {}", label);
+        for sub_name in escalate.sub_names() {
+            let dispatch = Dispatch::new(&source, ValidatedNotification::new(sub_name, notification.message().clone()));
+            if let Err(e) = escalate.dispatcher().send(dispatch).await {
+                warn!("Error sending escalated message to endpoint dispatcher: {}", e)
+            }
+        }
+    }
+
+    /// Record the notification that the endpoint with `label` failed to deliver in the dead letters.
+    fn record_dead_letter(&mut self, label: &str, notification: &ValidatedNotification, error: &DeliveryError) {
+        if let Some(dead_letters) = self.dead_letters.as_mut() {
+            let reason = error.to_string();
+            let record = DeadLetterRecord::new(label, notification, reason.as_str(), error.is_permanent());
+            match dead_letters.record(&record) {
+                Ok(_) => info!("Recorded undelivered notification for endpoint {} in the dead letters", label),
+                Err(e) => warn!("Unable to record dead letter for endpoint {}: {}", label, e),
+            }
+        }
+    }
+
+    /// Remove the notification with `id` from the spool.
+    fn acknowledge(&self, id: u64) {
+        if let Some(journal) = self.journal.as_ref() {
+            journal.ack(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoints::delivery::Receipt;
+    use crate::notifications::Message;
+    use crate::server::dead_letter::DeadLetter;
+    use crate::server::routing::tests::endpoint;
+    use crate::server::routing::EndpointRoute;
+
+    struct Test {
+        primary: mpsc::Receiver<Dispatch>,
+        backup: mpsc::Receiver<Dispatch>,
+        dead_letter: DeadLetter,
+        outcomes: mpsc::UnboundedSender<DeliveryOutcome>,
+        handler: tokio::task::JoinHandle<()>,
+    }
+
+    impl Test {
+        /// Run an `OutcomeHandler` for a primary endpoint that escalates to a backup endpoint.
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("pass-it-on-outcomes-{}.jsonl", name));
+            let _ = std::fs::remove_file(&path);
+            let dead_letter = DeadLetter::new(&path);
+            let dead_letters = DeadLetters::open(&dead_letter).unwrap();
+
+            let channels = [endpoint(&[("room1", &["alerts"])]), endpoint(&[("file", &["backups"])])];
+            let (primary_tx, primary) = mpsc::channel(4);
+            let (backup_tx, backup) = mpsc::channel(4);
+            let endpoints = vec![
+                EndpointRoute::new("primary", &channels[0], primary_tx),
+                EndpointRoute::new("backup", &channels[1], backup_tx),
+            ];
+            let routes = Arc::new(Routes::new(&channels, endpoints, vec![Some(1), None]));
+            let (_, routes) = watch::channel(routes);
+
+            let handler = OutcomeHandler::new(None, Some(dead_letters), routes, ServerStatus::default());
+            let (outcomes, outcome_rx) = mpsc::unbounded_channel();
+            let handler = tokio::spawn(async move { handler.run(outcome_rx).await });
+            Self { primary, backup, dead_letter, outcomes, handler }
+        }
+
+        fn report(&self, label: &str, text: &str, result: Result<(), DeliveryError>) {
+            let receipt = Receipt::new(label, 1, self.outcomes.clone());
+            receipt.report(ValidatedNotification::new("sub", Message::new(text)), result);
+        }
+
+        /// Wait for the handler to finish and return the endpoints and texts of the dead letters.
+        async fn finish(self) -> (mpsc::Receiver<Dispatch>, mpsc::Receiver<Dispatch>, Vec<(String, String)>) {
+            drop(self.outcomes);
+            self.handler.await.unwrap();
+            let dead_letters = DeadLetters::open(&self.dead_letter).unwrap().read(|_| true).unwrap();
+            let dead_letters = dead_letters
+                .into_iter()
+                .map(|record| (record.endpoint().to_string(), record.notification().message().text().to_string()))
+                .collect();
+            (self.primary, self.backup, dead_letters)
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_notification_is_escalated_to_the_next_endpoint() {
+        let test = Test::new("escalated");
+        test.report("primary", "disk full", Err(DeliveryError::transient("timeout")));
+
+        let (mut primary, mut backup, dead_letters) = test.finish().await;
+        let escalated = backup.try_recv().unwrap();
+        assert_eq!(escalated.key_id(), "escalation from primary");
+        assert_eq!(escalated.notification().sub_name(), "file");
+        assert_eq!(escalated.notification().message().text(), "disk full");
+        assert!(primary.try_recv().is_err());
+        assert!(dead_letters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failure_at_the_end_of_the_chain_is_a_dead_letter() {
+        let test = Test::new("end-of-chain");
+        test.report("backup", "disk full", Err(DeliveryError::permanent("gone")));
+
+        let (_, mut backup, dead_letters) = test.finish().await;
+        assert!(backup.try_recv().is_err());
+        assert_eq!(dead_letters, vec![("backup".to_string(), "disk full".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn delivered_notification_is_not_escalated() {
+        let test = Test::new("delivered");
+        test.report("primary", "disk full", Ok(()));
+
+        let (_, mut backup, dead_letters) = test.finish().await;
+        assert!(backup.try_recv().is_err());
+        assert!(dead_letters.is_empty());
+    }
+}
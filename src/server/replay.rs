@@ -0,0 +1,169 @@
+use crate::notifications::Notification;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CACHE_SIZE: usize = 1000;
+
+/// Settings used by the server to reject replayed notifications.
+///
+/// A notification is rejected when its message time is further than `max_age` from the server time, or when a
+/// notification with the same key was already seen among the last `cache_size` accepted notifications.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReplayProtection {
+    max_age: Option<Duration>,
+    cache_size: usize,
+}
+
+/// Data structure to represent [`ReplayProtection`] in the server configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReplayProtectionConfigFile {
+    max_age_secs: Option<u64>,
+    cache_size: Option<usize>,
+}
+
+/// Reason a notification was rejected by the [`ReplayGuard`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub(crate) enum ReplayRejection {
+    Stale,
+    Duplicate,
+}
+
+/// Tracks recently accepted notification keys and enforces the [`ReplayProtection`] settings.
+#[derive(Debug)]
+pub(crate) struct ReplayGuard {
+    protection: ReplayProtection,
+    seen: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl ReplayProtection {
+    /// Create a new `ReplayProtection`.
+    ///
+    /// A `max_age` of `None` disables the freshness check and a `cache_size` of 0 disables duplicate detection.
+    pub fn new(max_age: Option<Duration>, cache_size: usize) -> Self {
+        Self { max_age, cache_size }
+    }
+
+    /// Return the maximum allowed difference between the message time and the server time.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Return the number of recently accepted notification keys that are remembered.
+    pub fn cache_size(&self) -> usize {
+        self.cache_size
+    }
+}
+
+impl Default for ReplayProtection {
+    fn default() -> Self {
+        Self { max_age: None, cache_size: DEFAULT_CACHE_SIZE }
+    }
+}
+
+impl From<&ReplayProtectionConfigFile> for ReplayProtection {
+    fn from(value: &ReplayProtectionConfigFile) -> Self {
+        Self {
+            max_age: value.max_age_secs.map(Duration::from_secs),
+            cache_size: value.cache_size.unwrap_or(DEFAULT_CACHE_SIZE),
+        }
+    }
+}
+
+impl Display for ReplayRejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayRejection::Stale => write!(f, "stale"),
+            ReplayRejection::Duplicate => write!(f, "duplicate"),
+        }
+    }
+}
+
+impl ReplayGuard {
+    pub fn new(protection: ReplayProtection) -> Self {
+        let capacity = protection.cache_size();
+        Self { protection, seen: HashSet::with_capacity(capacity), order: VecDeque::with_capacity(capacity) }
+    }
+
+    /// Check a validated [`Notification`] and remember its key when it is accepted.
+    pub fn check(&mut self, notification: &Notification) -> Result<(), ReplayRejection> {
+        if let Some(max_age) = self.protection.max_age() {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+            if now.abs_diff(notification.message().time()) > max_age.as_nanos() {
+                return Err(ReplayRejection::Stale);
+            }
+        }
+
+        if self.protection.cache_size() == 0 {
+            return Ok(());
+        }
+
+        let key = notification.key();
+        if self.seen.contains(key) {
+            return Err(ReplayRejection::Duplicate);
+        }
+
+        if self.order.len() >= self.protection.cache_size()
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.seen.remove(&oldest);
+        }
+        self.seen.insert(key.to_string());
+        self.order.push_back(key.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::{Key, Message};
+
+    fn notification(text: &str) -> Notification {
+        Notification::new(Message::new(text), &Key::derive_shared_key("replay"))
+    }
+
+    #[test]
+    fn repeated_keys_are_rejected() {
+        let mut guard = ReplayGuard::new(ReplayProtection::default());
+        let first = notification("first");
+
+        assert_eq!(guard.check(&first), Ok(()));
+        assert_eq!(guard.check(&notification("second")), Ok(()));
+        assert_eq!(guard.check(&first), Err(ReplayRejection::Duplicate));
+    }
+
+    #[test]
+    fn oldest_key_is_forgotten_when_the_cache_is_full() {
+        let mut guard = ReplayGuard::new(ReplayProtection::new(None, 2));
+        let notifications: Vec<_> = ["one", "two", "three"].into_iter().map(notification).collect();
+        for notification in notifications.iter() {
+            assert_eq!(guard.check(notification), Ok(()));
+        }
+
+        assert_eq!(guard.check(&notifications[2]), Err(ReplayRejection::Duplicate));
+        assert_eq!(guard.check(&notifications[0]), Ok(()));
+    }
+
+    #[test]
+    fn empty_cache_accepts_every_notification() {
+        let mut guard = ReplayGuard::new(ReplayProtection::new(None, 0));
+        let first = notification("first");
+
+        assert_eq!(guard.check(&first), Ok(()));
+        assert_eq!(guard.check(&first), Ok(()));
+    }
+
+    #[test]
+    fn notifications_older_than_max_age_are_stale() {
+        let mut guard = ReplayGuard::new(ReplayProtection::new(Some(Duration::from_secs(60)), 10));
+        let old = Notification::from_json(r#"{"message":{"text":"old","time":1},"key":"abc"}"#).unwrap();
+
+        assert_eq!(guard.check(&old), Err(ReplayRejection::Stale));
+        assert_eq!(guard.check(&notification("fresh")), Ok(()));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::endpoints::filter::Filter;
+use crate::endpoints::EndpointChannel;
+use crate::notifications::{Key, Message, Notification};
+use crate::server::dispatch::Dispatch;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// Endpoint and sub-name a notification name [`Key`] is routed to, for the messages its [`Filter`] accepts.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub(crate) struct Route {
+    endpoint: usize,
+    sub_name: String,
+    filter: Option<Filter>,
+}
+
+/// Every destination for a single notification name [`Key`].
+#[derive(Debug, Clone)]
+pub(crate) struct KeyRoutes {
+    id: String,
+    key: Key,
+    routes: Vec<Route>,
+}
+
+/// Index of notification name [`Key`]s by their identifier, built from the configured endpoints.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RoutingTable {
+    keys: HashMap<String, KeyRoutes>,
+}
+
+/// Where notifications go in the running configuration, replaced as a whole when the configuration is reloaded.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Routes {
+    table: RoutingTable,
+    endpoints: Vec<EndpointRoute>,
+    escalations: HashMap<String, usize>,
+}
+
+/// Dispatcher of a running endpoint and the sub-names of its notification names.
+#[derive(Debug, Clone)]
+pub(crate) struct EndpointRoute {
+    label: String,
+    sub_names: Vec<String>,
+    dispatcher: mpsc::Sender<Dispatch>,
+}
+
+impl Route {
+    /// Index of the endpoint in the list used to build the [`RoutingTable`].
+    pub fn endpoint(&self) -> usize {
+        self.endpoint
+    }
+
+    pub fn sub_name(&self) -> &str {
+        &self.sub_name
+    }
+
+    /// Return if the endpoint takes the `message`, which it does when it has no [`Filter`].
+    pub fn accepts(&self, message: &Message) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter.accepts(message))
+    }
+}
+
+impl KeyRoutes {
+    /// Return the identifier of the notification name [`Key`].
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// Return the routes to the endpoints that take the `message`.
+    pub fn routes_for<'a>(&'a self, message: &Message) -> impl Iterator<Item = &'a Route> {
+        self.routes.iter().filter(|route| route.accepts(message))
+    }
+}
+
+impl EndpointRoute {
+    pub fn new<S: AsRef<str>>(label: S, endpoint: &EndpointChannel, dispatcher: mpsc::Sender<Dispatch>) -> Self {
+        let sub_names = endpoint.keys().keys().cloned().collect();
+        Self { label: label.as_ref().into(), sub_names, dispatcher }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn sub_names(&self) -> &[String] {
+        &self.sub_names
+    }
+
+    pub fn dispatcher(&self) -> &mpsc::Sender<Dispatch> {
+        &self.dispatcher
+    }
+}
+
+impl Routes {
+    /// Create `Routes` to the `endpoints` through the dispatchers in `routes`, in the same order.
+    ///
+    /// `escalations` holds the index of the next endpoint in the escalation chain of each endpoint.
+    pub fn new(endpoints: &[EndpointChannel], routes: Vec<EndpointRoute>, escalations: Vec<Option<usize>>) -> Self {
+        let escalations = routes
+            .iter()
+            .zip(escalations)
+            .filter_map(|(route, target)| target.map(|target| (route.label.clone(), target)))
+            .collect();
+        Self { table: RoutingTable::new(endpoints), endpoints: routes, escalations }
+    }
+
+    pub fn table(&self) -> &RoutingTable {
+        &self.table
+    }
+
+    /// Return the endpoint at the index used by the [`RoutingTable`].
+    pub fn endpoint(&self, index: usize) -> &EndpointRoute {
+        &self.endpoints[index]
+    }
+
+    /// Return the running endpoint with `label`.
+    pub fn find_endpoint(&self, label: &str) -> Option<&EndpointRoute> {
+        self.endpoints.iter().find(|endpoint| endpoint.label == label)
+    }
+
+    /// Return the next endpoint in the escalation chain of the endpoint with `label`.
+    pub fn escalation(&self, label: &str) -> Option<&EndpointRoute> {
+        self.escalations.get(label).map(|index| &self.endpoints[*index])
+    }
+}
+
+impl RoutingTable {
+    pub fn new(endpoints: &[EndpointChannel]) -> Self {
+        let mut keys: HashMap<String, KeyRoutes> = HashMap::new();
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            let filter = endpoint.endpoint().options().filter().cloned();
+            for (sub_name, sub_keys) in endpoint.keys() {
+                for key in sub_keys {
+                    let route = Route { endpoint: index, sub_name: sub_name.clone(), filter: filter.clone() };
+                    let id = key.identifier();
+                    keys.entry(id.clone())
+                        .or_insert_with(|| KeyRoutes { id, key: key.clone(), routes: Vec::new() })
+                        .routes
+                        .push(route);
+                }
+            }
+        }
+        Self { keys }
+    }
+
+    /// Return if the notification name [`Key`] with the identifier `key_id` is routed to any endpoint.
+    #[cfg(feature = "http-server")]
+    pub fn contains(&self, key_id: &str) -> bool {
+        self.keys.contains_key(key_id)
+    }
+
+    /// Find the routes for the notification name [`Key`] that validates the [`Notification`].
+    ///
+    /// Notifications with a key identifier are only validated against the matching key, notifications without one
+    /// are validated against each known key.
+    pub fn find(&self, notification: &Notification) -> Option<&KeyRoutes> {
+        match notification.key_id() {
+            Some(key_id) => self.keys.get(key_id).filter(|key_routes| notification.validate(key_routes.key())),
+            None => self.keys.values().find(|key_routes| notification.validate(key_routes.key())),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::endpoints::{Endpoint, EndpointOptions};
+    use crate::notifications::ValidatedNotification;
+    use crate::Error;
+    use async_trait::async_trait;
+    use std::any::Any;
+    use std::collections::{BTreeMap, HashSet};
+    use tokio::sync::{broadcast, watch};
+
+    #[derive(Debug, Clone)]
+    struct TestEndpoint {
+        options: EndpointOptions,
+    }
+
+    #[async_trait]
+    impl Endpoint for TestEndpoint {
+        async fn notify(
+            &self,
+            _endpoint_rx: broadcast::Receiver<ValidatedNotification>,
+            _shutdown: watch::Receiver<bool>,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn generate_keys(&self, _hash_key: &Key) -> HashMap<String, HashSet<Key>> {
+            HashMap::new()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn options(&self) -> EndpointOptions {
+            self.options.clone()
+        }
+    }
+
+    pub(crate) fn server_key() -> Key {
+        Key::derive_shared_key("routing")
+    }
+
+    /// Create an endpoint with a sub-group for each of the `sub_names` that receives the notification `names`.
+    pub(crate) fn endpoint(sub_names: &[(&str, &[&str])]) -> EndpointChannel {
+        endpoint_with_options(sub_names, EndpointOptions::default())
+    }
+
+    /// Create an endpoint with the `name` that does not receive any notification names.
+    pub(crate) fn named_endpoint(name: &str) -> Box<dyn Endpoint + Send> {
+        Box::new(TestEndpoint { options: EndpointOptions::default().with_name(Some(name)) })
+    }
+
+    pub(crate) fn endpoint_with_options(sub_names: &[(&str, &[&str])], options: EndpointOptions) -> EndpointChannel {
+        let keys = sub_names
+            .iter()
+            .map(|(sub_name, names)| {
+                let keys = names.iter().map(|name| Key::generate(name, &server_key())).collect();
+                (sub_name.to_string(), keys)
+            })
+            .collect();
+        EndpointChannel::from(Box::new(TestEndpoint { options }), broadcast::channel(1).0, keys)
+    }
+
+    fn notification(name: &str, text: &str) -> Notification {
+        Notification::new(Message::new(text), &Key::generate(name, &server_key()))
+    }
+
+    fn destinations<'a>(key_routes: &'a KeyRoutes, notification: &Notification) -> Vec<(usize, &'a str)> {
+        let message = notification.message();
+        let mut destinations: Vec<_> = key_routes.routes_for(&message).map(|r| (r.endpoint(), r.sub_name())).collect();
+        destinations.sort();
+        destinations
+    }
+
+    #[test]
+    fn notification_is_routed_to_every_endpoint_with_its_name() {
+        let table = RoutingTable::new(&[
+            endpoint(&[("room1", &["alerts"]), ("room2", &["alerts", "backups"])]),
+            endpoint(&[("file", &["backups"])]),
+        ]);
+
+        let alerts = notification("alerts", "disk full");
+        let key_routes = table.find(&alerts).unwrap();
+        assert_eq!(key_routes.id(), Key::generate("alerts", &server_key()).identifier());
+        assert_eq!(destinations(key_routes, &alerts), vec![(0, "room1"), (0, "room2")]);
+
+        let backups = notification("backups", "done");
+        assert_eq!(destinations(table.find(&backups).unwrap(), &backups), vec![(0, "room2"), (1, "file")]);
+    }
+
+    #[test]
+    fn notification_without_key_id_is_matched_by_its_key() {
+        let table = RoutingTable::new(&[endpoint(&[("room1", &["alerts", "backups"])])]);
+        let mut json = serde_json::to_value(notification("backups", "done")).unwrap();
+        json.as_object_mut().unwrap().remove("key_id");
+        let notification = Notification::from_json(json.to_string()).unwrap();
+
+        assert_eq!(notification.key_id(), None);
+        assert_eq!(table.find(&notification).unwrap().id(), Key::generate("backups", &server_key()).identifier());
+    }
+
+    #[test]
+    fn notification_that_does_not_validate_is_not_routed() {
+        let table = RoutingTable::new(&[endpoint(&[("room1", &["alerts"])])]);
+
+        assert!(table.find(&notification("unknown", "disk full")).is_none());
+
+        // A key identifier does not route a notification that was signed with another key
+        let mut json = serde_json::to_value(notification("unknown", "disk full")).unwrap();
+        json["key_id"] = Key::generate("alerts", &server_key()).identifier().into();
+        assert!(table.find(&Notification::from_json(json.to_string()).unwrap()).is_none());
+    }
+
+    #[test]
+    fn notification_is_only_routed_to_endpoints_whose_filter_accepts_it() {
+        let metadata = BTreeMap::from([("environment".to_string(), "production".to_string())]);
+        let production = EndpointOptions::default().with_filter(Some(Filter::new(Vec::new(), metadata).unwrap()));
+        let table = RoutingTable::new(&[
+            endpoint_with_options(&[("pager", &["alerts"])], production),
+            endpoint(&[("file", &["alerts"])]),
+        ]);
+        let message = |environment: &str| Message::new("disk full").with_metadata("environment", environment);
+        let key = Key::generate("alerts", &server_key());
+
+        let staging = Notification::new(message("staging"), &key);
+        assert_eq!(destinations(table.find(&staging).unwrap(), &staging), vec![(1, "file")]);
+        let production = Notification::new(message("production"), &key);
+        assert_eq!(destinations(table.find(&production).unwrap(), &production), vec![(0, "pager"), (1, "file")]);
+    }
+}
@@ -0,0 +1,423 @@
+//! Durable spool of notifications that have not been delivered yet
+//!
+//! When a spool is configured the server writes each notification to a journal in the spool directory as it
+//! passes it to an endpoint and removes it once the endpoint reports it was delivered or failed permanently.
+//! Notifications left in the journal, for example after a crash or a restart during an outage, are sent to their
+//! endpoints again when the server starts.
+//!
+//! Only endpoints that report delivery use the spool. Notifications are matched to endpoints by their `name`,
+//! or by their position in the configuration when they do not have one, so name endpoints to keep spooled
+//! notifications with the right endpoint when the configuration changes. Notifications held for batching,
+//! schedules or rate limits have not been passed to an endpoint yet and are not spooled.
+//!
+//! # Configuration Example
+//! ```toml
+//! [server.spool]
+//! path = '/var/lib/pass-it-on/spool'
+//! ```
+
+use crate::endpoints::delivery::{DeliveryOutcome, Receipt};
+use crate::notifications::{Message, ValidatedNotification};
+use crate::server::dispatch::Dispatch;
+use crate::server::routing::Routes;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+const JOURNAL_FILE: &str = "spool.jsonl";
+
+/// Acknowledged entries the journal may hold for every pending entry before it is compacted.
+const COMPACT_RATIO: usize = 4;
+
+/// Settings for the durable spool.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Spool {
+    path: PathBuf,
+}
+
+/// Data structure to represent [`Spool`] in the configuration file.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SpoolConfigFile {
+    path: String,
+}
+
+/// Notification waiting in the spool to be delivered to an endpoint.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub(crate) struct SpoolEntry {
+    id: u64,
+    endpoint: String,
+    sub_name: String,
+    message: Message,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JournalRecord {
+    Add(SpoolEntry),
+    Ack(u64),
+}
+
+/// Append only journal of [`SpoolEntry`]s and their acknowledgements.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    file: File,
+    pending: BTreeMap<u64, SpoolEntry>,
+    acked: usize,
+    next_id: u64,
+}
+
+/// Handle to the writer task of a [`Journal`].
+#[derive(Debug, Clone)]
+pub(crate) struct JournalWriter {
+    records: std::sync::mpsc::Sender<JournalRecord>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Spool {
+    /// Create a new `Spool` that keeps its journal in the `path` directory.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().into() }
+    }
+
+    /// Return the spool directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TryFrom<&SpoolConfigFile> for Spool {
+    type Error = Error;
+
+    fn try_from(value: &SpoolConfigFile) -> Result<Self, Self::Error> {
+        if value.path.is_empty() {
+            return Err(Error::invalid_server_configuration("spool path is blank".to_string()));
+        }
+        Ok(Spool::new(value.path.as_str()))
+    }
+}
+
+impl SpoolEntry {
+    pub fn new<S: AsRef<str>>(id: u64, endpoint: S, notification: &ValidatedNotification) -> Self {
+        Self {
+            id,
+            endpoint: endpoint.as_ref().into(),
+            sub_name: notification.sub_name().into(),
+            message: notification.message().clone(),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Label of the endpoint the notification is for.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn notification(&self) -> ValidatedNotification {
+        ValidatedNotification::new(self.sub_name.as_str(), self.message.clone())
+    }
+}
+
+impl Journal {
+    /// Open the journal in the [`Spool`] directory and compact it to the entries that are still pending.
+    pub fn open(spool: &Spool) -> Result<Self, Error> {
+        std::fs::create_dir_all(spool.path())?;
+        let path = spool.path().join(JOURNAL_FILE);
+
+        let mut pending = BTreeMap::new();
+        let mut next_id = 1;
+        if path.exists() {
+            for (number, line) in BufReader::new(File::open(&path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<JournalRecord>(line.as_str()) {
+                    Ok(JournalRecord::Add(entry)) => {
+                        next_id = next_id.max(entry.id + 1);
+                        pending.insert(entry.id, entry);
+                    }
+                    Ok(JournalRecord::Ack(id)) => {
+                        pending.remove(&id);
+                    }
+                    Err(e) => warn!("Skipping unreadable spool journal line {}: {}", number + 1, e),
+                }
+            }
+        }
+
+        let file = Self::rewrite(path.as_path(), pending.values())?;
+        Ok(Self { path, file, pending, acked: 0, next_id })
+    }
+
+    /// Return the entries that have not been acknowledged.
+    pub fn pending(&self) -> Vec<SpoolEntry> {
+        self.pending.values().cloned().collect()
+    }
+
+    /// Append the `entry` to the journal, it is only durable after the next [`sync`][Self::sync].
+    pub fn add(&mut self, entry: SpoolEntry) -> Result<(), Error> {
+        self.append(&JournalRecord::Add(entry.clone()))?;
+        self.pending.insert(entry.id, entry);
+        Ok(())
+    }
+
+    /// Remove the entry with `id` from the pending entries.
+    ///
+    /// The journal is rewritten with only the pending entries once it holds more than [`COMPACT_RATIO`] times as
+    /// many acknowledged entries as pending ones.
+    pub fn ack(&mut self, id: u64) -> Result<(), Error> {
+        if self.pending.remove(&id).is_none() {
+            return Ok(());
+        }
+
+        self.acked += 1;
+        match self.acked > self.pending.len() * COMPACT_RATIO {
+            true => {
+                self.file = Self::rewrite(self.path.as_path(), self.pending.values())?;
+                self.acked = 0;
+            }
+            false => self.append(&JournalRecord::Ack(id))?,
+        }
+        Ok(())
+    }
+
+    /// Flush the records appended to the journal to disk.
+    pub fn sync(&self) -> Result<(), Error> {
+        Ok(self.file.sync_data()?)
+    }
+
+    /// Move the journal to a writer task and return the handle used to add and acknowledge entries.
+    ///
+    /// The writer appends the records in the order they are sent and syncs the journal once for every set of
+    /// records that arrive together, so dispatchers never wait on the disk. It stops once every handle is dropped.
+    pub fn start(mut self) -> JournalWriter {
+        let (records, records_rx) = std::sync::mpsc::channel();
+        let next_id = Arc::new(AtomicU64::new(self.next_id));
+        tokio::task::spawn_blocking(move || {
+            while let Ok(record) = records_rx.recv() {
+                for record in std::iter::once(record).chain(records_rx.try_iter()) {
+                    let written = match record {
+                        JournalRecord::Add(entry) => self.add(entry),
+                        JournalRecord::Ack(id) => self.ack(id),
+                    };
+                    if let Err(e) = written {
+                        warn!("Unable to write to the spool journal: {}", e);
+                    }
+                }
+                if let Err(e) = self.sync() {
+                    warn!("Unable to sync the spool journal: {}", e);
+                }
+            }
+        });
+        JournalWriter { records, next_id }
+    }
+
+    fn append(&mut self, record: &JournalRecord) -> Result<(), Error> {
+        let line = serde_json::to_string(record)?;
+        writeln!(self.file, "{}", line)?;
+        Ok(())
+    }
+
+    /// Replace the journal with only the `entries` and return the file opened for appending.
+    fn rewrite<'a, I: Iterator<Item = &'a SpoolEntry>>(path: &Path, entries: I) -> Result<File, Error> {
+        let temp = path.with_extension("jsonl.tmp");
+        {
+            let mut file = File::create(&temp)?;
+            for entry in entries {
+                writeln!(file, "{}", serde_json::to_string(&JournalRecord::Add(entry.clone()))?)?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&temp, path)?;
+        Ok(OpenOptions::new().append(true).open(path)?)
+    }
+}
+
+impl JournalWriter {
+    /// Add a notification for the endpoint with `label` to the journal and return its identifier.
+    pub fn add<S: AsRef<str>>(&self, label: S, notification: &ValidatedNotification) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.send(JournalRecord::Add(SpoolEntry::new(id, label, notification)));
+        id
+    }
+
+    /// Remove the entry with `id` from the journal.
+    pub fn ack(&self, id: u64) {
+        self.send(JournalRecord::Ack(id));
+    }
+
+    fn send(&self, record: JournalRecord) {
+        if self.records.send(record).is_err() {
+            warn!("Unable to write to the spool journal, the writer has stopped");
+        }
+    }
+}
+
+/// Send the spooled notifications left from the last run to their endpoint dispatchers again.
+///
+/// The notifications keep their identifier in the journal and are sent straight to the endpoint queue, as they went
+/// through the dedup, schedule, batch and rate limit stages before they were spooled. Sending waits for room in a
+/// dispatcher when it is busy.
+pub(crate) async fn replay_spool(
+    entries: Vec<SpoolEntry>,
+    routes: &Routes,
+    outcomes: &mpsc::UnboundedSender<DeliveryOutcome>,
+) {
+    if !entries.is_empty() {
+        info!("Replaying {} notification(s) from the spool", entries.len());
+    }
+
+    for entry in entries {
+        match routes.find_endpoint(entry.endpoint()) {
+            None => warn!("Spooled notification {} is for unknown endpoint {}", entry.id(), entry.endpoint()),
+            Some(endpoint) => {
+                let receipt = Receipt::new(entry.endpoint(), entry.id(), outcomes.clone());
+                let dispatch = Dispatch::new("spool", entry.notification().with_receipt(receipt));
+                if let Err(e) = endpoint.dispatcher().send(dispatch).await {
+                    warn!("Error sending spooled message to endpoint dispatcher: {}", e)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoints::EndpointOptions;
+    use crate::endpoints::batch::Batch;
+    use crate::server::dispatch::{Tracking, start_dispatcher};
+    use crate::server::outcomes::OutcomeHandler;
+    use crate::server::routing::EndpointRoute;
+    use crate::server::routing::tests::endpoint_with_options;
+    use crate::ServerStatus;
+    use std::time::Duration;
+    use tokio::sync::watch;
+
+    fn open(name: &str) -> (Spool, Journal) {
+        let spool = Spool::new(std::env::temp_dir().join(name));
+        let _ = std::fs::remove_dir_all(spool.path());
+        let journal = Journal::open(&spool).unwrap();
+        (spool, journal)
+    }
+
+    fn entry(id: u64, text: &str) -> SpoolEntry {
+        SpoolEntry::new(id, "file", &ValidatedNotification::new("test", Message::new(text)))
+    }
+
+    fn ids(journal: &Journal) -> Vec<u64> {
+        journal.pending().iter().map(SpoolEntry::id).collect()
+    }
+
+    fn lines(spool: &Spool) -> usize {
+        std::fs::read_to_string(spool.path().join(JOURNAL_FILE)).unwrap().lines().count()
+    }
+
+    #[test]
+    fn reopened_journal_keeps_pending_entries() {
+        let (spool, mut journal) = open("pass-it-on-spool-reopen-test");
+        journal.add(entry(1, "1")).unwrap();
+        journal.add(entry(2, "2")).unwrap();
+        journal.add(entry(3, "3")).unwrap();
+        journal.ack(2).unwrap();
+        journal.sync().unwrap();
+
+        let journal = Journal::open(&spool).unwrap();
+        assert_eq!(ids(&journal), vec![1, 3]);
+        assert_eq!(journal.next_id, 4);
+        assert_eq!(lines(&spool), 2);
+    }
+
+    #[test]
+    fn journal_compacts_when_acked_entries_outnumber_pending() {
+        let (spool, mut journal) = open("pass-it-on-spool-compact-test");
+        journal.add(entry(1, "pending")).unwrap();
+        for id in 2..=(2 + COMPACT_RATIO as u64) {
+            journal.add(entry(id, "acked")).unwrap();
+        }
+        for id in 2..(2 + COMPACT_RATIO as u64) {
+            journal.ack(id).unwrap();
+        }
+        // Every entry and the acknowledgements are still in the journal
+        assert_eq!(lines(&spool), 2 + 2 * COMPACT_RATIO);
+
+        journal.ack(2 + COMPACT_RATIO as u64).unwrap();
+        assert_eq!(lines(&spool), 1);
+        assert_eq!(ids(&journal), vec![1]);
+
+        journal.add(entry(10, "after")).unwrap();
+        journal.ack(1).unwrap();
+        journal.ack(10).unwrap();
+        assert_eq!(lines(&spool), 0);
+    }
+
+    #[tokio::test]
+    async fn writer_records_in_order() {
+        let (spool, journal) = open("pass-it-on-spool-writer-test");
+        let writer = journal.start();
+        let notification = ValidatedNotification::new("test", Message::new("1"));
+        let first = writer.add("file", &notification);
+        let second = writer.add("file", &notification);
+        writer.ack(first);
+        assert_eq!(second, first + 1);
+
+        drop(writer);
+        for _ in 0..100 {
+            if lines(&spool) == 3 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(ids(&Journal::open(&spool).unwrap()), vec![second]);
+    }
+
+    #[tokio::test]
+    async fn replayed_entry_skips_the_batch_and_leaves_the_journal() {
+        let (spool, mut journal) = open("pass-it-on-spool-replay-test");
+        journal.add(entry(1, "spooled")).unwrap();
+        let entries = journal.pending();
+        let writer = journal.start();
+
+        let batch = Batch::new(Some(Duration::from_secs(3600)), None).unwrap();
+        let options = EndpointOptions::default().with_name(Some("file")).with_batch(Some(batch));
+        let channel = endpoint_with_options(&[("test", &["alerts"])], options);
+        let mut endpoint_rx = channel.channel_receiver();
+        let (outcomes, outcomes_rx) = mpsc::unbounded_channel();
+        let (_routes, routes_rx) = watch::channel(Arc::new(Routes::default()));
+        let (_pause, pause_rx) = watch::channel(false);
+        let (_shutdown, shutdown_rx) = watch::channel(false);
+        let tracking = Tracking::new(Some(writer.clone()), outcomes.clone());
+        let status = ServerStatus::default();
+        let routes = routes_rx.clone();
+        let (dispatcher, _) =
+            start_dispatcher(&channel, "file", None, tracking, status.clone(), pause_rx, routes, shutdown_rx).unwrap();
+        let route = EndpointRoute::new("file", &channel, dispatcher);
+        let routes = Routes::new(std::slice::from_ref(&channel), vec![route], vec![None]);
+        tokio::spawn(OutcomeHandler::new(Some(writer), None, routes_rx, status).run(outcomes_rx));
+
+        replay_spool(entries, &routes, &outcomes).await;
+        let notification = tokio::time::timeout(Duration::from_secs(1), endpoint_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(notification.message().text(), "spooled");
+        notification.report_delivery(Ok(()));
+
+        for _ in 0..100 {
+            if lines(&spool) == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(Journal::open(&spool).unwrap().pending().is_empty());
+    }
+}
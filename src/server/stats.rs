@@ -0,0 +1,101 @@
+use crate::server::replay::ReplayRejection;
+use jiff::Timestamp;
+use serde::Serialize;
+#[cfg(feature = "http-server")]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Counters collected by the server while processing notifications.
+#[derive(Debug, Default)]
+pub(crate) struct ServerStats {
+    interfaces: Mutex<HashMap<String, InterfaceStats>>,
+    endpoints: Mutex<HashMap<String, EndpointStats>>,
+}
+
+/// Counters for a single interface.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize)]
+pub(crate) struct InterfaceStats {
+    pub received: u64,
+    pub stale: u64,
+    pub duplicate: u64,
+    pub muted: u64,
+    pub last_received: Option<String>,
+}
+
+/// Counters for the delivery results of a single endpoint.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize)]
+pub(crate) struct EndpointStats {
+    pub delivered: u64,
+    pub failed: u64,
+    pub busy: u64,
+    pub last_delivered: Option<String>,
+    pub last_failed: Option<String>,
+}
+
+/// Copy of the [`ServerStats`] counters.
+#[cfg(feature = "http-server")]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize)]
+pub(crate) struct StatsSnapshot {
+    interfaces: BTreeMap<String, InterfaceStats>,
+    endpoints: BTreeMap<String, EndpointStats>,
+}
+
+impl ServerStats {
+    /// Count a notification received on an interface.
+    pub fn record_received(&self, interface: &str) {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        let stats = interfaces.entry(interface.to_string()).or_default();
+        stats.received += 1;
+        stats.last_received = Some(Timestamp::now().to_string());
+    }
+
+    /// Count a replay rejection for an interface and return the total rejections for that interface.
+    pub fn record_replay_rejection(&self, interface: &str, rejection: ReplayRejection) -> u64 {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        let stats = interfaces.entry(interface.to_string()).or_default();
+        match rejection {
+            ReplayRejection::Stale => stats.stale += 1,
+            ReplayRejection::Duplicate => stats.duplicate += 1,
+        }
+        stats.stale + stats.duplicate
+    }
+
+    /// Count a notification received on an interface that was dropped because its notification name is muted.
+    pub fn record_muted(&self, interface: &str) {
+        self.interfaces.lock().unwrap().entry(interface.to_string()).or_default().muted += 1;
+    }
+
+    /// Count a delivery result reported by the endpoint with `label`.
+    pub fn record_delivery(&self, label: &str, delivered: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints.entry(label.to_string()).or_default();
+        match delivered {
+            true => {
+                stats.delivered += 1;
+                stats.last_delivered = Some(Timestamp::now().to_string());
+            }
+            false => {
+                stats.failed += 1;
+                stats.last_failed = Some(Timestamp::now().to_string());
+            }
+        }
+    }
+
+    /// Count a notification that was not passed to the endpoint with `label` because it was not keeping up and
+    /// return the total for that endpoint.
+    pub fn record_busy(&self, label: &str) -> u64 {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints.entry(label.to_string()).or_default();
+        stats.busy += 1;
+        stats.busy
+    }
+
+    /// Return a copy of the counters.
+    #[cfg(feature = "http-server")]
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let interfaces = self.interfaces.lock().unwrap().iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        let endpoints = self.endpoints.lock().unwrap().iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        StatsSnapshot { interfaces, endpoints }
+    }
+}
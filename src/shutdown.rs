@@ -71,3 +71,20 @@ pub(crate) async fn listen_for_shutdown(
     tokio::time::sleep(Duration::from_secs(seconds_to_wait)).await;
     info!("Shutdown Complete")
 }
+
+#[cfg(feature = "server")]
+/// Create a shutdown channel for a single part of the server that is also signalled on shutdown by `parent`.
+pub(crate) fn child_shutdown(parent: &watch::Receiver<bool>) -> (watch::Sender<bool>, watch::Receiver<bool>) {
+    let (child_tx, child_rx) = watch::channel(false);
+    let forward = child_tx.clone();
+    let mut parent = parent.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = parent.changed() => {
+                let _ = forward.send(true);
+            }
+            _ = forward.closed() => (),
+        }
+    });
+    (child_tx, child_rx)
+}
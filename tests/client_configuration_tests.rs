@@ -1,4 +1,7 @@
+use pass_it_on::notifications::Key;
+use pass_it_on::ClientConfigFile;
 use pass_it_on::ClientConfiguration;
+use pass_it_on::ConfigFormat;
 use pass_it_on::Error;
 
 #[test]
@@ -56,3 +59,66 @@ fn interface_not_defined() {
 
     assert_eq!(config.unwrap_err().to_string(), Error::missing_interface().to_string())
 }
+
+#[test]
+fn client_key_from_file() {
+    let key = std::env::temp_dir().join("pass-it-on-client-key-test");
+    std::fs::write(&key, "sdfsf4633ghf44dfhdfhQdhdfhewaasg\n").unwrap();
+    let config = ClientConfiguration::try_from(
+        r#"
+    [client]
+    key_file = 'KEY'
+
+    [[client.interface]]
+    type = "http"
+    port = 8080
+"#
+        .replace("KEY", key.to_str().unwrap())
+        .as_str(),
+    )
+    .unwrap();
+
+    assert_eq!(config.key(), &Key::derive_shared_key("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+}
+
+#[test]
+fn client_json_config() {
+    let config = ClientConfiguration::from_format(
+        r#"
+    {
+        "client": {
+            "key": "sdfsf4633ghf44dfhdfhQdhdfhewaasg",
+            "interface": [{ "type": "http", "port": 8080 }]
+        }
+    }
+"#,
+        ConfigFormat::Json,
+    )
+    .unwrap();
+
+    assert_eq!(config.key(), &Key::derive_shared_key("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+    assert_eq!(config.interfaces().len(), 1);
+}
+
+#[test]
+fn client_config_written_as_toml() {
+    let path = std::env::temp_dir().join("pass-it-on-client-write-test.json");
+    std::fs::write(
+        &path,
+        r#"
+    {
+        "client": {
+            "key": "sdfsf4633ghf44dfhdfhQdhdfhewaasg",
+            "interface": [{ "type": "http", "port": 8080 }]
+        }
+    }
+"#,
+    )
+    .unwrap();
+
+    let file = ClientConfigFile::from_path(path.as_path()).unwrap();
+    let config = ClientConfiguration::try_from(file.to_format(ConfigFormat::Toml).unwrap().as_str()).unwrap();
+    assert_eq!(config.key(), &Key::derive_shared_key("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+    assert_eq!(config.interfaces().len(), 1);
+    assert!(!file.to_redacted_format(ConfigFormat::Json).unwrap().contains("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+}
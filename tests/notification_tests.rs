@@ -0,0 +1,125 @@
+use pass_it_on::notifications::{Key, Message, Notification, Priority};
+
+const CLIENT_KEY: &str = "sdfsf4633ghf44dfhdfhQdhdfhewaasg";
+const NOTIFICATION_NAME: &str = "notification1";
+
+fn client_key() -> Key {
+    Key::derive_shared_key(CLIENT_KEY)
+}
+
+#[test]
+fn normal_priority_not_serialized() {
+    let notification =
+        Message::new("test message").to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+    let json = notification.to_json().unwrap();
+
+    assert!(!json.contains("priority"));
+}
+
+#[test]
+fn priority_survives_json() {
+    let notification = Message::new("test message")
+        .with_priority(Priority::Critical)
+        .to_client_ready_message(NOTIFICATION_NAME)
+        .to_notification(&client_key());
+    let parsed = Notification::from_json(notification.to_json().unwrap()).unwrap();
+
+    assert_eq!(parsed.message().priority(), Priority::Critical);
+    assert_eq!(parsed, notification);
+}
+
+#[test]
+fn missing_priority_defaults_to_normal() {
+    let parsed = Notification::from_json(r#"{"message":{"text":"test message","time":1},"key":"abc"}"#).unwrap();
+
+    assert_eq!(parsed.message().priority(), Priority::Normal);
+}
+
+#[test]
+fn priority_changes_key() {
+    let normal = Message::new("test message");
+    let critical = normal.clone().with_priority(Priority::Critical);
+
+    let normal_key = normal.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+    let critical_key = critical.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+
+    assert_ne!(normal_key.key(), critical_key.key());
+}
+
+#[test]
+fn title_survives_json() {
+    let notification = Message::new("test message")
+        .with_title("Test Title")
+        .to_client_ready_message(NOTIFICATION_NAME)
+        .to_notification(&client_key());
+    let parsed = Notification::from_json(notification.to_json().unwrap()).unwrap();
+
+    assert_eq!(parsed.message().title(), Some("Test Title"));
+}
+
+#[test]
+fn title_changes_key() {
+    let message = Message::new("test message");
+    let titled = message.clone().with_title("Test Title");
+
+    let key = message.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+    let titled_key = titled.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+
+    assert_ne!(key.key(), titled_key.key());
+}
+
+#[test]
+fn metadata_and_tags_survive_json() {
+    let notification = Message::new("test message")
+        .with_metadata("host", "web01")
+        .with_metadata("service", "backup")
+        .with_tag("nightly")
+        .to_client_ready_message(NOTIFICATION_NAME)
+        .to_notification(&client_key());
+    let parsed = Notification::from_json(notification.to_json().unwrap()).unwrap();
+
+    assert_eq!(parsed.message().metadata().get("host").map(String::as_str), Some("web01"));
+    assert_eq!(parsed.message().metadata().get("service").map(String::as_str), Some("backup"));
+    assert_eq!(parsed.message().tags(), ["nightly".to_string()]);
+}
+
+#[test]
+fn metadata_changes_key() {
+    let message = Message::new("test message").with_metadata("host", "web01");
+    let tampered = message.clone().with_metadata("host", "web02");
+
+    let key = message.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+    let tampered_key = tampered.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+
+    assert_ne!(key.key(), tampered_key.key());
+}
+
+#[test]
+fn key_id_identifies_notification_name() {
+    let first = Message::new("first message").to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+    let second =
+        Message::new("second message").to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+    let other = Message::new("first message").to_client_ready_message("notification2").to_notification(&client_key());
+
+    assert!(first.key_id().is_some());
+    assert_eq!(first.key_id(), second.key_id());
+    assert_ne!(first.key_id(), other.key_id());
+}
+
+#[test]
+fn missing_key_id_accepted() {
+    let parsed = Notification::from_json(r#"{"message":{"text":"test message","time":1},"key":"abc"}"#).unwrap();
+
+    assert_eq!(parsed.key_id(), None);
+}
+
+#[test]
+fn optional_fields_cannot_be_mistaken_for_each_other() {
+    let tagged: Message = serde_json::from_str(r#"{"text":"test","time":1,"title":"a","tags":["b"]}"#).unwrap();
+    let titled: Message = serde_json::from_str(r#"{"text":"test","time":1,"title":"a\u0000tags=[\"b\"]"}"#).unwrap();
+
+    let tagged_key = tagged.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+    let titled_key = titled.to_client_ready_message(NOTIFICATION_NAME).to_notification(&client_key());
+
+    assert_ne!(tagged_key.key(), titled_key.key());
+}
@@ -1,7 +1,17 @@
+use pass_it_on::endpoints::batch::Batch;
+use pass_it_on::endpoints::dedup::Dedup;
 use pass_it_on::endpoints::file::FileEndpoint;
+use pass_it_on::endpoints::queue::{Queue, QueueOverflow};
+use pass_it_on::endpoints::rate_limit::{Overflow, RateLimit};
+use pass_it_on::endpoints::retry::Retry;
+use pass_it_on::endpoints::schedule::Outside;
 use pass_it_on::endpoints::Endpoint;
+use pass_it_on::notifications::{Key, Priority};
 use pass_it_on::Error;
-use pass_it_on::ServerConfiguration;
+use pass_it_on::{
+    Admin, ConfigFormat, DeadLetter, Escalation, ReplayProtection, ServerConfigFile, ServerConfiguration, Spool,
+};
+use std::time::Duration;
 
 #[test]
 fn server_valid_config_file() {
@@ -182,6 +192,14 @@ fn endpoint_not_defined() {
     assert_eq!(config.unwrap_err().to_string(), Error::missing_interface().to_string())
 }
 
+#[test]
+fn file_endpoint_reports_delivery() {
+    let notifications = ["test1".to_string(), "test2".to_string()];
+    let endpoint: Box<dyn Endpoint + Send> = Box::new(FileEndpoint::new("path", notifications.as_ref()));
+
+    assert!(endpoint.reports_delivery());
+}
+
 #[test]
 fn file_configfile_path_is_blank() {
     let config = ServerConfiguration::try_from(
@@ -227,3 +245,1214 @@ fn file_configfile_path_notification_is_blank() {
         Error::invalid_endpoint_configuration("File configuration has no notifications setup".to_string()).to_string()
     )
 }
+
+#[test]
+fn replay_protection_default() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.replay_protection(), &ReplayProtection::default())
+}
+
+#[test]
+fn replay_protection_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.replay_protection]
+    max_age_secs = 300
+    cache_size = 50
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.replay_protection(), &ReplayProtection::new(Some(Duration::from_secs(300)), 50))
+}
+
+#[test]
+fn dedup_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.dedup]
+    window_secs = 60
+    summary = true
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [server.endpoint.dedup]
+    window_secs = 0
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.dedup(), Some(Dedup::new(Duration::from_secs(60), true)));
+    assert_eq!(config.endpoints()[0].options().dedup(), Some(Dedup::new(Duration::ZERO, false)));
+}
+
+#[test]
+fn rate_limit_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [server.endpoint.rate_limit]
+    max_messages = 5
+    period_secs = 60
+    overflow = "queue"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.endpoints()[0].options().rate_limit(),
+        Some(RateLimit::new(5, Duration::from_secs(60), Overflow::Queue).unwrap())
+    );
+}
+
+#[test]
+fn rate_limit_zero_messages() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [server.endpoint.rate_limit]
+    max_messages = 0
+    period_secs = 60
+"#,
+    );
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_endpoint_configuration("rate_limit max_messages and period_secs must be greater than 0".to_string())
+            .to_string()
+    )
+}
+
+#[test]
+fn batch_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [server.endpoint.batch]
+    interval_secs = 3600
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.endpoints()[0].options().batch(), Some(Batch::new(Some(Duration::from_secs(3600)), None).unwrap()));
+}
+
+#[test]
+fn batch_without_limits() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [server.endpoint.batch]
+"#,
+    );
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_endpoint_configuration("batch requires interval_secs or max_count greater than 0".to_string())
+            .to_string()
+    )
+}
+
+#[test]
+fn schedule_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    name = "pager"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1"]
+
+    [server.endpoint.schedule]
+    timezone = "America/Edmonton"
+    outside = "fallback"
+    fallback = "digest"
+    bypass_priority = "high"
+
+    [[server.endpoint.schedule.window]]
+    days = ["mon", "Friday"]
+    start = "08:00"
+    end = "22:30"
+
+    [[server.endpoint]]
+    type = "file"
+    name = "digest"
+    path = '/test_data/file_endpoint2.txt'
+    notifications = ["notification1"]
+"#,
+    )
+    .unwrap();
+
+    let options = config.endpoints()[0].options();
+    let schedule = options.schedule().unwrap();
+    assert_eq!(options.name(), Some("pager"));
+    assert_eq!(schedule.timezone().iana_name(), Some("America/Edmonton"));
+    assert_eq!(schedule.outside(), Outside::Fallback);
+    assert_eq!(schedule.fallback(), Some("digest"));
+    assert_eq!(schedule.bypass_priority(), Priority::High);
+    assert_eq!(schedule.windows()[0].days().len(), 2);
+    assert_eq!(schedule.windows()[0].start().to_string(), "08:00:00");
+    assert_eq!(schedule.windows()[0].end().to_string(), "22:30:00");
+}
+
+#[test]
+fn schedule_fallback_not_defined() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1"]
+
+    [server.endpoint.schedule]
+    outside = "fallback"
+    fallback = "missing"
+
+    [[server.endpoint.schedule.window]]
+    start = "08:00"
+    end = "22:00"
+"#,
+    );
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_endpoint_configuration("fallback endpoint missing is not defined".to_string()).to_string()
+    )
+}
+
+#[test]
+fn schedule_fallback_loop() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    name = "first"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1"]
+
+    [server.endpoint.schedule]
+    outside = "fallback"
+    fallback = "second"
+
+    [[server.endpoint.schedule.window]]
+    start = "08:00"
+    end = "22:00"
+
+    [[server.endpoint]]
+    type = "file"
+    name = "second"
+    path = '/test_data/file_endpoint2.txt'
+    notifications = ["notification1"]
+
+    [server.endpoint.schedule]
+    outside = "fallback"
+    fallback = "first"
+
+    [[server.endpoint.schedule.window]]
+    start = "22:00"
+    end = "08:00"
+"#,
+    );
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_endpoint_configuration(
+            "fallback endpoint first leads back to an endpoint already in the fallback chain".to_string()
+        )
+        .to_string()
+    )
+}
+
+#[test]
+fn endpoint_name_not_unique() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1"]
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file"
+    path = '/test_data/file_endpoint2.txt'
+    notifications = ["notification1"]
+"#,
+    );
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_endpoint_configuration("endpoint name file is not unique".to_string()).to_string()
+    )
+}
+
+#[test]
+fn retry_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "discord"
+    url = "https://discord.com/api/webhooks/webhook_id/webhook_token"
+    notifications = ["notification1"]
+
+    [server.endpoint.retry]
+    max_attempts = 5
+    initial_backoff_secs = 2
+    jitter = false
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.endpoints()[0].options().retry(),
+        Some(Retry::new(5, Duration::from_secs(2), Duration::from_secs(60), false))
+    );
+}
+
+#[test]
+fn retry_zero_attempts() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "discord"
+    url = "https://discord.com/api/webhooks/webhook_id/webhook_token"
+    notifications = ["notification1"]
+
+    [server.endpoint.retry]
+    max_attempts = 0
+"#,
+    );
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_endpoint_configuration("retry max_attempts must be greater than 0".to_string()).to_string()
+    )
+}
+
+#[test]
+fn spool_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.spool]
+    path = '/test_data/spool'
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.spool(), Some(&Spool::new("/test_data/spool")));
+}
+
+#[test]
+fn spool_path_blank() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.spool]
+    path = ''
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_server_configuration("spool path is blank".to_string()).to_string()
+    );
+}
+
+#[test]
+fn dead_letter_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.dead_letter]
+    path = '/test_data/dead_letters.jsonl'
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.dead_letter(), Some(&DeadLetter::new("/test_data/dead_letters.jsonl")));
+}
+
+#[test]
+fn dead_letter_path_blank() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.dead_letter]
+    path = ''
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_server_configuration("dead_letter path is blank".to_string()).to_string()
+    );
+}
+
+#[test]
+fn escalation_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.escalation]]
+    chain = ["file-primary", "file-backup"]
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file-primary"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file-backup"
+    path = '/test_data/file_backup.txt'
+    notifications = ["notification1"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.escalations(), &[Escalation::new(&["file-primary", "file-backup"])]);
+}
+
+#[test]
+fn escalation_endpoint_not_defined() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.escalation]]
+    chain = ["file-primary", "file-missing"]
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file-primary"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file-backup"
+    path = '/test_data/file_backup.txt'
+    notifications = ["notification1"]
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_server_configuration("escalation endpoint file-missing is not defined".to_string()).to_string()
+    );
+}
+
+#[test]
+fn escalation_endpoint_repeated() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.escalation]]
+    chain = ["file-primary", "file-backup", "file-primary"]
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file-primary"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file-backup"
+    path = '/test_data/file_backup.txt'
+    notifications = ["notification1"]
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_server_configuration(
+            "endpoint file-primary is used more than once in escalation chains".to_string()
+        )
+        .to_string()
+    );
+}
+
+#[test]
+fn queue_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [server.endpoint.queue]
+    size = 500
+    overflow = "spill"
+    spill_path = '/test_data/file.spill'
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.endpoints()[0].options().queue(),
+        Some(&Queue::new(500).unwrap().with_overflow(QueueOverflow::Spill).with_spill_path(Some("/test_data/file.spill")))
+    );
+}
+
+#[test]
+fn queue_spill_without_path() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [server.endpoint.queue]
+    overflow = "spill"
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_endpoint_configuration("queue overflow is spill but no spill_path is set".to_string())
+            .to_string()
+    );
+}
+
+#[test]
+fn admin_configured() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.admin]
+    address = "127.0.0.1:8090"
+    token = "admin-token-for-tests"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    )
+    .unwrap();
+
+    let admin = Admin::new("127.0.0.1:8090".parse().unwrap(), "admin-token-for-tests").unwrap();
+    assert_eq!(config.admin(), Some(&admin));
+}
+
+#[test]
+fn admin_token_too_short() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.admin]
+    address = "127.0.0.1:8090"
+    token = "short"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_server_configuration("admin token must be at least 16 characters".to_string()).to_string()
+    );
+}
+
+#[test]
+fn admin_address_invalid() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.admin]
+    address = "localhost"
+    token = "admin-token-for-tests"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_server_configuration("admin address localhost is not a valid socket address".to_string())
+            .to_string()
+    );
+}
+
+#[test]
+fn check_reports_every_problem() {
+    let problems = ServerConfigFile::check(
+        r#"[server]
+key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+[server.spool]
+path = ""
+
+[[server.interface]]
+type = "http"
+port = 99999
+
+[[server.endpoint]]
+type = "file"
+path = '/test_data/file_endpoint.txt'
+notifications = ["notification1"]
+"#,
+    );
+
+    let lines: Vec<_> = problems.iter().map(|problem| (problem.line(), problem.column())).collect();
+    assert_eq!(lines, vec![(4, 1), (7, 1)]);
+    assert_eq!(problems[1].message(), Error::invalid_port_number(99999).to_string());
+}
+
+#[test]
+fn check_cross_references() {
+    let problems = ServerConfigFile::check(
+        r#"[server]
+key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+[[server.escalation]]
+chain = ["file1", "missing"]
+
+[[server.interface]]
+type = "http"
+port = 8080
+
+[[server.endpoint]]
+type = "file"
+name = "file1"
+path = '/test_data/file_endpoint.txt'
+notifications = ["notification1"]
+"#,
+    );
+
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].line(), 4);
+}
+
+#[test]
+fn routes_by_notification_name() {
+    let routes = ServerConfigFile::routes(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    name = "file1"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1", "notification2"]
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint2.txt'
+    notifications = ["notification1"]
+"#,
+    )
+    .unwrap();
+
+    let routes: Vec<_> = routes.iter().map(|route| (route.notification(), route.endpoint(), route.target())).collect();
+    assert_eq!(
+        routes,
+        vec![("notification1", "#1", ""), ("notification1", "file1", ""), ("notification2", "file1", "")]
+    );
+}
+
+#[test]
+fn secrets_from_file() {
+    let directory = std::env::temp_dir().join("pass-it-on-secrets-test");
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("key"), "sdfsf4633ghf44dfhdfhQdhdfhewaasg\n").unwrap();
+
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key_file = "DIRECTORY/key"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = 'DIRECTORY/file_endpoint.txt'
+    notifications = ["notification1", "$${literal}"]
+"#
+        .replace("DIRECTORY", directory.to_str().unwrap())
+        .as_str(),
+    )
+    .unwrap();
+
+    assert_eq!(config.key(), &Key::derive_shared_key("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+    let endpoint = config.endpoints()[0].as_any().downcast_ref::<FileEndpoint>().unwrap();
+    assert_eq!(endpoint.path(), &directory.join("file_endpoint.txt"));
+}
+
+#[test]
+fn secret_environment_variable_missing() {
+    let config = ServerConfiguration::try_from(
+        r#"[server]
+key = "${PASS_IT_ON_TEST_MISSING}"
+
+[[server.interface]]
+type = "http"
+port = 8080
+
+[[server.endpoint]]
+type = "file"
+path = '/test_data/file_endpoint.txt'
+notifications = ["notification1"]
+"#,
+    );
+
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::unresolved_secret(
+            "line 2, column 7: environment variable PASS_IT_ON_TEST_MISSING is not set".to_string()
+        )
+        .to_string()
+    );
+}
+
+/// Write the `files` to a new directory named `name` in the temporary directory and return it.
+fn configuration_directory(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&directory);
+    for (file, content) in files {
+        let path = directory.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    directory
+}
+
+#[test]
+fn include_conf_d_directory() {
+    let directory = configuration_directory(
+        "pass-it-on-include-test",
+        &[
+            (
+                "server.toml",
+                r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+    include = ["conf.d"]
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+"#,
+            ),
+            (
+                "conf.d/b.toml",
+                r#"
+    [[server.endpoint]]
+    type = "file"
+    name = "b"
+    path = '/test_data/b.txt'
+    notifications = ["notification1"]
+"#,
+            ),
+            (
+                "conf.d/a.toml",
+                r#"
+    [[server.endpoint]]
+    type = "file"
+    name = "a"
+    path = '/test_data/a.txt'
+    notifications = ["notification1"]
+"#,
+            ),
+        ],
+    );
+
+    let config = ServerConfiguration::try_from(directory.join("server.toml").as_path()).unwrap();
+    let names: Vec<_> = config.endpoints().iter().map(|endpoint| endpoint.options().name().map(String::from)).collect();
+    assert_eq!(names, vec![Some("a".to_string()), Some("b".to_string())]);
+}
+
+#[test]
+fn include_conflicts_name_file() {
+    let directory = configuration_directory(
+        "pass-it-on-include-conflict-test",
+        &[
+            (
+                "server.toml",
+                r#"[server]
+key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+include = ["conf.d/*.toml"]
+
+[[server.interface]]
+type = "http"
+port = 8080
+
+[[server.endpoint]]
+type = "file"
+name = "ops"
+path = '/test_data/ops.txt'
+notifications = ["notification1"]
+"#,
+            ),
+            (
+                "conf.d/team.toml",
+                r#"[server]
+key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+[[server.endpoint]]
+type = "file"
+name = "ops"
+path = '/test_data/team.txt'
+notifications = ["notification1"]
+"#,
+            ),
+        ],
+    );
+
+    let team = directory.join("conf.d").join("team.toml");
+    let problems = ServerConfigFile::check_file(directory.join("server.toml").as_path()).unwrap();
+    let problems: Vec<_> = problems.iter().map(|problem| (problem.file(), problem.line())).collect();
+    assert_eq!(problems, vec![(Some(team.as_path()), 2), (Some(team.as_path()), 4)]);
+    assert!(ServerConfiguration::try_from(directory.join("server.toml").as_path()).is_err());
+}
+
+#[test]
+fn yaml_config() {
+    let config = ServerConfiguration::from_format(
+        r#"
+server:
+  key: "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+  interface:
+    - type: http
+      port: 8080
+  endpoint:
+    - type: file
+      name: yaml
+      path: /test_data/file_endpoint.txt
+      notifications: ["notification1", "notification2"]
+"#,
+        ConfigFormat::Yaml,
+    )
+    .unwrap();
+
+    assert_eq!(config.key(), &Key::derive_shared_key("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+    assert_eq!(config.endpoints().len(), 1);
+    assert_eq!(config.endpoints()[0].options().name(), Some("yaml"));
+}
+
+#[test]
+fn json_include_not_supported() {
+    let config = ServerConfiguration::from_format(
+        r#"
+    {
+        "server": {
+            "key": "sdfsf4633ghf44dfhdfhQdhdfhewaasg",
+            "include": ["conf.d"],
+            "interface": [{ "type": "http", "port": 8080 }],
+            "endpoint": [{ "type": "file", "path": "/test_data/file_endpoint.txt", "notifications": ["notification1"] }]
+        }
+    }
+"#,
+        ConfigFormat::Json,
+    );
+    assert_eq!(
+        config.unwrap_err().to_string(),
+        Error::invalid_include("`include` is only supported in TOML configuration files").to_string()
+    )
+}
+
+#[test]
+fn check_yaml_file_syntax_error() {
+    let directory = configuration_directory(
+        "pass-it-on-yaml-check-test",
+        &[("server.yml", "server:\n  key: \"sdfsf4633ghf44dfhdfhQdhdfhewaasg\"\n  interface: [\n")],
+    );
+
+    let problems = ServerConfigFile::check_file(directory.join("server.yml").as_path()).unwrap();
+    assert_eq!(problems.len(), 1);
+    assert_eq!((problems[0].line(), problems[0].column()), (4, 1));
+}
+
+#[test]
+fn unknown_setting_rejected() {
+    let config = ServerConfiguration::try_from(
+        r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1"]
+
+    [server.endpoint.rate_limit]
+    max_messages = 10
+    period_sec = 60
+"#,
+    );
+    assert!(config.unwrap_err().to_string().contains("unknown field `period_sec`"));
+}
+
+#[test]
+fn check_unknown_settings() {
+    let problems = ServerConfigFile::check(
+        r#"[server]
+key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+replay_protecton = { max_age_secs = 60 }
+
+[[server.interface]]
+type = "http"
+port = 8080
+
+[[server.endpoint]]
+type = "file"
+path = '/test_data/file_endpoint.txt'
+notifications = ["notification1"]
+nmae = "file1"
+"#,
+    );
+
+    let lines: Vec<_> = problems.iter().map(|problem| (problem.line(), problem.column())).collect();
+    assert_eq!(lines, vec![(3, 1), (9, 1)]);
+    assert!(problems[0].message().starts_with("unknown field `replay_protecton`"));
+}
+
+#[test]
+fn schema_lists_every_endpoint_type() {
+    let schema = ServerConfigFile::schema();
+    let types = schema["definitions"]["EndpointConfig"]["oneOf"].as_array().unwrap();
+    for endpoint_type in types.iter().map(|schema| schema["properties"]["type"]["const"].as_str().unwrap()) {
+        let config = ServerConfiguration::try_from(
+            format!(
+                r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "{}"
+"#,
+                endpoint_type
+            )
+            .as_str(),
+        );
+        // Every type in the schema is known, so only its missing settings are reported
+        assert!(config.unwrap_err().to_string().contains("missing field"), "{}", endpoint_type);
+    }
+    assert_eq!(types.len(), 4);
+    assert!(schema["definitions"]["ServerConfigFile"]["properties"]["include"].is_object());
+}
+
+#[test]
+fn written_config_reads_back() {
+    let directory = configuration_directory(
+        "pass-it-on-write-test",
+        &[
+            (
+                "server.toml",
+                r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+    include = ["endpoints.toml"]
+
+    [server.admin]
+    address = "127.0.0.1:9090"
+    token = "admin-token-0123456789"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+"#,
+            ),
+            (
+                "endpoints.toml",
+                r#"
+    [[server.endpoint]]
+    type = "file"
+    name = "literal"
+    path = '/test_data/$${HOME}.txt'
+    notifications = ["notification1"]
+
+    [server.endpoint.rate_limit]
+    max_messages = 5
+    period_secs = 60
+    overflow = "queue"
+"#,
+            ),
+        ],
+    );
+
+    let file = ServerConfigFile::from_path(directory.join("server.toml").as_path()).unwrap();
+    let toml = file.to_format(ConfigFormat::Toml).unwrap();
+    assert!(!toml.contains("include"));
+    assert!(toml.contains("$${HOME}"));
+
+    std::fs::write(directory.join("written.yaml"), file.to_format(ConfigFormat::Yaml).unwrap()).unwrap();
+    let written = ServerConfigFile::from_path(directory.join("written.yaml").as_path()).unwrap();
+    assert_eq!(written.to_format(ConfigFormat::Toml).unwrap(), toml);
+
+    let config = ServerConfiguration::try_from(toml.as_str()).unwrap();
+    assert_eq!(config.key(), &Key::derive_shared_key("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+    assert_eq!(config.endpoints()[0].options().name(), Some("literal"));
+}
+
+#[test]
+fn redacted_config_hides_secrets() {
+    let directory = configuration_directory(
+        "pass-it-on-redact-test",
+        &[(
+            "server.toml",
+            r#"
+    [server]
+    key = "sdfsf4633ghf44dfhdfhQdhdfhewaasg"
+
+    [server.admin]
+    address = "127.0.0.1:9090"
+    token = "admin-token-0123456789"
+
+    [[server.interface]]
+    type = "http"
+    port = 8080
+
+    [[server.endpoint]]
+    type = "file"
+    path = '/test_data/file_endpoint.txt'
+    notifications = ["notification1"]
+"#,
+        )],
+    );
+
+    let file = ServerConfigFile::from_path(directory.join("server.toml").as_path()).unwrap();
+    let redacted = file.to_redacted_format(ConfigFormat::Toml).unwrap();
+    assert!(!redacted.contains("sdfsf4633ghf44dfhdfhQdhdfhewaasg"));
+    assert!(!redacted.contains("admin-token-0123456789"));
+    assert!(redacted.contains("key = \"<redacted>\""));
+    assert!(redacted.contains("/test_data/file_endpoint.txt"));
+}